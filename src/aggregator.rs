@@ -1,14 +1,45 @@
-use crate::db::{InMemoryDatabase, TransactionData};
-use log::{error, info};
-use solana_client::rpc_client::RpcClient;
+use crate::amount::{Amount, Asset, Direction, SOL_DECIMALS};
+use crate::batch::BatchError;
+use crate::checkpoint::SlotCheckpoint;
+use crate::classify::{ClassificationRule, Classifier};
+use crate::consistency::{compare_transactions, ConsistencyLog};
+use crate::db::{
+    Commitment, InMemoryDatabase, RetractionReason, TransactionData, TransactionStatus,
+};
+use crate::decoder::{DecoderRegistry, InstructionDecoder};
+use crate::endpoint::{Endpoint, EndpointPool, FailoverPolicy};
+use crate::events::TransactionEvent;
+use crate::instructions::{flatten_instructions, program_invocations};
+use crate::memo::extract_memos;
+use crate::nft::{detect_nft_movements, metadata_address, parse_metadata, NftMetadata};
+use crate::rate_limit::RateLimit;
+use crate::replay::{offline_client, Recording, RecordingSource, ReplaySource};
+use crate::retry::{
+    classify_batch_error, classify_client_error, with_retry, ErrorClass, RetryPolicy,
+};
+use crate::source::TransactionSource;
+use crate::subscription::IngestionMode;
+use crate::swap::detect_swap;
+use futures::stream::{self, StreamExt};
+use log::{info, warn};
+use rand::Rng;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcBlockConfig, RpcTransactionConfig};
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_response::RpcInflationReward;
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
 use solana_transaction_status::{
-    EncodedTransaction, UiMessage, UiTransaction, UiTransactionEncoding,
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, TransactionDetails,
+    UiConfirmedBlock, UiLoadedAddresses, UiMessage, UiTransaction, UiTransactionEncoding,
+    UiTransactionStatusMeta, UiTransactionTokenBalance,
 };
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
-use tokio::time::{error::Elapsed, timeout, Duration};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::{error::Elapsed, timeout_at, Duration, Instant};
 
 /// Custom error type for the `Aggregator` struct, encapsulating various errors
 /// that can occur while interacting with the Solana blockchain.
@@ -30,33 +61,417 @@ pub enum AggregatorError {
     #[error("Failed to parse signature: {0}")]
     ParseSignatureError(String),
 
+    /// Error that occurs when fetching inflation rewards from the Solana blockchain.
+    #[error("Failed to fetch inflation rewards: {0}")]
+    FetchRewardsError(#[source] solana_client::client_error::ClientError),
+
+    /// Error that occurs when fetching the supply or metadata of an NFT mint.
+    #[error("Failed to fetch NFT metadata: {0}")]
+    FetchMetadataError(#[source] solana_client::client_error::ClientError),
+
+    /// Error that occurs when fetching the statuses of stored transactions.
+    #[error("Failed to fetch signature statuses: {0}")]
+    FetchStatusesError(#[source] solana_client::client_error::ClientError),
+
+    /// Error that occurs when listing or fetching blocks.
+    #[error("Failed to fetch blocks: {0}")]
+    FetchBlocksError(#[source] solana_client::client_error::ClientError),
+
+    /// Error that occurs when fetching a batch of transaction details.
+    #[error("Failed to fetch transaction batch: {0}")]
+    FetchBatchError(#[from] BatchError),
+
     /// Indicates that an operation has timed out.
     #[error("Operation timed out")]
     Elapsed(#[from] Elapsed),
 }
 
+/// Number of epochs before the last completed one whose missing inflation rewards are
+/// backfilled.
+const REWARDS_BACKFILL_EPOCHS: u64 = 10;
+
+/// Slots after which a transaction the cluster no longer knows is considered orphaned.
+/// This is about the lifetime of a blockhash, so a transaction still propagating is not
+/// dropped.
+const ORPHAN_SLOTS: u64 = 150;
+
+/// Signatures per `getSignatureStatuses` request, the most the node accepts.
+const STATUSES_PER_REQUEST: usize = 256;
+
+/// Slots per `getBlocks` request, the widest range the node accepts.
+const BLOCKS_PER_REQUEST: u64 = 500_000;
+
+/// The outcome of a pass over the transactions not finalized yet.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FinalityUpdate {
+    pub promoted: usize,  // Transactions now observed at a higher commitment
+    pub retracted: usize, // Transactions retracted as orphaned or rolled back
+}
+
+/// The outcome of a pass of the block stream over the slots since the checkpoint.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BlockProgress {
+    pub last_slot: u64,      // Last slot processed, as saved to the checkpoint
+    pub blocks: usize,       // Blocks processed
+    pub transactions: usize, // Transactions stored for the watched addresses
+    pub skipped: usize,      // Blocks skipped after failing with a permanent error
+    pub caught_up: bool,     // Whether the pass reached the tip of the chain
+}
+
+/// Settings controlling how the `Aggregator` fetches transactions.
+#[derive(Debug, Clone)]
+pub struct AggregatorConfig {
+    pub concurrency: usize, // Maximum number of requests for transaction details in flight
+    pub batch_size: usize,  // Signatures per JSON-RPC batch; 1 disables batching
+    pub fetch_timeout: Duration, // Time allowed for fetching the transactions of one address
+    pub request_timeout: Duration, // Time allowed for a single RPC request before it fails
+    pub retry: RetryPolicy, // How RPC calls failing with a transient error are retried
+    pub rate_limit: Option<RateLimit>, // Request budget of each RPC endpoint; `None` for no limit
+    pub failover: FailoverPolicy, // How the RPC endpoint serving each call is picked
+    pub max_slot_lag: u64,  // Slots an RPC endpoint may lag behind the others and stay healthy
+    pub health_check_interval: Duration, // Time between health checks of the RPC endpoints
+    pub verify_sample_rate: f64, // Share of transactions verified against a second endpoint; 0 disables
+    pub commitment: Commitment,  // Commitment transactions are fetched at
+    pub finality_check_interval: Duration, // Time between checks of the transactions not finalized yet
+    pub ingestion: IngestionMode, // How new transactions of the watched addresses are discovered
+    pub blocks_per_pass: u64,     // Slots walked by each pass of the block stream, at most
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            batch_size: 1,
+            fetch_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+            rate_limit: None,
+            failover: FailoverPolicy::Primary,
+            max_slot_lag: 50,
+            health_check_interval: Duration::from_secs(30),
+            verify_sample_rate: 0.0,
+            commitment: Commitment::Finalized,
+            finality_check_interval: Duration::from_secs(30),
+            ingestion: IngestionMode::Poll,
+            blocks_per_pass: 500,
+        }
+    }
+}
+
+impl AggregatorConfig {
+    /// Reads the configuration from environment variables, falling back to the default
+    /// for each variable that is not set or not a number.
+    ///
+    /// * `FETCH_CONCURRENCY` - Maximum number of requests for transaction details in flight.
+    /// * `FETCH_BATCH_SIZE` - Signatures per JSON-RPC batch request; 1 disables batching.
+    /// * `FETCH_TIMEOUT_SECS` - Time allowed for fetching the transactions of one address.
+    /// * `RPC_TIMEOUT_SECS` - Time allowed for a single RPC request before it fails.
+    /// * `RPC_MAX_RETRIES` - Retries of an RPC call failing with a transient error.
+    /// * `RPC_RETRY_BASE_MS` - Delay before the first retry, doubled for each retry after it.
+    /// * `RPC_RATE_LIMIT_RPS` - Requests per second allowed to each RPC endpoint; unlimited if unset.
+    /// * `RPC_RATE_LIMIT_BURST` - Requests allowed at once; defaults to one second of requests.
+    /// * `RPC_FAILOVER_POLICY` - `primary` or `round_robin`.
+    /// * `RPC_MAX_SLOT_LAG` - Slots an RPC endpoint may lag behind the others and stay healthy.
+    /// * `RPC_HEALTH_CHECK_SECS` - Time between health checks of the RPC endpoints.
+    /// * `VERIFY_SAMPLE_RATE` - Share of transactions verified against a second endpoint, from 0 to 1.
+    /// * `SOLANA_COMMITMENT` - `processed`, `confirmed` or `finalized`.
+    /// * `FINALITY_CHECK_SECS` - Time between checks of the transactions not finalized yet.
+    /// * `INGESTION_MODE` - `poll`, `subscribe`, `blocks` or `geyser`.
+    /// * `BLOCKS_PER_PASS` - Slots walked by each pass of the block stream, at most.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let retry = RetryPolicy {
+            max_retries: env_number("RPC_MAX_RETRIES").unwrap_or(default.retry.max_retries),
+            base_delay: env_number("RPC_RETRY_BASE_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.retry.base_delay),
+            ..default.retry
+        };
+        let rate_limit = env_number::<f64>("RPC_RATE_LIMIT_RPS")
+            .filter(|rps| rps.is_finite() && *rps > 0.0)
+            .map(|rps| RateLimit {
+                requests_per_second: rps,
+                burst: env_number("RPC_RATE_LIMIT_BURST").unwrap_or(rps.ceil() as u32),
+            });
+        Self {
+            concurrency: env_number("FETCH_CONCURRENCY").unwrap_or(default.concurrency),
+            batch_size: env_number("FETCH_BATCH_SIZE").unwrap_or(default.batch_size),
+            fetch_timeout: env_number("FETCH_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.fetch_timeout),
+            request_timeout: env_number("RPC_TIMEOUT_SECS")
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(default.request_timeout),
+            retry,
+            rate_limit,
+            failover: env_number("RPC_FAILOVER_POLICY").unwrap_or(default.failover),
+            max_slot_lag: env_number("RPC_MAX_SLOT_LAG").unwrap_or(default.max_slot_lag),
+            health_check_interval: env_number("RPC_HEALTH_CHECK_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.health_check_interval),
+            verify_sample_rate: env_number("VERIFY_SAMPLE_RATE")
+                .map(sample_rate)
+                .unwrap_or(default.verify_sample_rate),
+            commitment: env_number("SOLANA_COMMITMENT").unwrap_or(default.commitment),
+            finality_check_interval: env_number("FINALITY_CHECK_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.finality_check_interval),
+            ingestion: env_number("INGESTION_MODE").unwrap_or(default.ingestion),
+            blocks_per_pass: env_number("BLOCKS_PER_PASS").unwrap_or(default.blocks_per_pass),
+        }
+    }
+}
+
+/// Parses a number or other value from an environment variable.
+fn env_number<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok()?.trim().parse().ok()
+}
+
+/// Turns a configured sample rate into a probability, treating a rate that is not a
+/// finite number as 0.
+fn sample_rate(rate: f64) -> f64 {
+    match rate.is_finite() {
+        true => rate.clamp(0.0, 1.0),
+        false => 0.0,
+    }
+}
+
+/// Number of sampled transactions waiting for verification, beyond which further
+/// samples are dropped.
+const VERIFICATION_QUEUE_SIZE: usize = 1000;
+
+/// The estimated start time of an epoch, remembered with the slots of the epoch.
+#[derive(Debug, Clone, Copy)]
+struct EpochStart {
+    first_slot: u64, // First slot of the epoch
+    last_slot: u64,  // Last slot of the epoch
+    start_time: i64, // Estimated start time of the epoch in seconds since Unix epoch
+}
+
+/// A fetched transaction waiting to be verified against a second endpoint.
+struct Verification {
+    signature: String, // Signature of the transaction
+    transaction: EncodedConfirmedTransactionWithStatusMeta, // Transaction as fetched from `endpoint`
+    endpoint: String, // URL of the endpoint the transaction was fetched from
+}
+
 /// Struct that handles fetching transactions from the Solana blockchain and storing
 /// them in an in-memory database.
 pub struct Aggregator {
-    client: RpcClient,         // Solana RPC client used to interact with the blockchain
+    endpoints: EndpointPool, // RPC endpoints used to interact with the blockchain
+    config: AggregatorConfig, // Concurrency and timeout settings for fetches
     db: Arc<InMemoryDatabase>, // In-memory database for storing transactions
+    decoders: DecoderRegistry, // Decoders turning instructions into typed events
+    classifier: Classifier,  // Rules tagging transactions with their kinds
+    rewarded_epochs: Mutex<HashSet<u64>>, // Epochs whose inflation rewards were fetched
+    nft_metadata: Mutex<HashMap<String, Option<NftMetadata>>>, // Metadata per mint, `None` if not an NFT
+    epoch_start: Mutex<Option<EpochStart>>, // Start of the epoch of the last ingested transaction
+    consistency: Arc<ConsistencyLog>, // Results of verifying transactions against a second endpoint
+    verifications: mpsc::Sender<Verification>, // Queue of the sampled transactions to verify
+    verification_queue: Mutex<Option<mpsc::Receiver<Verification>>>, // Receiving end, until taken by `run_verifications`
 }
 
 impl Aggregator {
-    /// Creates a new `Aggregator` instance with the specified Solana RPC URL and
-    /// in-memory database.
+    /// Creates a new `Aggregator` instance with the specified Solana RPC URLs and
+    /// in-memory database, decoding instructions with the built-in decoders.
     ///
     /// # Arguments
     ///
-    /// * `url` - A string slice representing the URL of the Solana RPC endpoint.
+    /// * `urls` - The URLs of the Solana RPC endpoints, the first being the primary.
     /// * `db` - A thread-safe reference to an `InMemoryDatabase` instance.
+    /// * `config` - The concurrency and timeout settings for fetches. The sample rate is
+    ///   clamped to a valid probability.
     ///
     /// # Returns
     ///
     /// A new instance of `Aggregator`.
-    pub fn new(url: &str, db: Arc<InMemoryDatabase>) -> Self {
-        let client = RpcClient::new(url.to_string());
-        Self { client, db }
+    pub fn new(urls: &[String], db: Arc<InMemoryDatabase>, mut config: AggregatorConfig) -> Self {
+        config.verify_sample_rate = sample_rate(config.verify_sample_rate);
+        let (verifications, verification_queue) = mpsc::channel(VERIFICATION_QUEUE_SIZE);
+        Self {
+            endpoints: EndpointPool::new(
+                urls,
+                config.failover,
+                config.rate_limit,
+                config.max_slot_lag,
+                config.commitment,
+                config.request_timeout,
+            ),
+            config,
+            db,
+            decoders: DecoderRegistry::with_builtins(),
+            classifier: Classifier::with_builtins(),
+            rewarded_epochs: Mutex::new(HashSet::new()),
+            nft_metadata: Mutex::new(HashMap::new()),
+            epoch_start: Mutex::new(None),
+            consistency: Arc::new(ConsistencyLog::default()),
+            verifications,
+            verification_queue: Mutex::new(Some(verification_queue)),
+        }
+    }
+
+    /// Replaces the RPC clients as the source of the signatures, transactions, clock
+    /// and accounts read by `fetch_recent_transactions` and `ingest_transaction`, e.g.
+    /// with in-memory fixtures. JSON-RPC batches would bypass the source, so they are
+    /// disabled; the other calls still go to the endpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source answering the calls of every endpoint.
+    pub fn with_source(mut self, source: Arc<dyn TransactionSource>) -> Self {
+        self.endpoints.map_sources(|_| source.clone());
+        self.config.batch_size = 1;
+        self
+    }
+
+    /// Records the responses of the sources of the signatures, transactions, clock and
+    /// accounts, so that they can be replayed with `replaying`. JSON-RPC batches are
+    /// disabled, so that every transaction is recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `recording` - The recording the responses are written to.
+    pub fn recording_to(mut self, recording: Recording) -> Self {
+        self.endpoints
+            .map_sources(|source| Arc::new(RecordingSource::new(source, recording.clone())));
+        self.config.batch_size = 1;
+        self
+    }
+
+    /// Replays a recording without a network. The signatures, transactions, clock and
+    /// accounts are read from the recording, and every other call, e.g. of the block
+    /// stream or the finality job, fails rather than reaching the endpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `recording` - The recording the responses are read from.
+    pub fn replaying(mut self, recording: Recording) -> Self {
+        self.endpoints
+            .map_clients(|client| offline_client(client.commitment()));
+        self.with_source(Arc::new(ReplaySource::new(recording)))
+    }
+
+    /// Returns the log of the verifications of fetched transactions against a second
+    /// endpoint, shared with the admin API.
+    pub fn consistency(&self) -> Arc<ConsistencyLog> {
+        self.consistency.clone()
+    }
+
+    /// Registers a decoder for a custom program, replacing any decoder previously
+    /// registered for the same program ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - The decoder to register, e.g. an `AnchorDecoder` loaded from an IDL.
+    pub fn register_decoder(&mut self, decoder: impl InstructionDecoder + 'static) {
+        self.decoders.register(decoder);
+    }
+
+    /// Registers a classification rule, applied after the built-in rules.
+    ///
+    /// # Arguments
+    ///
+    /// * `rule` - The rule to register, e.g. a `ProgramRule` tagging a DEX as `swap`.
+    pub fn register_rule(&mut self, rule: impl ClassificationRule + 'static) {
+        self.classifier.register(rule);
+    }
+
+    /// Checks the health of the RPC endpoints, so that calls avoid the endpoints that
+    /// are unhealthy or lagging behind.
+    pub async fn check_endpoints(&self) {
+        self.endpoints.check_health().await;
+    }
+
+    /// Verifies the sampled transactions against a second endpoint as they are queued
+    /// by the fetches, so that the verification calls stay off the fetch path. Runs
+    /// for as long as the aggregator, and returns at once if the queue is already
+    /// being processed.
+    pub async fn run_verifications(&self) {
+        let Some(mut queue) = self.verification_queue.lock().unwrap().take() else {
+            return;
+        };
+        while let Some(verification) = queue.recv().await {
+            self.verify_transaction(
+                &verification.signature,
+                &verification.transaction,
+                &verification.endpoint,
+            )
+            .await;
+        }
+    }
+
+    /// Sends an RPC call, failing over to the next endpoint of the pool while it keeps
+    /// failing with a transient error.
+    ///
+    /// See `call_with_endpoint`.
+    async fn call<'a, T, E, Fut>(
+        &'a self,
+        operation: &str,
+        requests: u32,
+        classify: impl Fn(&E) -> ErrorClass,
+        call: impl Fn(&'a Endpoint) -> Fut,
+    ) -> Result<T, E>
+    where
+        E: std::fmt::Display,
+        Fut: std::future::Future<Output = Result<T, E>>,
+    {
+        self.call_with_endpoint(operation, requests, classify, call)
+            .await
+            .map(|(value, _)| value)
+    }
+
+    /// Sends an RPC call within the request budget of an endpoint, retrying it while
+    /// it fails with a transient error. Each attempt waits for its tokens, so calls
+    /// beyond the budget are delayed rather than failed.
+    ///
+    /// Once the retries are exhausted, the endpoint is marked unhealthy and the call
+    /// fails over to the next endpoint picked by the policy. Permanent errors are
+    /// returned right away, since other endpoints would reject the call as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The name of the call, used in logs.
+    /// * `requests` - The number of requests the call sends, e.g. the size of a batch.
+    /// * `classify` - Classifies the errors of the call.
+    /// * `call` - Starts a new attempt of the call on an endpoint.
+    ///
+    /// # Returns
+    ///
+    /// The result of the first successful attempt with the endpoint that served it, or
+    /// the error of the last attempt.
+    async fn call_with_endpoint<'a, T, E, Fut>(
+        &'a self,
+        operation: &str,
+        requests: u32,
+        classify: impl Fn(&E) -> ErrorClass,
+        call: impl Fn(&'a Endpoint) -> Fut,
+    ) -> Result<(T, &'a Endpoint), E>
+    where
+        E: std::fmt::Display,
+        Fut: std::future::Future<Output = Result<T, E>>,
+    {
+        let mut last_error = None;
+        for endpoint in self.endpoints.candidates() {
+            let result = with_retry(&self.config.retry, operation, &classify, || async {
+                endpoint.acquire(requests).await;
+                call(endpoint).await
+            })
+            .await;
+            match result {
+                Ok(value) => return Ok((value, endpoint)),
+                Err(err) if classify(&err) == ErrorClass::Permanent => return Err(err),
+                Err(err) => {
+                    warn!(
+                        "{} failed on RPC endpoint {}, failing over: {}",
+                        operation, endpoint.url, err
+                    );
+                    self.endpoints.mark_unhealthy(endpoint, &err);
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error.expect("the pool has at least one RPC endpoint"))
     }
 
     /// Fetches the start time (Unix timestamp) of the current Solana epoch.
@@ -66,9 +481,21 @@ impl Aggregator {
     /// A result containing the epoch start time in seconds since Unix epoch, or an
     /// `AggregatorError` if an error occurs.
     async fn get_epoch_start_time(&self) -> Result<i64, AggregatorError> {
+        Ok(self.get_epoch_start().await?.start_time)
+    }
+
+    /// Fetches the start time and slots of the current Solana epoch.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the `EpochStart` of the current epoch, or an
+    /// `AggregatorError` if an error occurs.
+    async fn get_epoch_start(&self) -> Result<EpochStart, AggregatorError> {
         let epoch_info = self
-            .client
-            .get_epoch_info()
+            .call("getEpochInfo", 1, classify_client_error, |endpoint| {
+                endpoint.source.get_epoch_info()
+            })
+            .await
             .map_err(AggregatorError::FetchTransactionError)?;
 
         // Approximate time per Solana slot (in seconds)
@@ -79,19 +506,56 @@ impl Aggregator {
         let seconds_since_epoch_start =
             (slots_since_epoch_start as f64 * block_production_time_per_slot) as i64;
         let current_time = self
-            .client
-            .get_block_time(epoch_info.absolute_slot)
+            .call("getBlockTime", 1, classify_client_error, |endpoint| {
+                endpoint.source.get_block_time(epoch_info.absolute_slot)
+            })
+            .await
             .map_err(AggregatorError::FetchTransactionError)?;
 
-        Ok(current_time - seconds_since_epoch_start)
+        let first_slot = epoch_info.absolute_slot - slots_since_epoch_start;
+        Ok(EpochStart {
+            first_slot,
+            last_slot: first_slot + epoch_info.slots_in_epoch.saturating_sub(1),
+            start_time: current_time - seconds_since_epoch_start,
+        })
+    }
+
+    /// Returns the start time of the epoch holding a slot, fetching it only when the
+    /// slot is beyond the epoch remembered from the previous call.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot of a transaction being ingested.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the epoch start time in seconds since Unix epoch, or an
+    /// `AggregatorError` if an error occurs.
+    async fn epoch_start_time_at(&self, slot: u64) -> Result<i64, AggregatorError> {
+        let cached = *self.epoch_start.lock().unwrap();
+        if let Some(epoch_start) = cached {
+            if (epoch_start.first_slot..=epoch_start.last_slot).contains(&slot) {
+                return Ok(epoch_start.start_time);
+            }
+        }
+        let epoch_start = self.get_epoch_start().await?;
+        *self.epoch_start.lock().unwrap() = Some(epoch_start);
+        Ok(epoch_start.start_time)
     }
 
     /// Fetches recent transactions for the specified Solana address and stores
     /// them in the in-memory database.
     ///
+    /// The address may be a wallet or a program ID. For a program, every transaction
+    /// invoking it is indexed along with the details of each invocation.
+    ///
+    /// Transaction details are fetched concurrently, up to the configured concurrency.
+    /// If the fetch timeout is reached, the transactions fetched so far are still
+    /// stored and returned, in signature order.
+    ///
     /// # Arguments
     ///
-    /// * `address` - A string slice representing the Solana public key of the account.
+    /// * `address` - A string slice representing the Solana public key of the account or program.
     ///
     /// # Returns
     ///
@@ -100,158 +564,2396 @@ impl Aggregator {
         &self,
         address: &str,
     ) -> Result<Vec<TransactionData>, AggregatorError> {
-        let timeout_duration = Duration::from_secs(10); // Set a timeout duration of 10 seconds
+        let deadline = Instant::now() + self.config.fetch_timeout;
 
         info!("Starting transaction fetch for address: {}", address);
 
-        // Fetch the start time of the current epoch
-        let epoch_start_time = self.get_epoch_start_time().await?;
-
-        let transactions = timeout(timeout_duration, async {
-            let pubkey: Pubkey = address
-                .parse()
-                .map_err(|_| AggregatorError::InvalidPublicKey)?;
-
-            info!("Fetching signatures for address: {}", pubkey);
+        let pubkey: Pubkey = address
+            .parse()
+            .map_err(|_| AggregatorError::InvalidPublicKey)?;
 
-            // Fetch the signatures of recent transactions for the specified address
-            let signatures = self
-                .client
-                .get_signatures_for_address(&pubkey)
-                .map_err(AggregatorError::FetchSignaturesError)?;
-
-            info!(
-                "Fetched {} signatures for address: {}",
-                signatures.len(),
-                pubkey
-            );
+        // Fetch the start time of the current epoch
+        let epoch_start_time = timeout_at(deadline, self.get_epoch_start_time()).await??;
 
-            let mut transactions = Vec::new();
+        info!("Fetching signatures for address: {}", pubkey);
 
-            // Iterate through each signature and fetch transaction details
-            for signature_info in signatures {
-                info!("Processing signature: {}", signature_info.signature);
+        // Fetch the signatures of recent transactions for the specified address
+        let signatures = timeout_at(
+            deadline,
+            self.call(
+                "getSignaturesForAddress",
+                1,
+                classify_client_error,
+                |endpoint| {
+                    endpoint.source.get_signatures_for_address(
+                        &pubkey,
+                        GetConfirmedSignaturesForAddress2Config {
+                            commitment: Some(fetch_commitment(self.config.commitment)),
+                            ..Default::default()
+                        },
+                    )
+                },
+            ),
+        )
+        .await?
+        .map_err(AggregatorError::FetchSignaturesError)?;
 
-                let signature: Signature = signature_info.signature.parse().map_err(|_| {
-                    AggregatorError::ParseSignatureError(signature_info.signature.clone())
-                })?;
+        info!(
+            "Fetched {} signatures for address: {}",
+            signatures.len(),
+            pubkey
+        );
 
-                if let Ok(transaction_with_meta) = self
-                    .client
-                    .get_transaction(&signature, UiTransactionEncoding::JsonParsed)
-                    .map_err(AggregatorError::FetchTransactionError)
-                {
-                    if let Some(block_time) = transaction_with_meta.block_time {
-                        // Process only transactions from the current epoch
-                        if block_time >= epoch_start_time {
-                            let timestamp = block_time;
-                            if let Some(meta) = &transaction_with_meta.transaction.meta {
-                                match &transaction_with_meta.transaction.transaction {
-                                    EncodedTransaction::Json(transaction) => {
-                                        let UiTransaction { message, .. } = transaction;
-                                        let (sender, receiver) = match message {
-                                            UiMessage::Parsed(parsed_message) => {
-                                                let sender = parsed_message
-                                                    .account_keys
-                                                    .get(0)
-                                                    .map_or("unknown".to_string(), |acc| {
-                                                        acc.pubkey.clone()
-                                                    });
-                                                let receiver = parsed_message
-                                                    .account_keys
-                                                    .get(1)
-                                                    .map_or("unknown".to_string(), |acc| {
-                                                        acc.pubkey.clone()
-                                                    });
-                                                (sender, receiver)
-                                            }
-                                            UiMessage::Raw(raw_message) => {
-                                                let sender = raw_message
-                                                    .account_keys
-                                                    .get(0)
-                                                    .map_or("unknown".to_string(), |key| {
-                                                        key.clone()
-                                                    });
-                                                let receiver = raw_message
-                                                    .account_keys
-                                                    .get(1)
-                                                    .map_or("unknown".to_string(), |key| {
-                                                        key.clone()
-                                                    });
-                                                (sender, receiver)
-                                            }
-                                        };
-                                        let amount = meta.post_balances[1] - meta.pre_balances[1];
-
-                                        let transaction_data = TransactionData {
-                                            signature: signature_info.signature.clone(),
-                                            sender,
-                                            receiver,
-                                            amount: amount as u64,
-                                            timestamp: timestamp as u64,
-                                        };
-
-                                        transactions.push(transaction_data.clone());
-
-                                        // Save each transaction to the in-memory database
-                                        self.db.add_transaction(address, transaction_data).await;
-                                    }
-                                    _ => {}
-                                }
+        // Fetch the details of the signatures concurrently, in batches of the configured
+        // size, keeping each result at the position of its signature
+        let mut fetched: Vec<Option<TransactionData>> = vec![None; signatures.len()];
+        let pending: Vec<(usize, String)> = signatures
+            .iter()
+            .map(|s| s.signature.clone())
+            .enumerate()
+            .collect();
+        let chunks: Vec<Vec<(usize, String)>> = pending
+            .chunks(self.config.batch_size.max(1))
+            .map(<[_]>::to_vec)
+            .collect();
+        let mut fetches = stream::iter(chunks)
+            .map(|chunk| async move {
+                let size = chunk.len();
+                (
+                    size,
+                    self.fetch_transactions(address, chunk, epoch_start_time)
+                        .await,
+                )
+            })
+            .buffer_unordered(self.config.concurrency.max(1));
+        let mut completed = 0;
+        loop {
+            match timeout_at(deadline, fetches.next()).await {
+                Ok(Some((size, Ok(results)))) => {
+                    completed += size;
+                    for (index, result) in results {
+                        match result {
+                            Ok(Some(mut transaction_data)) => {
+                                // The listing reports how final each signature was
+                                transaction_data.commitment = signatures[index]
+                                    .confirmation_status
+                                    .clone()
+                                    .map(Commitment::from)
+                                    .unwrap_or(self.config.commitment);
+                                fetched[index] = Some(transaction_data);
                             }
-                        } else {
-                            info!(
-                                "Skipping transaction from previous epoch: {}",
-                                signature_info.signature
-                            );
+                            Ok(None) => {}
+                            Err(err) => warn!(
+                                "Failed to fetch transaction {}: {}",
+                                signatures[index].signature, err
+                            ),
                         }
                     }
                 }
+                Ok(Some((size, Err(err)))) => {
+                    completed += size;
+                    warn!("Failed to fetch a batch of {} transactions: {}", size, err);
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    warn!(
+                        "Fetch for address {} timed out after {} of {} signatures, storing partial results",
+                        address,
+                        completed,
+                        signatures.len()
+                    );
+                    break;
+                }
             }
+        }
+        drop(fetches);
 
-            Ok::<Vec<TransactionData>, AggregatorError>(transactions)
-        })
-        .await??;
+        // Save the fetched transactions to the in-memory database in signature order
+        let mut transactions = Vec::new();
+        for transaction_data in fetched.into_iter().flatten() {
+            transactions.push(transaction_data.clone());
+            self.db.add_transaction(address, transaction_data).await;
+        }
 
         info!(
-            "Transaction fetch completed successfully for address: {}",
-            address
+            "Transaction fetch completed for address: {} ({} transactions)",
+            address,
+            transactions.len()
         );
 
         Ok(transactions)
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Walks the blocks produced since the checkpoint and stores the transactions
+    /// mentioning any of the watched addresses, then advances the checkpoint.
+    ///
+    /// Blocks are listed with `getBlocks` and fetched in full with `getBlock`, up to the
+    /// configured concurrency, so that catching up after downtime is parallel. They are
+    /// processed in slot order, and a block that cannot be fetched for now ends the pass
+    /// so that the next pass resumes from it, while a block failing with a permanent
+    /// error, e.g. missing from long-term storage, is logged and skipped. Without a
+    /// checkpoint, the stream starts at the tip of the chain. Each pass walks at most
+    /// `blocks_per_pass` slots.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The public keys of the accounts and programs to index.
+    /// * `checkpoint` - The last slot processed by the previous pass.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the progress of the pass, or an `AggregatorError` if the
+    /// blocks could not be listed.
+    pub async fn stream_blocks(
+        &self,
+        addresses: &[String],
+        checkpoint: &SlotCheckpoint,
+    ) -> Result<BlockProgress, AggregatorError> {
+        let commitment = fetch_commitment(self.config.commitment);
+        let tip = self
+            .call("getSlot", 1, classify_client_error, |endpoint| {
+                endpoint.client.get_slot_with_commitment(commitment)
+            })
+            .await
+            .map_err(AggregatorError::FetchBlocksError)?;
+        let Some(start_slot) = checkpoint.load() else {
+            info!("Starting block stream at slot {}", tip);
+            checkpoint.save(tip);
+            return Ok(BlockProgress {
+                last_slot: tip,
+                caught_up: true,
+                ..Default::default()
+            });
+        };
+        let mut progress = BlockProgress {
+            last_slot: start_slot,
+            ..Default::default()
+        };
+        if start_slot >= tip {
+            progress.caught_up = true;
+            return Ok(progress);
+        }
 
-    use crate::db::{InMemoryDatabase, TransactionData};
-    use std::sync::Arc;
+        let end_slot = tip.min(start_slot + self.config.blocks_per_pass.max(1));
+        let slots = self
+            .call("getBlocks", 1, classify_client_error, |endpoint| {
+                endpoint.client.get_blocks_with_commitment(
+                    start_slot + 1,
+                    Some(end_slot),
+                    commitment,
+                )
+            })
+            .await
+            .map_err(AggregatorError::FetchBlocksError)?;
 
-    /// Test to verify that the `Aggregator` can add a transaction to the in-memory
-    /// database and retrieve it correctly.
-    #[tokio::test]
-    async fn test_aggregator_add_and_fetch_transaction() {
-        // Initialize the in-memory database
-        let db = Arc::new(InMemoryDatabase::new("test_transactions.txt".to_string()));
+        let mut blocks = stream::iter(slots)
+            .map(|slot| async move { (slot, self.get_block(slot).await) })
+            .buffered(self.config.concurrency.max(1));
+        let mut complete = true;
+        while let Some((slot, result)) = blocks.next().await {
+            match result {
+                Ok((block, endpoint)) => {
+                    progress.transactions +=
+                        self.process_block(addresses, slot, block, endpoint).await;
+                    progress.blocks += 1;
+                    progress.last_slot = slot;
+                }
+                // The block would fail the same way on every pass, e.g. when it is
+                // missing from long-term storage, so the stream moves past it
+                Err(AggregatorError::FetchBlocksError(err))
+                    if classify_client_error(&err) == ErrorClass::Permanent =>
+                {
+                    warn!("Skipping block {}, which cannot be fetched: {}", slot, err);
+                    progress.skipped += 1;
+                    progress.last_slot = slot;
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to fetch block {}, resuming from it later: {}",
+                        slot, err
+                    );
+                    complete = false;
+                    break;
+                }
+            }
+        }
+        drop(blocks);
 
-        // Create a mock transaction
-        let transaction = TransactionData {
-            signature: "test_signature".to_string(),
-            sender: "sender1".to_string(),
-            receiver: "receiver1".to_string(),
-            amount: 100,
-            timestamp: 1628500000,
+        // The slots after the last block up to the end of the range were skipped
+        if complete {
+            progress.last_slot = end_slot;
+            progress.caught_up = end_slot == tip;
+        }
+        if progress.last_slot != start_slot {
+            checkpoint.save(progress.last_slot);
+        }
+        info!(
+            "Processed {} blocks up to slot {} ({} transactions, {} blocks skipped)",
+            progress.blocks, progress.last_slot, progress.transactions, progress.skipped
+        );
+        Ok(progress)
+    }
+
+    /// Fetches a block with the full details of its transactions.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot of the block.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the block with the URL of the endpoint that served it, or an
+    /// `AggregatorError` if it could not be fetched.
+    async fn get_block(&self, slot: u64) -> Result<(UiConfirmedBlock, &str), AggregatorError> {
+        let config = RpcBlockConfig {
+            encoding: Some(UiTransactionEncoding::Json),
+            transaction_details: Some(TransactionDetails::Full),
+            rewards: Some(false),
+            commitment: Some(fetch_commitment(self.config.commitment)),
+            max_supported_transaction_version: Some(0),
         };
+        let (block, endpoint) = self
+            .call_with_endpoint("getBlock", 1, classify_client_error, |endpoint| {
+                endpoint.client.get_block_with_config(slot, config)
+            })
+            .await
+            .map_err(AggregatorError::FetchBlocksError)?;
+        Ok((block, endpoint.url.as_str()))
+    }
 
-        // Add the transaction to the database
-        db.add_transaction("sender1", transaction.clone()).await;
+    /// Stores the transactions of a block that mention any of the watched addresses,
+    /// under each address they mention.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The public keys of the accounts and programs to index.
+    /// * `slot` - The slot of the block.
+    /// * `block` - The block with the full details of its transactions.
+    /// * `endpoint` - The URL of the RPC endpoint the block was fetched from.
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions stored.
+    async fn process_block(
+        &self,
+        addresses: &[String],
+        slot: u64,
+        block: UiConfirmedBlock,
+        endpoint: &str,
+    ) -> usize {
+        let mut stored = 0;
+        for transaction in block.transactions.unwrap_or_default() {
+            let transaction_with_meta = EncodedConfirmedTransactionWithStatusMeta {
+                slot,
+                transaction,
+                block_time: block.block_time,
+            };
+            let (block_stored, _) = self
+                .store_watched_transaction(
+                    addresses,
+                    &transaction_with_meta,
+                    endpoint,
+                    // The checkpoint bounds the range, so catching up across an epoch
+                    // boundary keeps the blocks of the previous epoch
+                    i64::MIN,
+                    self.config.commitment.max(Commitment::Confirmed),
+                )
+                .await;
+            stored += block_stored;
+        }
+        stored
+    }
 
-        // Fetch the transactions for the sender
-        let transactions = db.get_transactions("sender1").await;
+    /// Stores a transaction pushed by a provider or a Geyser stream under each
+    /// watched address it mentions, unless it is already stored under that address.
+    ///
+    /// Pushed transactions are recent, so they are not checked against the start of
+    /// the epoch, which would cost RPC calls. Unless finalized, they are promoted or
+    /// retracted later as the cluster reports them.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The public keys of the accounts and programs to index.
+    /// * `transaction_with_meta` - The pushed transaction and its status metadata.
+    /// * `source` - The name of the source, recorded as the endpoint of the transaction.
+    /// * `commitment` - The commitment the source pushes transactions at.
+    ///
+    /// # Returns
+    ///
+    /// The number of addresses the transaction was stored under, and the number it
+    /// was already stored under.
+    pub async fn ingest_pushed_transaction(
+        &self,
+        addresses: &[String],
+        transaction_with_meta: &EncodedConfirmedTransactionWithStatusMeta,
+        source: &str,
+        commitment: Commitment,
+    ) -> (usize, usize) {
+        self.store_watched_transaction(
+            addresses,
+            transaction_with_meta,
+            source,
+            i64::MIN,
+            commitment,
+        )
+        .await
+    }
 
-        // Verify that the transaction is correctly stored and retrieved
-        assert_eq!(transactions.len(), 1);
-        assert_eq!(transactions[0], transaction);
+    /// Stores a transaction a provider pushed already parsed, e.g. in the enhanced
+    /// format of a webhook, unless it is already stored under the address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key the transaction is stored under.
+    /// * `transaction_data` - The parsed transaction.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the transaction was stored, `false` if it already was.
+    pub async fn store_pushed_transaction(
+        &self,
+        address: &str,
+        transaction_data: TransactionData,
+    ) -> bool {
+        if self
+            .db
+            .contains_transaction(address, &transaction_data.signature)
+            .await
+        {
+            return false;
+        }
+        self.db.add_transaction(address, transaction_data).await;
+        true
+    }
+
+    /// Stores a transaction under each watched address it mentions, skipping the
+    /// addresses it is already stored under.
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` - The public keys of the accounts and programs to index.
+    /// * `transaction_with_meta` - The transaction and its status metadata.
+    /// * `endpoint` - The URL of the RPC endpoint or the source the transaction came from.
+    /// * `epoch_start_time` - The start time of the current epoch; older transactions are skipped.
+    /// * `commitment` - The commitment the transaction was observed at.
+    ///
+    /// # Returns
+    ///
+    /// The number of addresses the transaction was stored under, and the number it
+    /// was already stored under.
+    async fn store_watched_transaction(
+        &self,
+        addresses: &[String],
+        transaction_with_meta: &EncodedConfirmedTransactionWithStatusMeta,
+        endpoint: &str,
+        epoch_start_time: i64,
+        commitment: Commitment,
+    ) -> (usize, usize) {
+        let (EncodedTransaction::Json(ui_transaction), Some(meta)) = (
+            &transaction_with_meta.transaction.transaction,
+            &transaction_with_meta.transaction.meta,
+        ) else {
+            return (0, 0);
+        };
+        let Some(signature) = ui_transaction.signatures.first() else {
+            return (0, 0);
+        };
+        let account_keys = message_account_keys(&ui_transaction.message, meta);
+
+        let (mut stored, mut duplicates) = (0, 0);
+        for address in addresses.iter().filter(|a| account_keys.contains(a)) {
+            if self.db.contains_transaction(address, signature).await {
+                duplicates += 1;
+                continue;
+            }
+            match self
+                .process_transaction(
+                    address,
+                    signature,
+                    transaction_with_meta,
+                    epoch_start_time,
+                    endpoint,
+                )
+                .await
+            {
+                Ok(Some(mut transaction_data)) => {
+                    transaction_data.commitment = commitment;
+                    self.db.add_transaction(address, transaction_data).await;
+                    stored += 1;
+                }
+                Ok(None) => {}
+                Err(err) => warn!("Failed to process transaction {}: {}", signature, err),
+            }
+        }
+        (stored, duplicates)
+    }
+
+    /// Fetches a single transaction of the specified address, e.g. one announced by a
+    /// subscription, and stores it in the in-memory database.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key of the account or program the transaction is indexed for.
+    /// * `signature` - The signature of the transaction.
+    /// * `commitment` - The commitment the transaction was observed at.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the stored `TransactionData`, `None` if the transaction is
+    /// skipped or already stored, or an `AggregatorError` if it could not be fetched.
+    pub async fn ingest_transaction(
+        &self,
+        address: &str,
+        signature: &str,
+        commitment: Commitment,
+    ) -> Result<Option<TransactionData>, AggregatorError> {
+        if self.db.contains_transaction(address, signature).await {
+            return Ok(None);
+        }
+        let (transaction_with_meta, endpoint) = self.get_transaction(signature).await?;
+        let epoch_start_time = self.epoch_start_time_at(transaction_with_meta.slot).await?;
+        let Some(mut transaction_data) = self
+            .process_transaction(
+                address,
+                signature,
+                &transaction_with_meta,
+                epoch_start_time,
+                endpoint,
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+        transaction_data.commitment = commitment;
+        self.db
+            .add_transaction(address, transaction_data.clone())
+            .await;
+        Ok(Some(transaction_data))
+    }
+
+    /// Fetches and parses the details of a group of transactions, in a single JSON-RPC
+    /// batch when batching is enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key of the account or program the transactions are indexed for.
+    /// * `signatures` - The signatures of the transactions with their positions.
+    /// * `epoch_start_time` - The start time of the current epoch; older transactions are skipped.
+    ///
+    /// # Returns
+    ///
+    /// A result containing, for each position, the parsed `TransactionData`, `None` if
+    /// the transaction is skipped, or the error of that transaction. The outer result
+    /// fails if the whole batch could not be fetched.
+    async fn fetch_transactions(
+        &self,
+        address: &str,
+        signatures: Vec<(usize, String)>,
+        epoch_start_time: i64,
+    ) -> Result<Vec<(usize, Result<Option<TransactionData>, AggregatorError>)>, AggregatorError>
+    {
+        let mut transactions = Vec::new();
+        if self.config.batch_size > 1 {
+            let batch: Vec<String> = signatures.iter().map(|(_, s)| s.clone()).collect();
+            info!("Processing batch of {} signatures", batch.len());
+            let config = transaction_config(self.config.commitment);
+            let (results, endpoint) = self
+                .call_with_endpoint(
+                    "getTransaction batch",
+                    batch.len() as u32,
+                    classify_batch_error,
+                    |endpoint| endpoint.batch_client.get_transactions(&batch, &config),
+                )
+                .await?;
+            for result in results {
+                match result {
+                    Ok(transaction) => {
+                        transactions.push(Ok(transaction.map(|tx| (tx, endpoint.url.as_str()))))
+                    }
+                    Err(err) => transactions.push(Err(AggregatorError::from(err))),
+                }
+            }
+        } else {
+            for (_, signature) in &signatures {
+                transactions.push(self.get_transaction(signature).await.map(Some));
+            }
+        }
+
+        let mut results = Vec::new();
+        for ((index, signature), transaction) in signatures.into_iter().zip(transactions) {
+            let result = match transaction {
+                Ok(Some((transaction_with_meta, endpoint))) => {
+                    self.process_transaction(
+                        address,
+                        &signature,
+                        &transaction_with_meta,
+                        epoch_start_time,
+                        endpoint,
+                    )
+                    .await
+                }
+                Ok(None) => Ok(None),
+                Err(err) => Err(err),
+            };
+            results.push((index, result));
+        }
+        Ok(results)
+    }
+
+    /// Fetches the details of a single transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the transaction.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the transaction and its status metadata with the URL of the
+    /// endpoint that served it, or an `AggregatorError` if it could not be fetched.
+    async fn get_transaction(
+        &self,
+        signature: &str,
+    ) -> Result<(EncodedConfirmedTransactionWithStatusMeta, &str), AggregatorError> {
+        info!("Processing signature: {}", signature);
+
+        let parsed_signature: Signature = signature
+            .parse()
+            .map_err(|_| AggregatorError::ParseSignatureError(signature.to_string()))?;
+        let (transaction, endpoint) = self
+            .call_with_endpoint("getTransaction", 1, classify_client_error, |endpoint| {
+                endpoint.source.get_transaction(
+                    &parsed_signature,
+                    transaction_config(self.config.commitment),
+                )
+            })
+            .await
+            .map_err(AggregatorError::FetchTransactionError)?;
+        Ok((transaction, endpoint.url.as_str()))
+    }
+
+    /// Parses a fetched transaction and enriches its events.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key of the account or program the transaction is indexed for.
+    /// * `signature` - The signature of the transaction.
+    /// * `transaction_with_meta` - The transaction and its status metadata.
+    /// * `epoch_start_time` - The start time of the current epoch; older transactions are skipped.
+    /// * `endpoint` - The URL of the RPC endpoint the transaction was fetched from.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the parsed `TransactionData`, or `None` if the transaction is
+    /// skipped.
+    async fn process_transaction(
+        &self,
+        address: &str,
+        signature: &str,
+        transaction_with_meta: &EncodedConfirmedTransactionWithStatusMeta,
+        epoch_start_time: i64,
+        endpoint: &str,
+    ) -> Result<Option<TransactionData>, AggregatorError> {
+        // Process only transactions from the current epoch
+        match transaction_with_meta.block_time {
+            Some(block_time) if block_time >= epoch_start_time => {}
+            Some(_) => {
+                info!("Skipping transaction from previous epoch: {}", signature);
+                return Ok(None);
+            }
+            None => return Ok(None),
+        }
+
+        let Some(mut transaction_data) = parse_transaction(
+            &self.decoders,
+            &self.classifier,
+            address,
+            signature,
+            transaction_with_meta,
+        ) else {
+            return Ok(None);
+        };
+        if rand::thread_rng().gen_bool(self.config.verify_sample_rate) {
+            self.queue_verification(signature, transaction_with_meta, endpoint);
+        }
+        transaction_data.endpoint = Some(endpoint.to_string());
+        self.enrich_nft_events(&mut transaction_data).await;
+        Ok(Some(transaction_data))
+    }
+
+    /// Queues a fetched transaction for verification by `run_verifications`, dropping
+    /// it if the queue is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the transaction.
+    /// * `transaction_with_meta` - The transaction as fetched from `endpoint`.
+    /// * `endpoint` - The URL of the endpoint the transaction was fetched from.
+    fn queue_verification(
+        &self,
+        signature: &str,
+        transaction_with_meta: &EncodedConfirmedTransactionWithStatusMeta,
+        endpoint: &str,
+    ) {
+        let verification = Verification {
+            signature: signature.to_string(),
+            // The transaction itself is not `Clone`, unlike its parts
+            transaction: EncodedConfirmedTransactionWithStatusMeta {
+                slot: transaction_with_meta.slot,
+                transaction: transaction_with_meta.transaction.clone(),
+                block_time: transaction_with_meta.block_time,
+            },
+            endpoint: endpoint.to_string(),
+        };
+        if let Err(TrySendError::Full(_)) = self.verifications.try_send(verification) {
+            warn!(
+                "Verification queue is full, not verifying transaction {}",
+                signature
+            );
+        }
+    }
+
+    /// Fetches a transaction again from a second endpoint and records the fields on
+    /// which the endpoints disagree. Nothing is recorded if the pool has a single
+    /// endpoint or the second endpoint cannot be reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the transaction.
+    /// * `transaction_with_meta` - The transaction as fetched from `endpoint`.
+    /// * `endpoint` - The URL of the endpoint the transaction was fetched from.
+    async fn verify_transaction(
+        &self,
+        signature: &str,
+        transaction_with_meta: &EncodedConfirmedTransactionWithStatusMeta,
+        endpoint: &str,
+    ) {
+        let Some(reference) = self.endpoints.reference_for(endpoint) else {
+            return;
+        };
+
+        // The transaction is requested as an option, so that a transaction unknown to
+        // the reference endpoint is reported as missing rather than as an error
+        let params = serde_json::json!([signature, transaction_config(self.config.commitment)]);
+        let result = with_retry(
+            &self.config.retry,
+            "getTransaction verification",
+            classify_client_error,
+            || async {
+                reference.acquire(1).await;
+                reference
+                    .client
+                    .send::<Option<EncodedConfirmedTransactionWithStatusMeta>>(
+                        RpcRequest::GetTransaction,
+                        params.clone(),
+                    )
+                    .await
+            },
+        )
+        .await;
+        let reference_transaction = match result {
+            Ok(transaction) => transaction,
+            Err(err) => {
+                warn!(
+                    "Failed to verify transaction {} against {}: {}",
+                    signature, reference.url, err
+                );
+                return;
+            }
+        };
+
+        self.consistency.record(
+            endpoint,
+            compare_transactions(
+                signature,
+                endpoint,
+                transaction_with_meta,
+                &reference.url,
+                reference_transaction.as_ref(),
+            ),
+        );
+    }
+
+    /// Confirms the candidate NFT events of a transaction and fills in their name and
+    /// collection from the Metaplex metadata of each mint.
+    ///
+    /// Events whose mint turns out to have a supply other than one are dropped. If the
+    /// mint cannot be looked up, the event is kept without name or collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The parsed transaction whose events are enriched.
+    async fn enrich_nft_events(&self, transaction: &mut TransactionData) {
+        let mut events = Vec::with_capacity(transaction.events.len());
+        for mut event in std::mem::take(&mut transaction.events) {
+            if let TransactionEvent::NftMint {
+                mint,
+                name,
+                collection,
+                ..
+            }
+            | TransactionEvent::NftTransfer {
+                mint,
+                name,
+                collection,
+                ..
+            } = &mut event
+            {
+                match self.nft_metadata(mint).await {
+                    Ok(Some(metadata)) => {
+                        *name = metadata.name;
+                        *collection = metadata.collection;
+                    }
+                    Ok(None) => continue,
+                    Err(err) => warn!("Failed to look up NFT mint {}: {}", mint, err),
+                }
+            }
+            events.push(event);
+        }
+        transaction.events = events;
+    }
+
+    /// Returns the metadata of an NFT mint, fetching it on first use and caching it.
+    ///
+    /// # Arguments
+    ///
+    /// * `mint` - The mint of the candidate NFT.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the `NftMetadata`, `None` if the mint's supply is not one, or
+    /// an `AggregatorError` if the mint could not be looked up.
+    async fn nft_metadata(&self, mint: &str) -> Result<Option<NftMetadata>, AggregatorError> {
+        if let Some(metadata) = self.nft_metadata.lock().unwrap().get(mint) {
+            return Ok(metadata.clone());
+        }
+
+        let pubkey: Pubkey = mint
+            .parse()
+            .map_err(|_| AggregatorError::InvalidPublicKey)?;
+        let supply = self
+            .call("getTokenSupply", 1, classify_client_error, |endpoint| {
+                endpoint.source.get_token_supply(&pubkey)
+            })
+            .await
+            .map_err(AggregatorError::FetchMetadataError)?;
+        let metadata = if supply == 1 {
+            // Mints without a metadata account are still NFTs, only without a name
+            let address = metadata_address(&pubkey);
+            let account = self
+                .call("getAccountInfo", 1, classify_client_error, |endpoint| {
+                    endpoint.source.get_account(&address)
+                })
+                .await
+                .map_err(AggregatorError::FetchMetadataError)?;
+            Some(
+                account
+                    .and_then(|account| parse_metadata(&account.data))
+                    .unwrap_or_default(),
+            )
+        } else {
+            None
+        };
+
+        self.nft_metadata
+            .lock()
+            .unwrap()
+            .insert(mint.to_string(), metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Fetches the inflation rewards credited to the specified stake accounts for the
+    /// last completed epoch and stores each one as a synthetic reward transaction.
+    ///
+    /// The rewards of up to `REWARDS_BACKFILL_EPOCHS` earlier epochs are fetched as well,
+    /// so that epochs missed before the first run or during downtime are backfilled; an
+    /// epoch the node no longer serves is logged and skipped. Each epoch is only fetched
+    /// once per run, and not at all if the rewards of all stake accounts are already
+    /// stored, so this can be called on every poll.
+    ///
+    /// # Arguments
+    ///
+    /// * `stake_accounts` - The public keys of the stake accounts.
+    ///
+    /// # Returns
+    ///
+    /// A result containing a vector of the newly stored reward `TransactionData`, or an
+    /// `AggregatorError` if an error occurs.
+    pub async fn fetch_inflation_rewards(
+        &self,
+        stake_accounts: &[String],
+    ) -> Result<Vec<TransactionData>, AggregatorError> {
+        let pubkeys = stake_accounts
+            .iter()
+            .map(|account| account.parse::<Pubkey>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| AggregatorError::InvalidPublicKey)?;
+
+        let epoch_info = self
+            .call("getEpochInfo", 1, classify_client_error, |endpoint| {
+                endpoint.client.get_epoch_info()
+            })
+            .await
+            .map_err(AggregatorError::FetchRewardsError)?;
+        let Some(last_epoch) = epoch_info.epoch.checked_sub(1) else {
+            return Ok(Vec::new());
+        };
+
+        let mut transactions = Vec::new();
+        for epoch in (last_epoch.saturating_sub(REWARDS_BACKFILL_EPOCHS)..=last_epoch).rev() {
+            let fetched = self.rewarded_epochs.lock().unwrap().contains(&epoch);
+            if fetched || self.rewards_stored(stake_accounts, epoch).await {
+                continue;
+            }
+
+            info!(
+                "Fetching inflation rewards of epoch {} for {} stake accounts",
+                epoch,
+                pubkeys.len()
+            );
+            let rewards = match self
+                .call("getInflationReward", 1, classify_client_error, |endpoint| {
+                    endpoint.client.get_inflation_reward(&pubkeys, Some(epoch))
+                })
+                .await
+            {
+                Ok(rewards) => rewards,
+                // The epoch is beyond the reward history of the node
+                Err(err) if classify_client_error(&err) == ErrorClass::Permanent => {
+                    warn!("Skipping inflation rewards of epoch {}: {}", epoch, err);
+                    self.rewarded_epochs.lock().unwrap().insert(epoch);
+                    continue;
+                }
+                Err(err) => return Err(AggregatorError::FetchRewardsError(err)),
+            };
+            self.store_inflation_rewards(stake_accounts, rewards, &mut transactions)
+                .await?;
+            self.rewarded_epochs.lock().unwrap().insert(epoch);
+        }
+
+        if !transactions.is_empty() {
+            info!("Stored {} new inflation rewards", transactions.len());
+        }
+
+        Ok(transactions)
+    }
+
+    /// Checks whether the inflation rewards of all stake accounts are stored for an epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `stake_accounts` - The public keys of the stake accounts.
+    /// * `epoch` - The epoch to check.
+    async fn rewards_stored(&self, stake_accounts: &[String], epoch: u64) -> bool {
+        for stake_account in stake_accounts {
+            let signature = reward_signature(stake_account, epoch);
+            if !self
+                .db
+                .contains_transaction(stake_account, &signature)
+                .await
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Stores the inflation rewards of an epoch that are not stored yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `stake_accounts` - The public keys of the stake accounts.
+    /// * `rewards` - The reward of each stake account, `None` if it earned none.
+    /// * `transactions` - The newly stored reward transactions, appended to.
+    async fn store_inflation_rewards(
+        &self,
+        stake_accounts: &[String],
+        rewards: Vec<Option<RpcInflationReward>>,
+        transactions: &mut Vec<TransactionData>,
+    ) -> Result<(), AggregatorError> {
+        for (stake_account, reward) in stake_accounts.iter().zip(rewards) {
+            // Accounts that were not staked during the epoch have no reward
+            let Some(reward) = reward else {
+                continue;
+            };
+            if self
+                .db
+                .contains_transaction(
+                    stake_account,
+                    &reward_signature(stake_account, reward.epoch),
+                )
+                .await
+            {
+                continue;
+            }
+
+            let block_time = self
+                .call("getBlockTime", 1, classify_client_error, |endpoint| {
+                    endpoint.source.get_block_time(reward.effective_slot)
+                })
+                .await
+                .map_err(AggregatorError::FetchRewardsError)?;
+            let mut transaction_data = reward_transaction(stake_account, &reward, block_time);
+            transaction_data.kinds = self.classifier.classify(&transaction_data, &[]);
+
+            transactions.push(transaction_data.clone());
+            self.db
+                .add_transaction(stake_account, transaction_data)
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Revisits the stored transactions that are not finalized yet, promoting those the
+    /// cluster now reports at a higher commitment and retracting those orphaned on an
+    /// abandoned fork or whose slot was rolled back.
+    ///
+    /// A transaction is orphaned once the cluster no longer knows its signature and
+    /// more than `ORPHAN_SLOTS` slots have passed since the slot it was observed in. It
+    /// was rolled back if its slot is at or below the finalized slot but was skipped by
+    /// the finalized chain, as reported by `getBlocks`. Subscribers of the database are
+    /// notified of each retraction.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the number of transactions promoted and retracted, or an
+    /// `AggregatorError` if the statuses or blocks could not be fetched.
+    pub async fn track_finality(&self) -> Result<FinalityUpdate, AggregatorError> {
+        let mut update = FinalityUpdate::default();
+        let unfinalized = self.db.unfinalized_transactions().await;
+        if unfinalized.is_empty() {
+            return Ok(update);
+        }
+
+        let current_slot = self
+            .call("getSlot", 1, classify_client_error, |endpoint| {
+                endpoint
+                    .client
+                    .get_slot_with_commitment(CommitmentConfig::confirmed())
+            })
+            .await
+            .map_err(AggregatorError::FetchStatusesError)?;
+        let finalized_slot = self
+            .call("getSlot", 1, classify_client_error, |endpoint| {
+                endpoint
+                    .client
+                    .get_slot_with_commitment(CommitmentConfig::finalized())
+            })
+            .await
+            .map_err(AggregatorError::FetchStatusesError)?;
+
+        let mut statuses = Vec::with_capacity(unfinalized.len());
+        for chunk in unfinalized.chunks(STATUSES_PER_REQUEST) {
+            let signatures = chunk
+                .iter()
+                .map(|(signature, ..)| signature.parse::<Signature>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| AggregatorError::ParseSignatureError(err.to_string()))?;
+            let chunk_statuses = self
+                .call(
+                    "getSignatureStatuses",
+                    1,
+                    classify_client_error,
+                    |endpoint| {
+                        endpoint
+                            .client
+                            .get_signature_statuses_with_history(&signatures)
+                    },
+                )
+                .await
+                .map_err(AggregatorError::FetchStatusesError)?
+                .value;
+            statuses.extend(chunk_statuses);
+        }
+
+        // The slot each transaction currently lives in, as reported by the cluster if it
+        // still knows the transaction, and whether that slot should already be finalized
+        let settled_slots: Vec<Option<u64>> = unfinalized
+            .iter()
+            .zip(&statuses)
+            .map(|((_, slot, _), status)| {
+                let observed = status
+                    .as_ref()
+                    .map(|status| (status.slot, Commitment::from(status.confirmation_status())));
+                match observed.unwrap_or((*slot, Commitment::Confirmed)) {
+                    (_, Commitment::Finalized) => None,
+                    (slot, _) => (slot <= finalized_slot).then_some(slot),
+                }
+            })
+            .collect();
+        // Only the ranges holding a settled slot are queried, each within the widest
+        // range the node accepts
+        let mut pending_slots: Vec<u64> = settled_slots.iter().flatten().copied().collect();
+        pending_slots.sort_unstable();
+        let mut finalized_blocks = HashSet::new();
+        let mut queried_until = None;
+        for start_slot in pending_slots {
+            if queried_until.is_some_and(|end_slot| start_slot <= end_slot) {
+                continue;
+            }
+            let end_slot = finalized_slot.min(start_slot + BLOCKS_PER_REQUEST - 1);
+            let blocks = self
+                .call("getBlocks", 1, classify_client_error, |endpoint| {
+                    endpoint.client.get_blocks_with_commitment(
+                        start_slot,
+                        Some(end_slot),
+                        CommitmentConfig::finalized(),
+                    )
+                })
+                .await
+                .map_err(AggregatorError::FetchStatusesError)?;
+            finalized_blocks.extend(blocks);
+            queried_until = Some(end_slot);
+        }
+
+        let mut promotions = Vec::new();
+        for (((signature, slot, commitment), status), settled_slot) in
+            unfinalized.iter().zip(statuses).zip(settled_slots)
+        {
+            if let Some(settled_slot) = settled_slot {
+                if !finalized_blocks.contains(&settled_slot) {
+                    warn!(
+                        "Retracting transaction {} from slot {} skipped by the finalized chain",
+                        signature, settled_slot
+                    );
+                    self.db
+                        .retract_transaction(signature, RetractionReason::RolledBack)
+                        .await;
+                    update.retracted += 1;
+                    continue;
+                }
+            }
+
+            match status {
+                Some(status) => {
+                    let observed = Commitment::from(status.confirmation_status());
+                    if observed > *commitment || status.slot != *slot {
+                        info!(
+                            "Transaction {} is now {:?} in slot {}",
+                            signature, observed, status.slot
+                        );
+                        promotions.push((
+                            signature.clone(),
+                            status.slot,
+                            observed.max(*commitment),
+                        ));
+                    }
+                }
+                None if current_slot > slot + ORPHAN_SLOTS => {
+                    warn!(
+                        "Retracting transaction {} orphaned since slot {}",
+                        signature, slot
+                    );
+                    self.db
+                        .retract_transaction(signature, RetractionReason::Orphaned)
+                        .await;
+                    update.retracted += 1;
+                }
+                None => {}
+            }
+        }
+
+        update.promoted = promotions.len();
+        self.db.update_commitments(&promotions).await;
+        Ok(update)
+    }
+}
+
+/// Returns the configuration used to fetch transaction details.
+///
+/// Transactions are requested in raw JSON encoding so that instructions carry their
+/// account indexes, and versioned transactions are accepted so that transactions using
+/// address lookup tables are not rejected by the node.
+fn transaction_config(commitment: Commitment) -> RpcTransactionConfig {
+    RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(fetch_commitment(commitment)),
+        max_supported_transaction_version: Some(0),
+    }
+}
+
+/// Returns the commitment used to list and fetch transactions, which the node only
+/// supports at the confirmed commitment or above.
+fn fetch_commitment(commitment: Commitment) -> CommitmentConfig {
+    commitment.max(Commitment::Confirmed).into()
+}
+
+/// Converts a confirmed transaction returned by the RPC node into a `TransactionData`
+/// record for the given account or program.
+///
+/// # Arguments
+///
+/// * `decoders` - The decoders used to turn the transaction's instructions into events.
+/// * `address` - The public key of the account or program the transaction is indexed for.
+/// * `signature` - The signature of the transaction.
+/// * `transaction_with_meta` - The transaction and its status metadata as returned by
+///   `get_transaction`.
+///
+/// # Returns
+///
+/// `Some(TransactionData)` if the transaction carries JSON-encoded data, status metadata
+/// and a block time, `None` otherwise.
+fn parse_transaction(
+    decoders: &DecoderRegistry,
+    classifier: &Classifier,
+    address: &str,
+    signature: &str,
+    transaction_with_meta: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Option<TransactionData> {
+    let block_time = transaction_with_meta.block_time?;
+    let meta = transaction_with_meta.transaction.meta.as_ref()?;
+    let EncodedTransaction::Json(transaction) = &transaction_with_meta.transaction.transaction
+    else {
+        return None;
+    };
+
+    let UiTransaction { message, .. } = transaction;
+    let account_keys = message_account_keys(message, meta);
+    let recent_blockhash = match message {
+        UiMessage::Parsed(parsed_message) => parsed_message.recent_blockhash.clone(),
+        UiMessage::Raw(raw_message) => raw_message.recent_blockhash.clone(),
+    };
+    let sender = account_keys
+        .first()
+        .map_or("unknown".to_string(), |key| key.clone());
+    let receiver = account_keys
+        .get(1)
+        .map_or("unknown".to_string(), |key| key.clone());
+
+    // A transaction that failed still lands on chain and pays its fee, so the
+    // error is recorded alongside the data instead of dropping the transaction.
+    let (status, error) = match &meta.err {
+        Some(err) => (TransactionStatus::Failed, Some(err.to_string())),
+        None => (TransactionStatus::Success, None),
+    };
+
+    let instructions = flatten_instructions(message, meta, &account_keys);
+    let log_messages: Option<&Vec<String>> = meta.log_messages.as_ref().into();
+    let log_messages = log_messages.map_or(&[][..], Vec::as_slice);
+    let mut events = decoders.decode_all(&instructions);
+    events.extend(decoders.decode_logs(log_messages));
+    let memos = extract_memos(&events, log_messages);
+
+    events.extend(detect_nft_movements(meta));
+
+    // Swaps are classified from the balance changes of the signer
+    let signer_sol_delta = sol_delta(0, meta);
+    let signer_token_deltas = token_deltas(&sender, meta);
+    events.extend(detect_swap(
+        &sender,
+        signer_sol_delta,
+        &signer_token_deltas,
+        &instructions,
+    ));
+
+    let mut transaction_data = TransactionData {
+        signature: signature.to_string(),
+        sender,
+        receiver,
+        amount: balance_change(address, &account_keys, meta),
+        timestamp: block_time as u64,
+        status,
+        error,
+        fee: meta.fee,
+        compute_units_consumed: meta.compute_units_consumed.clone().into(),
+        slot: transaction_with_meta.slot,
+        recent_blockhash,
+        invocations: program_invocations(address, &instructions),
+        events,
+        memos,
+        ..Default::default()
+    };
+    transaction_data.kinds = classifier.classify(&transaction_data, &instructions);
+    Some(transaction_data)
+}
+
+/// Returns the account keys of a transaction in message order, followed by the
+/// accounts loaded from address lookup tables.
+///
+/// # Arguments
+///
+/// * `message` - The message of the transaction.
+/// * `meta` - The status metadata of the transaction, listing the loaded accounts.
+fn message_account_keys(message: &UiMessage, meta: &UiTransactionStatusMeta) -> Vec<String> {
+    match message {
+        // Parsed messages already list the accounts loaded from lookup tables
+        UiMessage::Parsed(parsed_message) => parsed_message
+            .account_keys
+            .iter()
+            .map(|acc| acc.pubkey.clone())
+            .collect(),
+        UiMessage::Raw(raw_message) => {
+            let mut account_keys = raw_message.account_keys.clone();
+            let loaded: Option<&UiLoadedAddresses> = meta.loaded_addresses.as_ref().into();
+            if let Some(loaded) = loaded {
+                account_keys.extend(loaded.writable.iter().cloned());
+                account_keys.extend(loaded.readonly.iter().cloned());
+            }
+            account_keys
+        }
+    }
+}
+
+/// Returns the synthetic signature identifying the reward of a stake account for an
+/// epoch, e.g. `reward:512:<stake account>`.
+fn reward_signature(stake_account: &str, epoch: u64) -> String {
+    format!("reward:{}:{}", epoch, stake_account)
+}
+
+/// Converts an inflation reward into a synthetic `TransactionData` record, so that
+/// rewards are stored and persisted alongside the transactions of the stake account.
+///
+/// # Arguments
+///
+/// * `stake_account` - The public key of the stake account the reward was credited to.
+/// * `reward` - The reward as returned by `get_inflation_reward`.
+/// * `block_time` - The time of the block the reward was credited in.
+///
+/// # Returns
+///
+/// A `TransactionData` crediting the reward to the stake account.
+fn reward_transaction(
+    stake_account: &str,
+    reward: &RpcInflationReward,
+    block_time: i64,
+) -> TransactionData {
+    TransactionData {
+        signature: reward_signature(stake_account, reward.epoch),
+        sender: "unknown".to_string(),
+        receiver: stake_account.to_string(),
+        amount: Amount::sol(reward.amount, Direction::Credit),
+        timestamp: block_time as u64,
+        slot: reward.effective_slot,
+        events: vec![TransactionEvent::InflationReward {
+            stake_account: stake_account.to_string(),
+            epoch: reward.epoch,
+            lamports: reward.amount,
+            post_balance: reward.post_balance,
+            commission: reward.commission,
+        }],
+        ..Default::default()
+    }
+}
+
+/// Computes the balance change of an account in a transaction.
+///
+/// If the account owns token accounts whose balance changed, the change of the first
+/// such mint is returned. Otherwise the change of the account's SOL balance is
+/// returned, excluding the fee when the account is the fee payer since the fee is
+/// recorded separately.
+///
+/// A change too large to be represented is logged and skipped, leaving the amount
+/// empty rather than wrong.
+///
+/// # Arguments
+///
+/// * `address` - The public key of the account.
+/// * `account_keys` - The account keys of the transaction, in message order.
+/// * `meta` - The status metadata of the transaction.
+///
+/// # Returns
+///
+/// The signed balance change of the account as an `Amount`.
+fn balance_change(
+    address: &str,
+    account_keys: &[String],
+    meta: &UiTransactionStatusMeta,
+) -> Amount {
+    let (asset, delta, decimals) = match token_deltas(address, meta)
+        .into_iter()
+        .find(|(_, delta, _)| *delta != 0)
+    {
+        Some((mint, delta, decimals)) => (Asset::Token(mint), delta, decimals),
+        None => match account_keys.iter().position(|key| key == address) {
+            Some(index) => (Asset::Sol, sol_delta(index, meta), SOL_DECIMALS),
+            None => return Amount::default(),
+        },
+    };
+    Amount::from_delta(asset.clone(), delta, decimals).unwrap_or_else(|| {
+        warn!(
+            "Skipping the balance change of {} in {:?}: {} overflows the amount",
+            address, asset, delta
+        );
+        Amount::default()
+    })
+}
+
+/// Computes the change of an account's SOL balance, excluding the fee if the account
+/// is the fee payer.
+///
+/// # Arguments
+///
+/// * `index` - The index of the account in the transaction's account keys.
+/// * `meta` - The status metadata of the transaction.
+///
+/// # Returns
+///
+/// The signed balance change in lamports.
+fn sol_delta(index: usize, meta: &UiTransactionStatusMeta) -> i128 {
+    let pre = meta.pre_balances.get(index).copied().unwrap_or_default();
+    let post = meta.post_balances.get(index).copied().unwrap_or_default();
+    let mut delta = i128::from(post) - i128::from(pre);
+    if index == 0 {
+        delta += i128::from(meta.fee);
+    }
+    delta
+}
+
+/// Sums the token balance changes of all token accounts owned by an address, per mint.
+///
+/// # Arguments
+///
+/// * `owner` - The public key of the owner of the token accounts.
+/// * `meta` - The status metadata of the transaction.
+///
+/// # Returns
+///
+/// A vector of `(mint, raw delta, decimals)`, in the order the mints first appear.
+fn token_deltas(owner: &str, meta: &UiTransactionStatusMeta) -> Vec<(String, i128, u8)> {
+    let pre_token_balances: Option<&Vec<UiTransactionTokenBalance>> =
+        meta.pre_token_balances.as_ref().into();
+    let post_token_balances: Option<&Vec<UiTransactionTokenBalance>> =
+        meta.post_token_balances.as_ref().into();
+
+    let mut token_deltas: Vec<(String, i128, u8)> = Vec::new();
+    let pre = pre_token_balances.into_iter().flatten().map(|b| (b, -1));
+    let post = post_token_balances.into_iter().flatten().map(|b| (b, 1));
+    for (balance, sign) in pre.chain(post) {
+        let balance_owner: Option<&String> = balance.owner.as_ref().into();
+        if balance_owner.map(String::as_str) != Some(owner) {
+            continue;
+        }
+        let raw = balance.ui_token_amount.amount.parse::<i128>().unwrap_or(0);
+        match token_deltas
+            .iter_mut()
+            .find(|(mint, _, _)| *mint == balance.mint)
+        {
+            Some((_, delta, _)) => *delta += sign * raw,
+            None => token_deltas.push((
+                balance.mint.clone(),
+                sign * raw,
+                balance.ui_token_amount.decimals,
+            )),
+        }
+    }
+    token_deltas
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{
+        parse_transaction, reward_signature, reward_transaction, Aggregator, AggregatorConfig,
+        BlockProgress, FinalityUpdate,
+    };
+    use crate::amount::{Amount, Direction};
+    use crate::checkpoint::SlotCheckpoint;
+    use crate::classify::{Classifier, TransactionKind};
+    use crate::db::{
+        Commitment, InMemoryDatabase, RetractionReason, TransactionData, TransactionStatus,
+    };
+    use crate::decoder::DecoderRegistry;
+    use crate::events::TransactionEvent;
+    use crate::metrics::METRICS;
+    use crate::mock_rpc::{self, MockReply, MockRpc};
+    use crate::rate_limit::RateLimit;
+    use crate::retry::RetryPolicy;
+    use crate::source::fixture::FixtureSource;
+    use solana_client::rpc_response::RpcInflationReward;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::time::{Duration, Instant};
+
+    /// Starts a mock node serving a transfer for each signature, answering
+    /// `getTransaction` for the signature at each position after the given delay in
+    /// milliseconds.
+    async fn mock_node(signatures: Vec<String>, delays: Vec<u64>) -> MockRpc {
+        let transfers = mock_rpc::transfer_handler(signatures.clone(), "sender");
+        MockRpc::start(move |method, params| {
+            let reply = transfers(method, params);
+            match method {
+                "getTransaction" => {
+                    let signature = params[0].as_str().unwrap_or_default();
+                    let index = signatures.iter().position(|s| s == signature).unwrap();
+                    reply.delayed(delays[index])
+                }
+                _ => reply,
+            }
+        })
+        .await
+    }
+
+    /// Test to verify that transaction details are fetched concurrently up to the
+    /// configured limit and stored in signature order, even when later signatures
+    /// complete first.
+    #[tokio::test]
+    async fn test_fetch_transactions_concurrently_in_order() {
+        let signatures: Vec<String> = (0..6)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let node = mock_node(signatures.clone(), vec![300, 250, 200, 150, 100, 50]).await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            concurrency: 3,
+            ..Default::default()
+        };
+        let aggregator = Aggregator::new(std::slice::from_ref(&node.url), db.clone(), config);
+
+        let address = Pubkey::new_unique().to_string();
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        let fetched: Vec<String> = transactions.into_iter().map(|tx| tx.signature).collect();
+        assert_eq!(fetched, signatures);
+        let stored: Vec<String> = db
+            .get_transactions(&address)
+            .await
+            .into_iter()
+            .map(|tx| tx.signature)
+            .collect();
+        assert_eq!(stored, signatures);
+        assert_eq!(node.calls("getTransaction"), 6);
+        assert_eq!(node.max_in_flight(), 3);
+    }
+
+    /// Test to verify that the transactions fetched before the timeout are stored when
+    /// a slow signature cuts the batch off.
+    #[tokio::test]
+    async fn test_fetch_transactions_commits_partial_progress() {
+        let signatures: Vec<String> = (0..3)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let node = mock_node(signatures.clone(), vec![50, 5000, 50]).await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            concurrency: 3,
+            fetch_timeout: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let aggregator = Aggregator::new(std::slice::from_ref(&node.url), db.clone(), config);
+
+        let address = Pubkey::new_unique().to_string();
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        let fetched: Vec<String> = transactions.into_iter().map(|tx| tx.signature).collect();
+        assert_eq!(fetched, vec![signatures[0].clone(), signatures[2].clone()]);
+        assert_eq!(db.get_transactions(&address).await.len(), 2);
+    }
+
+    /// Test to verify that transaction details are fetched in JSON-RPC batches of the
+    /// configured size, and that a signature failing within a batch is skipped.
+    #[tokio::test]
+    async fn test_fetch_transactions_in_batches() {
+        let signatures: Vec<String> = (0..5)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let failing = signatures[2].clone();
+        let transfers = mock_rpc::transfer_handler(signatures.clone(), "sender");
+        let node = MockRpc::start(move |method, params| match method {
+            "getTransaction" if params[0] == failing.as_str() => {
+                MockReply::error(-32009, "Slot 1000 was skipped")
+            }
+            _ => transfers(method, params),
+        })
+        .await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            batch_size: 2,
+            ..Default::default()
+        };
+        let aggregator = Aggregator::new(std::slice::from_ref(&node.url), db.clone(), config);
+
+        let address = Pubkey::new_unique().to_string();
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        let fetched: Vec<String> = transactions.into_iter().map(|tx| tx.signature).collect();
+        let mut expected = signatures.clone();
+        expected.remove(2);
+        assert_eq!(fetched, expected);
+        assert_eq!(node.calls("getTransaction"), 5);
+        // One request each for the epoch info, block time and signatures, then 3 batches
+        assert_eq!(node.http_requests(), 6);
+    }
+
+    /// Test to verify that an RPC call failing with a server error is retried, and that
+    /// a rate-limited batch is retried after the delay of its `Retry-After` header.
+    #[tokio::test]
+    async fn test_fetch_transactions_retries_transient_errors() {
+        let signatures: Vec<String> = (0..2)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let transfers = mock_rpc::transfer_handler(signatures.clone(), "sender");
+        let attempts = AtomicUsize::new(0);
+        let node = MockRpc::start(move |method, params| match method {
+            "getEpochInfo" | "getBlockTime" => transfers(method, params),
+            _ => match (method, attempts.fetch_add(1, Ordering::SeqCst)) {
+                ("getSignaturesForAddress", 0) => MockReply::http_status(503, None),
+                // Both requests of the first batch are rate limited
+                ("getTransaction", 1 | 2) => MockReply::http_status(429, Some(1)),
+                _ => transfers(method, params),
+            },
+        })
+        .await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            batch_size: 2,
+            retry: RetryPolicy {
+                base_delay: Duration::from_millis(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let aggregator = Aggregator::new(std::slice::from_ref(&node.url), db.clone(), config);
+        let retries_before = METRICS.snapshot().rpc_retries;
+
+        let started = Instant::now();
+        let address = Pubkey::new_unique().to_string();
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        let fetched: Vec<String> = transactions.into_iter().map(|tx| tx.signature).collect();
+        assert_eq!(fetched, signatures);
+        assert_eq!(node.calls("getSignaturesForAddress"), 2);
+        assert_eq!(node.calls("getTransaction"), 4);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        // Other tests may retry concurrently, so the global counter is only bounded below
+        assert!(METRICS.snapshot().rpc_retries >= retries_before + 2);
+    }
+
+    /// Test to verify that a rate-limited single call is only retried by the retry
+    /// policy, once per retry, and that its `Retry-After` delay is capped by the
+    /// policy's maximum delay.
+    #[tokio::test]
+    async fn test_rate_limited_call_is_retried_by_policy_only() {
+        let node = MockRpc::start(|_, _| MockReply::http_status(429, Some(60))).await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            retry: RetryPolicy {
+                max_retries: 2,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(50),
+            },
+            ..Default::default()
+        };
+        let aggregator = Aggregator::new(std::slice::from_ref(&node.url), db, config);
+
+        let started = Instant::now();
+        let result = aggregator.get_epoch_start_time().await;
+
+        assert!(result.is_err());
+        assert_eq!(node.calls("getEpochInfo"), 3);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    /// Test to verify that calls fail over to the secondary endpoint while the primary
+    /// keeps failing, and that each transaction records the endpoint it came from.
+    #[tokio::test]
+    async fn test_fetch_transactions_fails_over_to_secondary() {
+        let signatures: Vec<String> = (0..2)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let primary = MockRpc::start(|_, _| MockReply::http_status(503, None)).await;
+        let secondary = mock_node(signatures.clone(), vec![0; 2]).await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            retry: RetryPolicy {
+                max_retries: 1,
+                base_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let urls = vec![primary.url.clone(), secondary.url.clone()];
+        let aggregator = Aggregator::new(&urls, db.clone(), config);
+
+        let address = Pubkey::new_unique().to_string();
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        assert_eq!(transactions.len(), 2);
+        for transaction in &transactions {
+            assert_eq!(
+                transaction.endpoint.as_deref(),
+                Some(secondary.url.as_str())
+            );
+        }
+        // The primary is retried once, then marked unhealthy and skipped for later calls
+        assert_eq!(primary.calls("getEpochInfo"), 2);
+        assert_eq!(primary.http_requests(), 2);
+    }
+
+    /// Test to verify that sampled transactions are fetched again from the other
+    /// endpoint and that differing balances are recorded against the serving endpoint.
+    #[tokio::test]
+    async fn test_fetch_transactions_verifies_against_second_endpoint() {
+        let signatures: Vec<String> = (0..2)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let primary = mock_node(signatures.clone(), vec![0; 2]).await;
+        let stale_signature = signatures[1].clone();
+        let reference = MockRpc::start(move |method, params| {
+            let signature = params[0].as_str().unwrap_or_default();
+            let mut transaction = mock_rpc::transaction(signature, "sender", "receiver");
+            if signature == stale_signature {
+                transaction["meta"]["postBalances"] = serde_json::json!([1000000, 0, 1]);
+            }
+            match method {
+                "getTransaction" => MockReply::ok(transaction),
+                _ => MockReply::ok(serde_json::Value::Null),
+            }
+        })
+        .await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            verify_sample_rate: 1.0,
+            ..Default::default()
+        };
+        let urls = vec![primary.url.clone(), reference.url.clone()];
+        let aggregator = Arc::new(Aggregator::new(&urls, db.clone(), config));
+
+        let address = Pubkey::new_unique().to_string();
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        // The fetch only queues the verifications
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(reference.calls("getTransaction"), 0);
+        let verifier = aggregator.clone();
+        tokio::spawn(async move { verifier.run_verifications().await });
+        while aggregator.consistency().summary().checked < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(reference.calls("getTransaction"), 2);
+        let summary = aggregator.consistency().summary();
+        assert_eq!((summary.checked, summary.mismatched), (2, 1));
+        assert_eq!(summary.endpoints[&primary.url].mismatched, 1);
+        assert_eq!(summary.recent_mismatches[0].signature, signatures[1]);
+        assert_eq!(summary.recent_mismatches[0].field, "post_balances");
+    }
+
+    /// Test to verify that a configured sample rate is clamped to a probability, and
+    /// that a rate that is not a finite number disables verification rather than
+    /// reaching the sampling.
+    #[test]
+    fn test_sample_rate_is_clamped() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let urls = vec!["http://127.0.0.1:1".to_string()];
+        for (rate, expected) in [
+            (f64::NAN, 0.0),
+            (f64::INFINITY, 0.0),
+            (-1.0, 0.0),
+            (2.0, 1.0),
+        ] {
+            let config = AggregatorConfig {
+                verify_sample_rate: rate,
+                ..Default::default()
+            };
+            let aggregator = Aggregator::new(&urls, db.clone(), config);
+            assert_eq!(aggregator.config.verify_sample_rate, expected);
+        }
+    }
+
+    /// Test to verify that transactions the cluster now reports as finalized are
+    /// promoted, that transactions it no longer knows are retracted once orphaned, and
+    /// that transactions in a slot skipped by the finalized chain are retracted and
+    /// reported to subscribers.
+    #[tokio::test]
+    async fn test_track_finality() {
+        let [finalized, confirmed, orphaned, rolled_back, recent] =
+            [(); 5].map(|_| Signature::new_unique().to_string());
+        let (finalized_status, confirmed_status, rolled_back_status) =
+            (finalized.clone(), confirmed.clone(), rolled_back.clone());
+        let node = MockRpc::start(move |method, params| match method {
+            "getSlot" if params[0]["commitment"] == "finalized" => {
+                MockReply::ok(serde_json::json!(1940))
+            }
+            "getSlot" => MockReply::ok(serde_json::json!(2000)),
+            // Slot 1500 was skipped by the finalized chain
+            "getBlocks" => MockReply::ok(serde_json::json!([1000, 1200, 1900])),
+            _ => {
+                let statuses: Vec<serde_json::Value> = params[0]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|signature| {
+                        let (slot, status) = match signature.as_str().unwrap() {
+                            s if s == finalized_status => (1000, "finalized"),
+                            s if s == confirmed_status => (1000, "confirmed"),
+                            s if s == rolled_back_status => (1500, "confirmed"),
+                            _ => return serde_json::Value::Null,
+                        };
+                        serde_json::json!({
+                            "slot": slot,
+                            "confirmations": null,
+                            "err": null,
+                            "status": { "Ok": null },
+                            "confirmationStatus": status
+                        })
+                    })
+                    .collect();
+                MockReply::ok(serde_json::json!({ "context": { "slot": 2000 }, "value": statuses }))
+            }
+        })
+        .await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        for (signature, slot) in [
+            (&finalized, 1000),
+            (&confirmed, 1000),
+            (&orphaned, 1000),
+            (&rolled_back, 1500),
+            (&recent, 1950),
+        ] {
+            let transaction = TransactionData {
+                signature: signature.clone(),
+                slot,
+                commitment: Commitment::Confirmed,
+                ..Default::default()
+            };
+            db.add_transaction("finality_account", transaction).await;
+        }
+        let aggregator = Aggregator::new(
+            std::slice::from_ref(&node.url),
+            db.clone(),
+            AggregatorConfig::default(),
+        );
+
+        let mut subscriber = db.subscribe_retractions();
+
+        let update = aggregator.track_finality().await.unwrap();
+
+        assert_eq!(
+            update,
+            FinalityUpdate {
+                promoted: 1,
+                retracted: 2
+            }
+        );
+        let mut retractions = vec![
+            subscriber.recv().await.unwrap(),
+            subscriber.recv().await.unwrap(),
+        ];
+        retractions.sort_by_key(|retraction| retraction.slot);
+        assert_eq!(
+            retractions
+                .into_iter()
+                .map(|retraction| (retraction.signature, retraction.reason))
+                .collect::<Vec<_>>(),
+            vec![
+                (orphaned, RetractionReason::Orphaned),
+                (rolled_back, RetractionReason::RolledBack),
+            ]
+        );
+        let stored: Vec<(String, Commitment)> = db
+            .get_transactions("finality_account")
+            .await
+            .into_iter()
+            .map(|tx| (tx.signature, tx.commitment))
+            .collect();
+        assert_eq!(
+            stored,
+            vec![
+                (finalized, Commitment::Finalized),
+                (confirmed, Commitment::Confirmed),
+                (recent, Commitment::Confirmed),
+            ]
+        );
+    }
+
+    /// Test to verify that the finalized blocks are fetched in ranges the node accepts
+    /// when the unfinalized transactions span more slots than a single request allows.
+    #[tokio::test]
+    async fn test_track_finality_fetches_blocks_in_ranges() {
+        let transactions = [
+            (Signature::new_unique().to_string(), 1000u64),
+            (Signature::new_unique().to_string(), 700_000u64),
+        ];
+        let slots: HashMap<String, u64> = transactions.iter().cloned().collect();
+        let node = MockRpc::start(move |method, params| match method {
+            "getSlot" if params[0]["commitment"] == "finalized" => {
+                MockReply::ok(serde_json::json!(800_000))
+            }
+            "getSlot" => MockReply::ok(serde_json::json!(800_100)),
+            "getBlocks" => {
+                let (start, end) = (params[0].as_u64().unwrap(), params[1].as_u64().unwrap());
+                if end - start >= 500_000 {
+                    return MockReply::error(-32602, "Slot range too large; max 500000");
+                }
+                let blocks: Vec<u64> = [1000, 700_000]
+                    .into_iter()
+                    .filter(|slot| (start..=end).contains(slot))
+                    .collect();
+                MockReply::ok(serde_json::json!(blocks))
+            }
+            _ => {
+                let statuses: Vec<serde_json::Value> = params[0]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|signature| {
+                        serde_json::json!({
+                            "slot": slots[signature.as_str().unwrap()],
+                            "confirmations": 10,
+                            "err": null,
+                            "status": { "Ok": null },
+                            "confirmationStatus": "confirmed"
+                        })
+                    })
+                    .collect();
+                MockReply::ok(
+                    serde_json::json!({ "context": { "slot": 800_100 }, "value": statuses }),
+                )
+            }
+        })
+        .await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        for (signature, slot) in &transactions {
+            let transaction = TransactionData {
+                signature: signature.clone(),
+                slot: *slot,
+                commitment: Commitment::Confirmed,
+                ..Default::default()
+            };
+            db.add_transaction("finality_account", transaction).await;
+        }
+        let aggregator = Aggregator::new(
+            std::slice::from_ref(&node.url),
+            db.clone(),
+            AggregatorConfig::default(),
+        );
+
+        let update = aggregator.track_finality().await.unwrap();
+
+        assert_eq!(update, FinalityUpdate::default());
+        assert_eq!(node.calls("getBlocks"), 2);
+        assert_eq!(db.get_transactions("finality_account").await.len(), 2);
+    }
+
+    /// Test to verify that transactions ingested one at a time share the start time of
+    /// their epoch, and that signatures already stored are not fetched again.
+    #[tokio::test]
+    async fn test_ingest_transaction_reuses_epoch_start() {
+        let signatures: Vec<String> = (0..2)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let node = mock_node(signatures.clone(), vec![0; 2]).await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let aggregator = Aggregator::new(
+            std::slice::from_ref(&node.url),
+            db.clone(),
+            AggregatorConfig::default(),
+        );
+
+        for signature in signatures.iter().chain(&signatures) {
+            aggregator
+                .ingest_transaction("sender", signature, Commitment::Finalized)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(db.get_transactions("sender").await.len(), 2);
+        assert_eq!(node.calls("getTransaction"), 2);
+        assert_eq!(node.calls("getEpochInfo"), 1);
+    }
+
+    /// Test to verify that the block stream starts at the tip, stores the transactions
+    /// of later blocks mentioning a watched address, including blocks of a previous
+    /// epoch, skips the blocks failing with a permanent error and resumes from the
+    /// block it failed to fetch for now.
+    #[tokio::test]
+    async fn test_stream_blocks_from_checkpoint() {
+        let watched = Pubkey::new_unique().to_string();
+        let [sent, unrelated, received] = [(); 3].map(|_| Signature::new_unique().to_string());
+        let tip = Arc::new(AtomicUsize::new(1000));
+        let failures = Arc::new(AtomicUsize::new(0));
+
+        let (node_tip, node_failures) = (tip.clone(), failures.clone());
+        let blocks = [
+            (1002, mock_rpc::transaction(&sent, &watched, "receiver")),
+            (
+                1004,
+                mock_rpc::transaction(&unrelated, "sender", "receiver"),
+            ),
+            (1005, serde_json::Value::Null),
+            (1007, mock_rpc::transaction(&received, "sender", &watched)),
+        ];
+        let transfers = mock_rpc::transfer_handler(Vec::new(), "sender");
+        let node = MockRpc::start(move |method, params| match method {
+            "getSlot" => MockReply::ok(serde_json::json!(node_tip.load(Ordering::SeqCst))),
+            "getBlocks" => {
+                let (start, end) = (params[0].as_u64().unwrap(), params[1].as_u64().unwrap());
+                let slots: Vec<u64> = blocks
+                    .iter()
+                    .map(|(slot, _)| *slot)
+                    .filter(|slot| (start..=end).contains(slot))
+                    .collect();
+                MockReply::ok(serde_json::json!(slots))
+            }
+            "getBlock" => {
+                let slot = params[0].as_u64().unwrap();
+                // One block is missing from long-term storage for good
+                if slot == 1005 {
+                    return MockReply::error(-32009, "Slot 1005 was skipped");
+                }
+                // The last block is unavailable the first time it is requested
+                if slot == 1007 && node_failures.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return MockReply::error(-32004, "Block not available for slot 1007");
+                }
+                let (_, transaction) = blocks.iter().find(|(s, _)| *s == slot).unwrap();
+                let mut block = mock_rpc::block(slot, std::slice::from_ref(transaction));
+                // The first block was produced before the current epoch started
+                if slot == 1002 {
+                    block["blockTime"] = serde_json::json!(1600000000);
+                }
+                MockReply::ok(block)
+            }
+            _ => transfers(method, params),
+        })
+        .await;
+
+        let (db, dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let checkpoint_path = dir.path().join("checkpoint.txt");
+        let checkpoint = SlotCheckpoint::new(checkpoint_path.to_string_lossy().into_owned());
+        let aggregator = Aggregator::new(
+            std::slice::from_ref(&node.url),
+            db.clone(),
+            AggregatorConfig {
+                commitment: Commitment::Confirmed,
+                retry: RetryPolicy {
+                    max_retries: 0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let addresses = vec![watched.clone()];
+
+        // Without a checkpoint, the stream starts at the tip
+        let progress = aggregator
+            .stream_blocks(&addresses, &checkpoint)
+            .await
+            .unwrap();
+        assert_eq!((progress.last_slot, progress.caught_up), (1000, true));
+
+        // The pass skips the missing block and stops at the block it failed to fetch
+        tip.store(1010, Ordering::SeqCst);
+        let progress = aggregator
+            .stream_blocks(&addresses, &checkpoint)
+            .await
+            .unwrap();
+        assert_eq!(
+            progress,
+            BlockProgress {
+                last_slot: 1005,
+                blocks: 2,
+                transactions: 1,
+                skipped: 1,
+                caught_up: false
+            }
+        );
+        assert_eq!(checkpoint.load(), Some(1005));
+
+        // The next pass resumes from it and runs to the tip
+        let progress = aggregator
+            .stream_blocks(&addresses, &checkpoint)
+            .await
+            .unwrap();
+        assert_eq!(
+            progress,
+            BlockProgress {
+                last_slot: 1010,
+                blocks: 1,
+                transactions: 1,
+                skipped: 0,
+                caught_up: true
+            }
+        );
+        let stored: Vec<(String, Commitment)> = db
+            .get_transactions(&watched)
+            .await
+            .into_iter()
+            .map(|tx| (tx.signature, tx.commitment))
+            .collect();
+        assert_eq!(
+            stored,
+            vec![
+                (sent, Commitment::Confirmed),
+                (received, Commitment::Confirmed)
+            ]
+        );
+    }
+
+    /// Test to verify that every RPC call of a fetch, including the epoch info and block
+    /// time, waits for the rate limit instead of failing.
+    #[tokio::test]
+    async fn test_fetch_transactions_within_rate_limit() {
+        let signatures: Vec<String> = (0..3)
+            .map(|_| Signature::new_unique().to_string())
+            .collect();
+        let node = mock_node(signatures.clone(), vec![0; 3]).await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let config = AggregatorConfig {
+            rate_limit: Some(RateLimit {
+                requests_per_second: 20.0,
+                burst: 1,
+            }),
+            ..Default::default()
+        };
+        let aggregator = Aggregator::new(std::slice::from_ref(&node.url), db.clone(), config);
+
+        let started = Instant::now();
+        let address = Pubkey::new_unique().to_string();
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        assert_eq!(transactions.len(), 3);
+        // Six calls: the first uses the burst, the other five wait 50ms each
+        assert_eq!(node.http_requests(), 6);
+        assert!(started.elapsed() >= Duration::from_millis(250));
+    }
+
+    /// Test to verify that the transactions listed for an address are fetched from the
+    /// source, parsed and stored, skipping those from a previous epoch and leaving out
+    /// the transactions of other addresses.
+    #[tokio::test]
+    async fn test_fetch_recent_transactions_from_fixtures() {
+        let address = Pubkey::new_unique().to_string();
+        let other = Pubkey::new_unique().to_string();
+        let [sent, received, old, failed, unrelated] =
+            [(); 5].map(|_| Signature::new_unique().to_string());
+        let mut old_transaction = mock_rpc::transaction(&old, &address, "receiver");
+        old_transaction["blockTime"] = serde_json::json!(1600000000);
+        let mut failed_transaction = mock_rpc::transaction(&failed, &address, "receiver");
+        let error = serde_json::json!({ "InstructionError": [0, { "Custom": 1 }] });
+        failed_transaction["meta"]["err"] = error.clone();
+        failed_transaction["meta"]["status"] = serde_json::json!({ "Err": error });
+        let source = Arc::new(
+            FixtureSource::default()
+                .with_transaction(
+                    &[&address],
+                    mock_rpc::transaction(&sent, &address, "receiver"),
+                )
+                .with_transaction(
+                    &[&address],
+                    mock_rpc::transaction(&received, "sender", &address),
+                )
+                .with_transaction(&[&address], old_transaction)
+                .with_transaction(&[&address], failed_transaction)
+                .with_transaction(
+                    &[&other],
+                    mock_rpc::transaction(&unrelated, &other, "receiver"),
+                ),
+        );
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let aggregator = Aggregator::new(
+            &["fixture".to_string()],
+            db.clone(),
+            AggregatorConfig::default(),
+        )
+        .with_source(source.clone());
+
+        let transactions = aggregator
+            .fetch_recent_transactions(&address)
+            .await
+            .unwrap();
+
+        let signatures: Vec<&str> = transactions
+            .iter()
+            .map(|tx| tx.signature.as_str())
+            .collect();
+        assert_eq!(
+            signatures,
+            vec![failed.as_str(), received.as_str(), sent.as_str()]
+        );
+        assert_eq!(transactions[0].status, TransactionStatus::Failed);
+        assert_eq!(
+            transactions[1].amount,
+            Amount::sol(500000, Direction::Credit)
+        );
+        assert_eq!(
+            transactions[2].amount,
+            Amount::sol(500000, Direction::Debit)
+        );
+        assert_eq!(transactions[2].receiver, "receiver");
+        assert!(transactions
+            .iter()
+            .all(|tx| tx.commitment == Commitment::Finalized
+                && tx.endpoint.as_deref() == Some("fixture")));
+        assert_eq!(db.get_transactions(&address).await, transactions);
+        assert!(db.get_transactions(&other).await.is_empty());
+        assert_eq!(source.calls("getTransaction"), 4);
+    }
+
+    /// Test to verify that an announced transaction is fetched from the source and
+    /// stored at the announced commitment, and that a transaction the source does not
+    /// know is reported without being retried.
+    #[tokio::test]
+    async fn test_ingest_transaction_from_fixture() {
+        let address = Pubkey::new_unique().to_string();
+        let [known, unknown] = [(); 2].map(|_| Signature::new_unique().to_string());
+        let source = Arc::new(FixtureSource::default().with_transaction(
+            &[&address],
+            mock_rpc::transaction(&known, &address, "receiver"),
+        ));
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let aggregator = Aggregator::new(
+            &["fixture".to_string()],
+            db.clone(),
+            AggregatorConfig::default(),
+        )
+        .with_source(source.clone());
+
+        let stored = aggregator
+            .ingest_transaction(&address, &known, Commitment::Confirmed)
+            .await
+            .unwrap()
+            .expect("The transaction was skipped");
+        assert_eq!(stored.commitment, Commitment::Confirmed);
+        assert_eq!(db.get_transactions(&address).await, vec![stored]);
+
+        assert!(aggregator
+            .ingest_transaction(&address, &unknown, Commitment::Confirmed)
+            .await
+            .is_err());
+        assert_eq!(source.calls("getTransaction"), 2);
+    }
+
+    /// Test to verify that the `Aggregator` can add a transaction to the in-memory
+    /// database and retrieve it correctly.
+    #[tokio::test]
+    async fn test_aggregator_add_and_fetch_transaction() {
+        // Initialize the in-memory database
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        // Create a mock transaction
+        let transaction = TransactionData {
+            signature: "test_signature".to_string(),
+            sender: "sender1".to_string(),
+            receiver: "receiver1".to_string(),
+            amount: Amount::sol(100, Direction::Credit),
+            timestamp: 1628500000,
+            ..Default::default()
+        };
+
+        // Add the transaction to the database
+        db.add_transaction("sender1", transaction.clone()).await;
+
+        // Fetch the transactions for the sender
+        let transactions = db.get_transactions("sender1").await;
+
+        // Verify that the transaction is correctly stored and retrieved
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0], transaction);
+    }
+
+    /// Test to verify that a failed transaction is recorded with its error, fee,
+    /// compute units, slot and recent blockhash taken from the status metadata, and
+    /// classified as failed.
+    #[test]
+    fn test_parse_failed_transaction() {
+        let decoders = DecoderRegistry::with_builtins();
+        let classifier = Classifier::with_builtins();
+        let mut transaction = mock_rpc::transaction("mock_signature", "sender", "receiver");
+        transaction["meta"]["err"] = serde_json::json!({
+            "InstructionError": [0, { "Custom": 1 }]
+        });
+        let transaction = serde_json::from_value(transaction).unwrap();
+
+        let parsed = parse_transaction(
+            &decoders,
+            &classifier,
+            "sender",
+            "mock_signature",
+            &transaction,
+        )
+        .unwrap();
+
+        assert_eq!(parsed.status, TransactionStatus::Failed);
+        assert!(parsed
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("custom program error"));
+        assert_eq!(parsed.fee, 5000);
+        assert_eq!(parsed.compute_units_consumed, Some(150));
+        assert_eq!(parsed.slot, 1000);
+        assert_eq!(parsed.recent_blockhash, "mock_blockhash");
+        assert_eq!(parsed.kinds, vec![TransactionKind::Failed]);
+    }
+
+    /// Test to verify that the amount is the signed balance change of the indexed
+    /// account, excluding the fee paid by the fee payer.
+    #[test]
+    fn test_parse_transaction_amount_direction() {
+        let decoders = DecoderRegistry::with_builtins();
+        let classifier = Classifier::with_builtins();
+        let mut transaction = mock_rpc::transaction("mock_signature", "sender", "receiver");
+        // Only the fee leaves the fee payer
+        transaction["meta"]["postBalances"] = serde_json::json!([995000, 0, 1]);
+        let fee_only = serde_json::from_value(transaction).unwrap();
+
+        let sent = parse_transaction(
+            &decoders,
+            &classifier,
+            "sender",
+            "mock_signature",
+            &fee_only,
+        )
+        .unwrap();
+        assert_eq!(sent.amount, Amount::sol(0, Direction::Credit));
+
+        let transaction = serde_json::from_value(mock_rpc::transaction(
+            "mock_signature",
+            "sender",
+            "receiver",
+        ))
+        .unwrap();
+
+        let sent = parse_transaction(
+            &decoders,
+            &classifier,
+            "sender",
+            "mock_signature",
+            &transaction,
+        )
+        .unwrap();
+        assert_eq!(sent.amount, Amount::sol(500000, Direction::Debit));
+
+        let received = parse_transaction(
+            &decoders,
+            &classifier,
+            "receiver",
+            "mock_signature",
+            &transaction,
+        )
+        .unwrap();
+        assert_eq!(received.amount, Amount::sol(500000, Direction::Credit));
+        assert_eq!(
+            received.events,
+            vec![TransactionEvent::SolTransfer {
+                from: "sender".to_string(),
+                to: "receiver".to_string(),
+                lamports: 500000,
+            }]
+        );
+    }
+
+    /// Test to verify that the rewards of the last completed epoch and of earlier
+    /// epochs are fetched once, that epochs the node no longer serves are skipped, and
+    /// that epochs already fetched are skipped on the following polls.
+    #[tokio::test]
+    async fn test_fetch_inflation_rewards_backfills_epochs() {
+        let stake_account = Pubkey::new_unique().to_string();
+        let transfers = mock_rpc::transfer_handler(Vec::new(), "sender");
+        let node = MockRpc::start(move |method, params| match method {
+            "getInflationReward" => match params[1]["epoch"].as_u64().unwrap() {
+                epoch @ (498 | 499) => MockReply::ok(serde_json::json!([{
+                    "epoch": epoch,
+                    "effectiveSlot": epoch * 432000,
+                    "amount": 2_500_000,
+                    "postBalance": 10_002_500_000u64,
+                    "commission": 7
+                }])),
+                497 => MockReply::ok(serde_json::json!([null])),
+                _ => MockReply::error(-32009, "Slot was skipped"),
+            },
+            _ => transfers(method, params),
+        })
+        .await;
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let aggregator = Aggregator::new(
+            std::slice::from_ref(&node.url),
+            db.clone(),
+            AggregatorConfig::default(),
+        );
+        let stake_accounts = vec![stake_account.clone()];
+
+        let stored = aggregator
+            .fetch_inflation_rewards(&stake_accounts)
+            .await
+            .unwrap();
+        let signatures: Vec<String> = stored.into_iter().map(|tx| tx.signature).collect();
+        assert_eq!(
+            signatures,
+            vec![
+                reward_signature(&stake_account, 499),
+                reward_signature(&stake_account, 498)
+            ]
+        );
+        assert_eq!(node.calls("getInflationReward"), 11);
+
+        let stored = aggregator
+            .fetch_inflation_rewards(&stake_accounts)
+            .await
+            .unwrap();
+        assert!(stored.is_empty());
+        assert_eq!(node.calls("getInflationReward"), 11);
+    }
+
+    /// Test to verify that an inflation reward is stored as a synthetic transaction
+    /// crediting the stake account, identified by the epoch.
+    #[test]
+    fn test_reward_transaction() {
+        let reward = RpcInflationReward {
+            epoch: 512,
+            effective_slot: 221184000,
+            amount: 2_500_000,
+            post_balance: 10_002_500_000,
+            commission: Some(7),
+        };
+
+        let transaction = reward_transaction("stake_account", &reward, 1700000000);
+
+        assert_eq!(transaction.signature, "reward:512:stake_account");
+        assert_eq!(transaction.receiver, "stake_account");
+        assert_eq!(
+            transaction.amount,
+            Amount::sol(2_500_000, Direction::Credit)
+        );
+        assert_eq!(transaction.slot, 221184000);
+        assert_eq!(
+            transaction.events,
+            vec![TransactionEvent::InflationReward {
+                stake_account: "stake_account".to_string(),
+                epoch: 512,
+                lamports: 2_500_000,
+                post_balance: 10_002_500_000,
+                commission: Some(7),
+            }]
+        );
     }
 }
@@ -0,0 +1,245 @@
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Number of decimals used by native SOL (1 SOL = 10^9 lamports).
+pub const SOL_DECIMALS: u8 = 9;
+
+/// The asset an `Amount` is denominated in.
+///
+/// Serialized as the string `"SOL"` for native SOL, or as the mint address for SPL tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Asset {
+    /// Native SOL, denominated in lamports.
+    #[default]
+    Sol,
+    /// An SPL token, identified by its mint address.
+    Token(String),
+}
+
+impl Serialize for Asset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Asset::Sol => serializer.serialize_str("SOL"),
+            Asset::Token(mint) => serializer.serialize_str(mint),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Asset {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let asset = String::deserialize(deserializer)?;
+        Ok(if asset == "SOL" {
+            Asset::Sol
+        } else {
+            Asset::Token(asset)
+        })
+    }
+}
+
+/// Direction of a balance change, relative to the account the transaction was indexed for.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The account's balance increased.
+    #[default]
+    Credit,
+    /// The account's balance decreased.
+    Debit,
+}
+
+/// A typed amount: the asset, its raw integer amount, the asset's decimals and the
+/// direction of the balance change.
+///
+/// The raw amount is serialized as a string so that large token amounts survive
+/// JSON consumers that parse numbers as doubles. For compatibility with transactions
+/// persisted before amounts were typed, a bare integer is accepted on deserialization
+/// and interpreted as a credit in lamports.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, Default)]
+pub struct Amount {
+    pub asset: Asset, // Asset the amount is denominated in
+    #[serde(serialize_with = "serialize_raw")]
+    pub raw: u64, // Amount in the asset's smallest unit (lamports for SOL)
+    pub decimals: u8, // Number of decimals of the asset
+    pub direction: Direction, // Whether the amount was received or sent
+}
+
+impl Amount {
+    /// Creates an `Amount` in lamports of native SOL.
+    ///
+    /// # Arguments
+    ///
+    /// * `lamports` - The amount in lamports.
+    /// * `direction` - The direction of the balance change.
+    pub fn sol(lamports: u64, direction: Direction) -> Self {
+        Self {
+            asset: Asset::Sol,
+            raw: lamports,
+            decimals: SOL_DECIMALS,
+            direction,
+        }
+    }
+
+    /// Creates an `Amount` from a signed balance delta.
+    ///
+    /// # Arguments
+    ///
+    /// * `asset` - The asset the delta is denominated in.
+    /// * `delta` - The signed change of the balance in the asset's smallest unit.
+    /// * `decimals` - The number of decimals of the asset.
+    ///
+    /// # Returns
+    ///
+    /// The amount, or `None` if the magnitude of the delta does not fit in a `u64`.
+    pub fn from_delta(asset: Asset, delta: i128, decimals: u8) -> Option<Self> {
+        let direction = if delta < 0 {
+            Direction::Debit
+        } else {
+            Direction::Credit
+        };
+        Some(Self {
+            asset,
+            raw: u64::try_from(delta.unsigned_abs()).ok()?,
+            decimals,
+            direction,
+        })
+    }
+
+    /// Returns the signed amount as a decimal string in UI units, e.g. `"-0.000005"`
+    /// for a debit of 5000 lamports.
+    pub fn to_ui_string(&self) -> String {
+        let sign = match self.direction {
+            Direction::Credit => "",
+            Direction::Debit if self.raw == 0 => "",
+            Direction::Debit => "-",
+        };
+        // Shift the decimal point within the zero-padded digits rather than dividing,
+        // so that any number of decimals is supported without overflow.
+        let decimals = usize::from(self.decimals);
+        let digits = format!("{:0>width$}", self.raw, width = decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        let fraction = fraction.trim_end_matches('0');
+
+        if fraction.is_empty() {
+            format!("{}{}", sign, whole)
+        } else {
+            format!("{}{}.{}", sign, whole, fraction)
+        }
+    }
+}
+
+/// Serializes a raw amount as a decimal string.
+fn serialize_raw<S: Serializer>(raw: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(raw)
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Raw amounts may be encoded as strings or, in older records, as numbers.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawAmount {
+            Number(u64),
+            String(String),
+        }
+
+        /// The typed representation, or a legacy bare lamport amount.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum AmountRepr {
+            Legacy(u64),
+            Typed {
+                asset: Asset,
+                raw: RawAmount,
+                decimals: u8,
+                direction: Direction,
+            },
+        }
+
+        match AmountRepr::deserialize(deserializer)? {
+            AmountRepr::Legacy(lamports) => Ok(Amount::sol(lamports, Direction::Credit)),
+            AmountRepr::Typed {
+                asset,
+                raw,
+                decimals,
+                direction,
+            } => {
+                let raw = match raw {
+                    RawAmount::Number(raw) => raw,
+                    RawAmount::String(raw) => raw.parse().map_err(de::Error::custom)?,
+                };
+                Ok(Amount {
+                    asset,
+                    raw,
+                    decimals,
+                    direction,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test to verify that amounts render as signed decimal strings in UI units.
+    #[test]
+    fn test_amount_to_ui_string() {
+        assert_eq!(
+            Amount::from_delta(Asset::Sol, -5000, SOL_DECIMALS)
+                .unwrap()
+                .to_ui_string(),
+            "-0.000005"
+        );
+        assert_eq!(
+            Amount::from_delta(Asset::Token("mint".to_string()), 1_500_000, 6)
+                .unwrap()
+                .to_ui_string(),
+            "1.5"
+        );
+        assert_eq!(
+            Amount::from_delta(Asset::Token("mint".to_string()), 12, 40)
+                .unwrap()
+                .to_ui_string(),
+            format!("0.{}12", "0".repeat(38))
+        );
+        assert_eq!(
+            Amount::from_delta(Asset::Token("mint".to_string()), -1, 255)
+                .unwrap()
+                .to_ui_string(),
+            format!("-0.{}1", "0".repeat(254))
+        );
+        assert_eq!(
+            Amount::sol(2_000_000_000, Direction::Credit).to_ui_string(),
+            "2"
+        );
+    }
+
+    /// Test to verify that typed amounts round-trip through JSON without loss and that
+    /// legacy bare integer amounts are still accepted.
+    #[test]
+    fn test_amount_serialization() {
+        let amount =
+            Amount::from_delta(Asset::Token("mint".to_string()), i128::from(u64::MAX), 0).unwrap();
+        let json = serde_json::to_value(&amount).unwrap();
+        assert_eq!(json["raw"], u64::MAX.to_string());
+        assert_eq!(serde_json::from_value::<Amount>(json).unwrap(), amount);
+
+        let legacy: Amount = serde_json::from_str("600").unwrap();
+        assert_eq!(legacy, Amount::sol(600, Direction::Credit));
+    }
+
+    /// Test to verify that a delta whose magnitude overflows a `u64` is rejected
+    /// rather than saturated.
+    #[test]
+    fn test_amount_from_overflowing_delta() {
+        let mint = Asset::Token("mint".to_string());
+        let overflow = i128::from(u64::MAX) + 1;
+        assert_eq!(Amount::from_delta(mint.clone(), overflow, 0), None);
+        assert_eq!(Amount::from_delta(mint.clone(), -overflow, 0), None);
+        assert_eq!(
+            Amount::from_delta(mint, -i128::from(u64::MAX), 0).map(|a| a.direction),
+            Some(Direction::Debit)
+        );
+    }
+}
@@ -0,0 +1,518 @@
+use crate::decoder::InstructionDecoder;
+use crate::events::TransactionEvent;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use solana_sdk::bs58;
+use solana_sdk::hash::hashv;
+use solana_sdk::pubkey::Pubkey;
+use std::path::Path;
+use thiserror::Error;
+
+/// Discriminator prefixed to the data of the self-CPI instructions Anchor uses to emit
+/// events with `emit_cpi!`, in little-endian byte order.
+const EVENT_IX_TAG: [u8; 8] = 0x1d9a_cb51_2ea5_45e4u64.to_le_bytes();
+
+/// Error type for loading Anchor IDL files.
+#[derive(Debug, Error)]
+pub enum AnchorIdlError {
+    /// Error that occurs when reading an IDL file.
+    #[error("Failed to read IDL file: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// Error that occurs when parsing an IDL file.
+    #[error("Failed to parse IDL: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    /// Indicates that the IDL does not declare the address of its program.
+    #[error("IDL does not declare a program address")]
+    MissingAddress,
+
+    /// Indicates that the program address declared by the IDL is not a valid public key.
+    #[error("Invalid program address in IDL: {0}")]
+    InvalidAddress(String),
+}
+
+/// An Anchor IDL, accepting both the legacy format and the format introduced in
+/// Anchor 0.30. Only the parts needed to decode instructions and events are read.
+#[derive(Debug, Deserialize)]
+pub struct Idl {
+    #[serde(default)]
+    address: Option<String>, // Program address (Anchor 0.30 and later)
+    #[serde(default)]
+    metadata: Option<IdlMetadata>, // Metadata, carrying the address in legacy IDLs
+    #[serde(default)]
+    instructions: Vec<IdlInstruction>, // Instructions of the program
+    #[serde(default)]
+    events: Vec<IdlEvent>, // Events emitted by the program
+    #[serde(default)]
+    types: Vec<IdlTypeDef>, // User-defined types referenced by instructions and events
+}
+
+/// Metadata section of an IDL.
+#[derive(Debug, Deserialize)]
+struct IdlMetadata {
+    #[serde(default)]
+    address: Option<String>, // Program address (legacy IDLs)
+}
+
+/// An instruction declared by an IDL.
+#[derive(Debug, Deserialize)]
+struct IdlInstruction {
+    name: String, // Name of the instruction
+    #[serde(default)]
+    discriminator: Option<Vec<u8>>, // Explicit discriminator (Anchor 0.30 and later)
+    #[serde(default)]
+    args: Vec<IdlField>, // Arguments of the instruction
+}
+
+/// An event declared by an IDL.
+#[derive(Debug, Deserialize)]
+struct IdlEvent {
+    name: String, // Name of the event
+    #[serde(default)]
+    discriminator: Option<Vec<u8>>, // Explicit discriminator (Anchor 0.30 and later)
+    #[serde(default)]
+    fields: Option<Vec<IdlField>>, // Inline fields (legacy IDLs), otherwise a type of the same name
+}
+
+/// A named field of an instruction, event or struct.
+#[derive(Debug, Deserialize)]
+struct IdlField {
+    name: String, // Name of the field
+    #[serde(rename = "type")]
+    ty: IdlType, // Type of the field
+}
+
+/// A user-defined type declared by an IDL.
+#[derive(Debug, Deserialize)]
+struct IdlTypeDef {
+    name: String, // Name of the type
+    #[serde(rename = "type")]
+    ty: IdlTypeDefBody, // Definition of the type
+}
+
+/// The definition of a user-defined type.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum IdlTypeDefBody {
+    Struct {
+        #[serde(default)]
+        fields: Option<IdlFields>,
+    },
+    Enum {
+        variants: Vec<IdlEnumVariant>,
+    },
+    Type {
+        alias: IdlType,
+    },
+}
+
+/// A variant of a user-defined enum.
+#[derive(Debug, Deserialize)]
+struct IdlEnumVariant {
+    name: String, // Name of the variant
+    #[serde(default)]
+    fields: Option<IdlFields>, // Fields of the variant, if any
+}
+
+/// The fields of a struct or enum variant, either named or positional.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IdlFields {
+    Named(Vec<IdlField>),
+    Tuple(Vec<IdlType>),
+}
+
+/// The type of a field, as written in the IDL.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IdlType {
+    Primitive(String),
+    Vec { vec: Box<IdlType> },
+    Option { option: Box<IdlType> },
+    COption { coption: Box<IdlType> },
+    Array { array: (Box<IdlType>, usize) },
+    Defined { defined: IdlDefined },
+}
+
+/// A reference to a user-defined type.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IdlDefined {
+    Name(String),
+    Named { name: String },
+}
+
+impl IdlDefined {
+    fn name(&self) -> &str {
+        match self {
+            IdlDefined::Name(name) | IdlDefined::Named { name } => name,
+        }
+    }
+}
+
+/// Decodes the instructions and events of an Anchor program from its IDL.
+pub struct AnchorDecoder {
+    program_id: Pubkey, // Program described by the IDL
+    idl: Idl,           // IDL of the program
+}
+
+impl AnchorDecoder {
+    /// Creates a new `AnchorDecoder` from a parsed IDL.
+    ///
+    /// # Arguments
+    ///
+    /// * `idl` - The IDL of the program, which must declare the program address.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the decoder, or an `AnchorIdlError` if the IDL does not
+    /// declare a valid program address.
+    pub fn new(idl: Idl) -> Result<Self, AnchorIdlError> {
+        let address = idl
+            .address
+            .clone()
+            .or_else(|| idl.metadata.as_ref().and_then(|m| m.address.clone()))
+            .ok_or(AnchorIdlError::MissingAddress)?;
+        let program_id = address
+            .parse()
+            .map_err(|_| AnchorIdlError::InvalidAddress(address))?;
+        Ok(Self { program_id, idl })
+    }
+
+    /// Loads an `AnchorDecoder` from an IDL JSON file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the IDL file.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the decoder, or an `AnchorIdlError` if the file cannot be
+    /// read or parsed.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AnchorIdlError> {
+        let idl = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Self::new(idl)
+    }
+
+    /// Decodes an event from its discriminator-prefixed payload.
+    fn decode_event(&self, data: &[u8]) -> Option<TransactionEvent> {
+        let (discriminator, payload) = data.split_at_checked(8)?;
+        let event = self.idl.events.iter().find(|event| {
+            discriminator_of(&event.discriminator, "event", &event.name) == discriminator
+        })?;
+
+        let mut reader = BorshReader::new(payload);
+        let data = match &event.fields {
+            Some(fields) => reader.read_named_fields(fields, &self.idl),
+            None => reader.read_defined(&event.name, &self.idl),
+        };
+
+        Some(TransactionEvent::AnchorEvent {
+            program_id: self.program_id.to_string(),
+            name: event.name.clone(),
+            data: data.unwrap_or(Value::Null),
+        })
+    }
+}
+
+impl InstructionDecoder for AnchorDecoder {
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn decode(&self, data: &[u8], _accounts: &[String]) -> Option<TransactionEvent> {
+        if let Some(event) = data.strip_prefix(&EVENT_IX_TAG) {
+            return self.decode_event(event);
+        }
+
+        let (discriminator, payload) = data.split_at_checked(8)?;
+        let instruction = self.idl.instructions.iter().find(|instruction| {
+            discriminator_of(
+                &instruction.discriminator,
+                "global",
+                &to_snake_case(&instruction.name),
+            ) == discriminator
+        })?;
+
+        // Arguments that cannot be decoded are reported as null rather than dropping
+        // the instruction, so that it can still be found by name.
+        let args = BorshReader::new(payload)
+            .read_named_fields(&instruction.args, &self.idl)
+            .unwrap_or(Value::Null);
+
+        Some(TransactionEvent::Instruction {
+            program_id: self.program_id.to_string(),
+            name: instruction.name.clone(),
+            args,
+        })
+    }
+
+    fn decode_log(&self, data: &[u8]) -> Option<TransactionEvent> {
+        self.decode_event(data)
+    }
+}
+
+/// Returns the explicit discriminator if present, otherwise the first 8 bytes of
+/// `sha256("<namespace>:<name>")` as computed by Anchor.
+fn discriminator_of(explicit: &Option<Vec<u8>>, namespace: &str, name: &str) -> Vec<u8> {
+    match explicit {
+        Some(discriminator) => discriminator.clone(),
+        None => hashv(&[namespace.as_bytes(), b":", name.as_bytes()]).to_bytes()[..8].to_vec(),
+    }
+}
+
+/// Converts a camelCase instruction name from a legacy IDL into snake_case.
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len());
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// Reads Borsh-encoded values into JSON according to IDL types.
+struct BorshReader<'a> {
+    data: &'a [u8], // Remaining bytes to read
+}
+
+impl<'a> BorshReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.data.split_at_checked(len)?;
+        self.data = rest;
+        Some(taken)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take_array()?))
+    }
+
+    fn read_named_fields(&mut self, fields: &[IdlField], idl: &Idl) -> Option<Value> {
+        let mut object = Map::new();
+        for field in fields {
+            object.insert(field.name.clone(), self.read(&field.ty, idl)?);
+        }
+        Some(Value::Object(object))
+    }
+
+    fn read_fields(&mut self, fields: &Option<IdlFields>, idl: &Idl) -> Option<Value> {
+        match fields {
+            None => Some(Value::Null),
+            Some(IdlFields::Named(fields)) => self.read_named_fields(fields, idl),
+            Some(IdlFields::Tuple(types)) => types
+                .iter()
+                .map(|ty| self.read(ty, idl))
+                .collect::<Option<Vec<_>>>()
+                .map(Value::Array),
+        }
+    }
+
+    fn read_defined(&mut self, name: &str, idl: &Idl) -> Option<Value> {
+        let type_def = idl.types.iter().find(|type_def| type_def.name == name)?;
+        match &type_def.ty {
+            IdlTypeDefBody::Struct { fields } => self.read_fields(fields, idl),
+            IdlTypeDefBody::Enum { variants } => {
+                let variant = variants.get(usize::from(self.take(1)?[0]))?;
+                match self.read_fields(&variant.fields, idl)? {
+                    Value::Null => Some(Value::String(variant.name.clone())),
+                    fields => Some(serde_json::json!({ variant.name.clone(): fields })),
+                }
+            }
+            IdlTypeDefBody::Type { alias } => self.read(alias, idl),
+        }
+    }
+
+    fn read(&mut self, ty: &IdlType, idl: &Idl) -> Option<Value> {
+        let value = match ty {
+            IdlType::Primitive(primitive) => match primitive.as_str() {
+                "bool" => Value::Bool(self.take(1)?[0] != 0),
+                "u8" => Value::from(self.take(1)?[0]),
+                "i8" => Value::from(i8::from_le_bytes(self.take_array()?)),
+                "u16" => Value::from(u16::from_le_bytes(self.take_array()?)),
+                "i16" => Value::from(i16::from_le_bytes(self.take_array()?)),
+                "u32" => Value::from(self.read_u32()?),
+                "i32" => Value::from(i32::from_le_bytes(self.take_array()?)),
+                "u64" => Value::from(u64::from_le_bytes(self.take_array()?)),
+                "i64" => Value::from(i64::from_le_bytes(self.take_array()?)),
+                // 128-bit integers do not fit JSON numbers and are rendered as strings
+                "u128" => Value::String(u128::from_le_bytes(self.take_array()?).to_string()),
+                "i128" => Value::String(i128::from_le_bytes(self.take_array()?).to_string()),
+                "f32" => Value::from(f32::from_le_bytes(self.take_array()?)),
+                "f64" => Value::from(f64::from_le_bytes(self.take_array()?)),
+                "string" => {
+                    let len = self.read_u32()? as usize;
+                    Value::String(String::from_utf8(self.take(len)?.to_vec()).ok()?)
+                }
+                "bytes" => {
+                    let len = self.read_u32()? as usize;
+                    Value::from(self.take(len)?.to_vec())
+                }
+                "publicKey" | "pubkey" => Value::String(bs58::encode(self.take(32)?).into_string()),
+                _ => return None,
+            },
+            IdlType::Vec { vec } => {
+                let len = self.read_u32()?;
+                (0..len)
+                    .map(|_| self.read(vec, idl))
+                    .collect::<Option<Vec<_>>>()
+                    .map(Value::Array)?
+            }
+            IdlType::Option { option } => match self.take(1)?[0] {
+                0 => Value::Null,
+                _ => self.read(option, idl)?,
+            },
+            IdlType::COption { coption } => match self.read_u32()? {
+                0 => {
+                    // A `COption` reserves space for its value even when empty
+                    let mut empty = BorshReader::new(self.data);
+                    empty.read(coption, idl)?;
+                    self.data = empty.data;
+                    Value::Null
+                }
+                _ => self.read(coption, idl)?,
+            },
+            IdlType::Array { array: (ty, len) } => (0..*len)
+                .map(|_| self.read(ty, idl))
+                .collect::<Option<Vec<_>>>()
+                .map(Value::Array)?,
+            IdlType::Defined { defined } => self.read_defined(defined.name(), idl)?,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::DecoderRegistry;
+    use crate::instructions::InstructionRecord;
+    use base64::Engine;
+
+    const PROGRAM_ID: &str = "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS";
+
+    /// Returns a legacy-format IDL with one instruction and one event.
+    fn mock_idl() -> Idl {
+        serde_json::from_value(serde_json::json!({
+            "version": "0.1.0",
+            "name": "vault",
+            "instructions": [{
+                "name": "depositFunds",
+                "accounts": [{ "name": "user", "isMut": true, "isSigner": true }],
+                "args": [
+                    { "name": "amount", "type": "u64" },
+                    { "name": "memo", "type": { "option": "string" } },
+                    { "name": "config", "type": { "defined": "DepositConfig" } }
+                ]
+            }],
+            "types": [{
+                "name": "DepositConfig",
+                "type": {
+                    "kind": "struct",
+                    "fields": [
+                        { "name": "beneficiary", "type": "publicKey" },
+                        { "name": "tags", "type": { "vec": "u8" } }
+                    ]
+                }
+            }],
+            "events": [{
+                "name": "Deposited",
+                "fields": [
+                    { "name": "user", "type": "publicKey", "index": false },
+                    { "name": "amount", "type": "u64", "index": false }
+                ]
+            }],
+            "metadata": { "address": PROGRAM_ID }
+        }))
+        .unwrap()
+    }
+
+    /// Test to verify that instruction names and Borsh-encoded arguments are decoded
+    /// according to the IDL.
+    #[test]
+    fn test_decode_instruction_from_idl() {
+        let decoder = AnchorDecoder::new(mock_idl()).unwrap();
+        let beneficiary = Pubkey::new_unique();
+
+        let mut data = hashv(&[b"global:deposit_funds"]).to_bytes()[..8].to_vec();
+        data.extend(1_000_000u64.to_le_bytes());
+        data.push(1);
+        data.extend(6u32.to_le_bytes());
+        data.extend(b"INV-42");
+        data.extend(beneficiary.to_bytes());
+        data.extend(2u32.to_le_bytes());
+        data.extend([7, 8]);
+
+        assert_eq!(
+            decoder.decode(&data, &[]),
+            Some(TransactionEvent::Instruction {
+                program_id: PROGRAM_ID.to_string(),
+                name: "depositFunds".to_string(),
+                args: serde_json::json!({
+                    "amount": 1_000_000,
+                    "memo": "INV-42",
+                    "config": { "beneficiary": beneficiary.to_string(), "tags": [7, 8] }
+                }),
+            })
+        );
+        assert_eq!(decoder.decode(&[0; 8], &[]), None);
+    }
+
+    /// Test to verify that events emitted through `Program data:` log lines are
+    /// attributed to the executing program and decoded.
+    #[test]
+    fn test_decode_events_from_logs() {
+        let mut registry = DecoderRegistry::with_builtins();
+        registry.register(AnchorDecoder::new(mock_idl()).unwrap());
+        let user = Pubkey::new_unique();
+
+        let mut event = hashv(&[b"event:Deposited"]).to_bytes()[..8].to_vec();
+        event.extend(user.to_bytes());
+        event.extend(500u64.to_le_bytes());
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&event);
+
+        let logs = vec![
+            format!("Program {} invoke [1]", PROGRAM_ID),
+            "Program log: Instruction: DepositFunds".to_string(),
+            format!("Program data: {}", encoded),
+            format!("Program {} success", PROGRAM_ID),
+            // The same payload outside of the program's invocation is not attributed to it
+            format!("Program data: {}", encoded),
+        ];
+
+        assert_eq!(
+            registry.decode_logs(&logs),
+            vec![TransactionEvent::AnchorEvent {
+                program_id: PROGRAM_ID.to_string(),
+                name: "Deposited".to_string(),
+                data: serde_json::json!({ "user": user.to_string(), "amount": 500 }),
+            }]
+        );
+
+        // Events emitted through self-CPI are decoded from the instruction data
+        let mut cpi_data = EVENT_IX_TAG.to_vec();
+        cpi_data.extend(&event);
+        let events = registry.decode_all(&[InstructionRecord {
+            index: 0,
+            stack_height: 2,
+            program_id: PROGRAM_ID.to_string(),
+            accounts: Vec::new(),
+            data: cpi_data,
+        }]);
+        assert_eq!(events.len(), 1);
+    }
+}
@@ -1,12 +1,27 @@
-use crate::db::InMemoryDatabase;
+use crate::amount::Direction;
+use crate::auth::token_matches;
+use crate::classify::TransactionKind;
+use crate::consistency::ConsistencyLog;
+use crate::db::{Commitment, InMemoryDatabase, Retraction, TransactionData, TransactionStatus};
+use crate::events::TransactionEvent;
+use crate::metrics::METRICS;
+use crate::webhook::{WebhookError, WebhookReceiver, SIGNATURE_HEADER};
 use chrono::{NaiveDate, TimeZone, Utc};
+use futures::stream::{self, Stream};
 use log::{error, info};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
 use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::sse::Event;
 use warp::Filter;
 use warp::Reply;
 
+/// Largest webhook body accepted, in bytes.
+const MAX_WEBHOOK_BODY: u64 = 16 * 1024 * 1024;
+
 /// Struct to define the query parameters for the API requests.
 #[derive(Debug, Deserialize)]
 pub struct TransactionQueryParams {
@@ -14,6 +29,66 @@ pub struct TransactionQueryParams {
     pub day: Option<String>, // Optional date filter in "dd/mm/yyyy" format
     pub limit: Option<usize>, // Optional limit on the number of transactions to return
     pub offset: Option<usize>, // Optional pagination offset
+    pub status: Option<TransactionStatus>, // Optional status filter ("success" or "failed")
+    pub units: Option<AmountUnits>, // Optional typed rendering of amounts ("raw" or "ui")
+    pub instruction: Option<String>, // Optional filter on the name of a decoded instruction
+    pub memo: Option<String>, // Optional filter on an exact memo
+    pub memo_prefix: Option<String>, // Optional filter on the prefix of a memo
+    pub kind: Option<TransactionKind>, // Optional filter on a classified kind, e.g. "swap"
+    pub finality: Option<Commitment>, // Optional minimum commitment, e.g. "finalized"
+}
+
+/// Struct to define the query parameters for program transaction requests.
+#[derive(Debug, Deserialize)]
+pub struct ProgramQueryParams {
+    pub limit: Option<usize>, // Optional limit on the number of transactions to return
+    pub offset: Option<usize>, // Optional pagination offset
+    pub units: Option<AmountUnits>, // Optional typed rendering of amounts ("raw" or "ui")
+}
+
+/// Struct to define the query parameters for inflation reward reports.
+#[derive(Debug, Deserialize)]
+pub struct RewardsQueryParams {
+    pub stake_accounts: String, // Comma-separated list of the stake accounts to report on
+}
+
+/// The inflation rewards credited to a set of stake accounts for one epoch.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EpochRewards {
+    pub epoch: u64,                // Epoch the rewards were earned in
+    pub total_lamports: u64,       // Sum of the rewards credited in the epoch
+    pub rewards: Vec<StakeReward>, // Reward credited to each stake account
+}
+
+/// The inflation reward credited to a single stake account.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct StakeReward {
+    pub stake_account: String,  // Stake account the reward was credited to
+    pub lamports: u64,          // Reward amount
+    pub post_balance: u64,      // Balance of the stake account after the reward
+    pub commission: Option<u8>, // Commission of the vote account when the reward was credited
+}
+
+/// An event of an account, with the transaction it was found in.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EventRecord {
+    pub signature: String, // Signature of the transaction
+    pub timestamp: u64,    // Unix timestamp of the transaction
+    #[serde(flatten)]
+    pub event: TransactionEvent, // The event
+}
+
+/// How amounts are rendered in API responses when requested with `units`. Without it,
+/// `amount` is a signed integer in the asset's smallest unit, negative for debits, and
+/// the asset is served alongside it as `asset`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountUnits {
+    /// Amounts are typed objects with the raw amount in the asset's smallest unit
+    /// (lamports for SOL), as a string.
+    Raw,
+    /// Amounts additionally carry a signed decimal string in UI units as `ui_amount`.
+    Ui,
 }
 
 /// Creates the API with enhanced querying capabilities.
@@ -21,19 +96,190 @@ pub struct TransactionQueryParams {
 /// # Arguments
 ///
 /// * `db` - A thread-safe reference to an `InMemoryDatabase`.
+/// * `consistency` - The log of cross-endpoint verifications, served to admins.
+/// * `admin_token` - The token admins must send in the `Authorization` header, `None`
+///   to disable the admin endpoints.
+/// * `webhook` - The receiver of the transactions pushed by a provider, `None` to
+///   disable the webhook.
 ///
 /// # Returns
 ///
 /// A warp filter that handles incoming HTTP requests to fetch transactions.
 pub fn create_api(
     db: Arc<InMemoryDatabase>,
+    consistency: Arc<ConsistencyLog>,
+    admin_token: Option<String>,
+    webhook: Option<Arc<WebhookReceiver>>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let db_filter = warp::any().map(move || db.clone());
 
-    warp::path("transactions")
+    let transactions = warp::path("transactions")
         .and(warp::query::<TransactionQueryParams>()) // Parse query parameters
+        .and(db_filter.clone())
+        .and_then(handle_get_transactions);
+
+    let program_transactions = warp::path!("programs" / String / "transactions")
+        .and(warp::query::<ProgramQueryParams>())
+        .and(db_filter.clone())
+        .and_then(handle_get_program_transactions);
+
+    let rewards = warp::path("rewards")
+        .and(warp::query::<RewardsQueryParams>())
+        .and(db_filter.clone())
+        .and_then(handle_get_rewards);
+
+    let swaps = warp::path!("accounts" / String / "swaps")
+        .and(warp::query::<ProgramQueryParams>())
+        .and(db_filter.clone())
+        .and_then(handle_get_swaps);
+
+    let nfts = warp::path!("accounts" / String / "nfts")
+        .and(warp::query::<ProgramQueryParams>())
+        .and(db_filter.clone())
+        .and_then(handle_get_nfts);
+
+    let retractions = warp::path("retractions")
+        .and(warp::get())
         .and(db_filter)
-        .and_then(handle_get_transactions)
+        .map(|db: Arc<InMemoryDatabase>| {
+            let events = retraction_events(db.subscribe_retractions());
+            warp::sse::reply(warp::sse::keep_alive().stream(events))
+        });
+
+    let metrics = warp::path("metrics")
+        .and(warp::get())
+        .map(|| warp::reply::json(&METRICS.snapshot()));
+
+    let admin_consistency = warp::path!("admin" / "consistency")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::any().map(move || admin_token.clone()))
+        .and(warp::any().map(move || consistency.clone()))
+        .and_then(handle_admin_consistency);
+
+    let ingest_webhook = warp::path!("ingest" / "webhook")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+        .and(warp::body::content_length_limit(MAX_WEBHOOK_BODY))
+        .and(warp::body::bytes())
+        .and(warp::any().map(move || webhook.clone()))
+        .and_then(handle_webhook);
+
+    transactions
+        .or(program_transactions)
+        .or(rewards)
+        .or(swaps)
+        .or(nfts)
+        .or(retractions)
+        .or(metrics)
+        .or(admin_consistency)
+        .or(ingest_webhook)
+}
+
+/// Handles the transactions pushed by a provider to the webhook.
+///
+/// # Arguments
+///
+/// * `authorization` - The `Authorization` header of the request, if any.
+/// * `signature` - The HMAC signature header of the request, if any.
+/// * `body` - The raw body of the request.
+/// * `webhook` - The receiver of the pushed transactions, `None` if the webhook is disabled.
+///
+/// # Returns
+///
+/// A JSON response with the counts of the delivery, or an error message if it is
+/// rejected. The route is not found while the webhook is disabled.
+async fn handle_webhook(
+    authorization: Option<String>,
+    signature: Option<String>,
+    body: Bytes,
+    webhook: Option<Arc<WebhookReceiver>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let Some(webhook) = webhook else {
+        return Err(warp::reject::not_found());
+    };
+
+    match webhook
+        .receive(authorization.as_deref(), signature.as_deref(), &body)
+        .await
+    {
+        Ok(report) => Ok(warp::reply::json(&report).into_response()),
+        Err(err) => {
+            error!("Rejected webhook delivery: {}", err);
+            let status = match err {
+                WebhookError::Unauthorized => StatusCode::UNAUTHORIZED,
+                WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            };
+            let error_message = warp::reply::json(&serde_json::json!({
+                "error": err.to_string()
+            }));
+            Ok(warp::reply::with_status(error_message, status).into_response())
+        }
+    }
+}
+
+/// Handles admin requests for the results of the cross-endpoint verifications.
+///
+/// # Arguments
+///
+/// * `authorization` - The `Authorization` header of the request, if any.
+/// * `admin_token` - The token admins must send, `None` if the admin endpoints are disabled.
+/// * `consistency` - The log of cross-endpoint verifications.
+///
+/// # Returns
+///
+/// A JSON response with the summary of the verifications, or an error message if the
+/// request is not authorized. The route is not found while no admin token is set.
+async fn handle_admin_consistency(
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    consistency: Arc<ConsistencyLog>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let Some(admin_token) = admin_token else {
+        return Err(warp::reject::not_found());
+    };
+
+    if !token_matches(&admin_token, authorization.as_deref()) {
+        let error_message = warp::reply::json(&serde_json::json!({
+            "error": "Unauthorized"
+        }));
+        return Ok(
+            warp::reply::with_status(error_message, StatusCode::UNAUTHORIZED).into_response(),
+        );
+    }
+
+    Ok(warp::reply::json(&consistency.summary()).into_response())
+}
+
+/// Turns the retractions received from the database into server-sent events.
+///
+/// # Arguments
+///
+/// * `receiver` - A subscription to the retractions of the database.
+///
+/// # Returns
+///
+/// A stream of `retraction` events carrying each retraction as JSON, which skips the
+/// retractions missed by a subscriber lagging behind and ends when the database is
+/// dropped.
+fn retraction_events(
+    receiver: broadcast::Receiver<Retraction>,
+) -> impl Stream<Item = Result<Event, serde_json::Error>> + Send + 'static {
+    stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(retraction) => {
+                    let event = Event::default().event("retraction").json_data(&retraction);
+                    return Some((event, receiver));
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    error!("Retraction subscriber missed {} retractions", missed)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
 }
 
 /// Handles incoming API requests to fetch transactions.
@@ -76,6 +322,68 @@ async fn handle_get_transactions(
         transactions
     };
 
+    // Filter transactions by execution status if the `status` parameter is provided
+    let filtered_transactions: Vec<_> = match params.status {
+        Some(status) => filtered_transactions
+            .into_iter()
+            .filter(|tx| tx.status == status)
+            .collect(),
+        None => filtered_transactions,
+    };
+
+    // Keep only transactions carrying the exact memo given by the `memo` parameter
+    let filtered_transactions: Vec<_> = match params.memo {
+        Some(ref memo) => filtered_transactions
+            .into_iter()
+            .filter(|tx| tx.memos.contains(memo))
+            .collect(),
+        None => filtered_transactions,
+    };
+
+    // Keep only transactions with a memo starting with the `memo_prefix` parameter
+    let filtered_transactions: Vec<_> = match params.memo_prefix {
+        Some(ref prefix) => filtered_transactions
+            .into_iter()
+            .filter(|tx| {
+                tx.memos
+                    .iter()
+                    .any(|memo| memo.starts_with(prefix.as_str()))
+            })
+            .collect(),
+        None => filtered_transactions,
+    };
+
+    // Keep only transactions classified with the given kind
+    let filtered_transactions: Vec<_> = match params.kind {
+        Some(kind) => filtered_transactions
+            .into_iter()
+            .filter(|tx| tx.kinds.contains(&kind))
+            .collect(),
+        None => filtered_transactions,
+    };
+
+    // Keep only transactions at least as final as the given commitment
+    let filtered_transactions: Vec<_> = match params.finality {
+        Some(finality) => filtered_transactions
+            .into_iter()
+            .filter(|tx| tx.commitment >= finality)
+            .collect(),
+        None => filtered_transactions,
+    };
+
+    // Keep only transactions containing a decoded instruction with the given name
+    let filtered_transactions: Vec<_> = match params.instruction {
+        Some(ref instruction) => filtered_transactions
+            .into_iter()
+            .filter(|tx| {
+                tx.events
+                    .iter()
+                    .any(|event| event.instruction_name() == Some(instruction.as_str()))
+            })
+            .collect(),
+        None => filtered_transactions,
+    };
+
     // Apply pagination based on `limit` and `offset` parameters
     let total = filtered_transactions.len();
     let limit = params.limit.unwrap_or(5); // Default limit is 5
@@ -93,7 +401,289 @@ async fn handle_get_transactions(
         params.pub_key
     );
 
-    Ok(warp::reply::json(&limited_transactions).into_response())
+    Ok(render_transactions(&limited_transactions, params.units))
+}
+
+/// Renders transactions with their amounts in the requested units.
+///
+/// # Arguments
+///
+/// * `transactions` - The transactions to render.
+/// * `units` - The requested rendering of amounts, or `None` for the legacy signed integer.
+///
+/// # Returns
+///
+/// A JSON response with the rendered transactions.
+fn render_transactions(
+    transactions: &[TransactionData],
+    units: Option<AmountUnits>,
+) -> warp::reply::Response {
+    match units {
+        Some(AmountUnits::Raw) => warp::reply::json(&transactions).into_response(),
+        Some(AmountUnits::Ui) => {
+            let rendered = transactions
+                .iter()
+                .map(render_ui_amount)
+                .collect::<Vec<_>>();
+            warp::reply::json(&rendered).into_response()
+        }
+        None => {
+            let rendered = transactions
+                .iter()
+                .map(render_legacy_amount)
+                .collect::<Vec<_>>();
+            warp::reply::json(&rendered).into_response()
+        }
+    }
+}
+
+/// Renders a transaction with its amount as a signed integer in the asset's smallest
+/// unit, negative for debits, and the asset as a separate `asset` field.
+///
+/// Debits whose magnitude does not fit in an `i64` are rendered as a signed decimal string.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to render.
+///
+/// # Returns
+///
+/// The JSON representation of the transaction.
+fn render_legacy_amount(transaction: &TransactionData) -> serde_json::Value {
+    let amount = &transaction.amount;
+    let mut value = serde_json::to_value(transaction).unwrap_or_default();
+    value["amount"] = match amount.direction {
+        Direction::Credit => serde_json::Value::from(amount.raw),
+        Direction::Debit => i64::try_from(amount.raw)
+            .map(|raw| serde_json::Value::from(-raw))
+            .unwrap_or_else(|_| serde_json::Value::String(format!("-{}", amount.raw))),
+    };
+    value["asset"] = serde_json::to_value(&amount.asset).unwrap_or_default();
+    value
+}
+
+/// Handles incoming API requests to fetch the transactions invoking a watched program.
+///
+/// # Arguments
+///
+/// * `program_id` - The program ID taken from the request path.
+/// * `params` - The pagination parameters provided by the client.
+/// * `db` - A thread-safe reference to an `InMemoryDatabase`.
+///
+/// # Returns
+///
+/// A JSON response containing the program's transactions, each with the details of
+/// its invocations.
+async fn handle_get_program_transactions(
+    program_id: String,
+    params: ProgramQueryParams,
+    db: Arc<InMemoryDatabase>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Received request for program: {}", program_id);
+
+    // Program transactions are indexed under the program ID
+    let transactions = db.get_transactions(&program_id).await;
+
+    let limit = params.limit.unwrap_or(5); // Default limit is 5
+    let offset = params.offset.unwrap_or(0); // Default offset is 0
+    let limited_transactions = transactions
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .collect::<Vec<_>>();
+
+    info!(
+        "Returning {} transactions for program: {}",
+        limited_transactions.len(),
+        program_id
+    );
+
+    Ok(render_transactions(&limited_transactions, params.units))
+}
+
+/// Handles requests for the per-epoch inflation rewards report of a set of stake accounts.
+///
+/// # Arguments
+///
+/// * `params` - The query parameters listing the stake accounts.
+/// * `db` - A thread-safe reference to an `InMemoryDatabase`.
+///
+/// # Returns
+///
+/// A JSON response containing the rewards grouped by epoch, oldest epoch first.
+async fn handle_get_rewards(
+    params: RewardsQueryParams,
+    db: Arc<InMemoryDatabase>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut epochs: BTreeMap<u64, EpochRewards> = BTreeMap::new();
+
+    for stake_account in params
+        .stake_accounts
+        .split(',')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+    {
+        info!(
+            "Received rewards request for stake account: {}",
+            stake_account
+        );
+
+        // Rewards are stored as synthetic transactions indexed under the stake account
+        for transaction in db.get_transactions(stake_account).await {
+            for event in transaction.events {
+                if let TransactionEvent::InflationReward {
+                    stake_account,
+                    epoch,
+                    lamports,
+                    post_balance,
+                    commission,
+                } = event
+                {
+                    let entry = epochs.entry(epoch).or_insert_with(|| EpochRewards {
+                        epoch,
+                        total_lamports: 0,
+                        rewards: Vec::new(),
+                    });
+                    entry.total_lamports += lamports;
+                    entry.rewards.push(StakeReward {
+                        stake_account,
+                        lamports,
+                        post_balance,
+                        commission,
+                    });
+                }
+            }
+        }
+    }
+
+    let report: Vec<EpochRewards> = epochs.into_values().collect();
+    info!("Returning rewards for {} epochs", report.len());
+
+    Ok(warp::reply::json(&report))
+}
+
+/// Handles requests for the swaps made by an account.
+///
+/// # Arguments
+///
+/// * `pub_key` - The public key of the account, which must be the signer of the swaps.
+/// * `params` - The pagination parameters provided by the client.
+/// * `db` - A thread-safe reference to an `InMemoryDatabase`.
+///
+/// # Returns
+///
+/// A JSON response containing the normalized swaps of the account.
+async fn handle_get_swaps(
+    pub_key: String,
+    params: ProgramQueryParams,
+    db: Arc<InMemoryDatabase>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Received swaps request for public key: {}", pub_key);
+
+    let swaps = account_events(
+        &db,
+        &pub_key,
+        &params,
+        |event| matches!(event, TransactionEvent::Swap { signer, .. } if *signer == pub_key),
+    )
+    .await;
+
+    info!("Returning {} swaps", swaps.len());
+
+    Ok(warp::reply::json(&swaps))
+}
+
+/// Handles requests for the NFTs minted to, sent or received by an account.
+///
+/// # Arguments
+///
+/// * `pub_key` - The public key of the account.
+/// * `params` - The pagination parameters provided by the client.
+/// * `db` - A thread-safe reference to an `InMemoryDatabase`.
+///
+/// # Returns
+///
+/// A JSON response containing the NFT mints and transfers of the account.
+async fn handle_get_nfts(
+    pub_key: String,
+    params: ProgramQueryParams,
+    db: Arc<InMemoryDatabase>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Received NFTs request for public key: {}", pub_key);
+
+    let nfts = account_events(&db, &pub_key, &params, |event| match event {
+        TransactionEvent::NftMint { owner, .. } => *owner == pub_key,
+        TransactionEvent::NftTransfer { from, to, .. } => *from == pub_key || *to == pub_key,
+        _ => false,
+    })
+    .await;
+
+    info!("Returning {} NFT events", nfts.len());
+
+    Ok(warp::reply::json(&nfts))
+}
+
+/// Collects the events matching a predicate from the transactions of an account.
+///
+/// # Arguments
+///
+/// * `db` - A thread-safe reference to an `InMemoryDatabase`.
+/// * `pub_key` - The public key the transactions are indexed under.
+/// * `params` - The pagination parameters provided by the client.
+/// * `predicate` - Selects the events to return.
+///
+/// # Returns
+///
+/// A page of `EventRecord`, in the order the transactions were stored.
+async fn account_events(
+    db: &InMemoryDatabase,
+    pub_key: &str,
+    params: &ProgramQueryParams,
+    predicate: impl Fn(&TransactionEvent) -> bool,
+) -> Vec<EventRecord> {
+    db.get_transactions(pub_key)
+        .await
+        .into_iter()
+        .flat_map(|transaction| {
+            let TransactionData {
+                signature,
+                timestamp,
+                events,
+                ..
+            } = transaction;
+            events
+                .into_iter()
+                .filter(|event| predicate(event))
+                .map(move |event| EventRecord {
+                    signature: signature.clone(),
+                    timestamp,
+                    event,
+                })
+                .collect::<Vec<_>>()
+        })
+        .skip(params.offset.unwrap_or(0))
+        .take(params.limit.unwrap_or(5))
+        .collect()
+}
+
+/// Serializes a transaction with its amount extended by a `ui_amount` decimal string.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to render.
+///
+/// # Returns
+///
+/// The JSON representation of the transaction.
+fn render_ui_amount(transaction: &TransactionData) -> serde_json::Value {
+    let mut value = serde_json::to_value(transaction).unwrap_or_default();
+    if let Some(amount) = value.get_mut("amount").and_then(|a| a.as_object_mut()) {
+        amount.insert(
+            "ui_amount".to_string(),
+            serde_json::Value::String(transaction.amount.to_ui_string()),
+        );
+    }
+    value
 }
 
 /// Parses a date string in "dd/mm/yyyy" format into a `NaiveDate`.
@@ -131,31 +721,37 @@ fn is_same_day(timestamp: u64, date: NaiveDate) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::{InMemoryDatabase, TransactionData};
+    use crate::aggregator::{Aggregator, AggregatorConfig};
+    use crate::amount::{Amount, Direction};
+    use crate::consistency::Mismatch;
+    use crate::db::{InMemoryDatabase, ProgramInvocation, RetractionReason};
+    use crate::mock_rpc;
+    use crate::webhook::WebhookAuth;
     use warp::test::request;
 
     /// Test to verify that the API correctly handles fetching transactions with mock data.
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn test_api_get_transactions_with_mock_data() {
-        let db = Arc::new(InMemoryDatabase::new(
-            "mock_test_transactions.txt".to_string(),
-        ));
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
 
         // Mock some transaction data
         let transaction1 = TransactionData {
             signature: "mock_sig_1".to_string(),
             sender: "mock_sender_1".to_string(),
             receiver: "mock_receiver_1".to_string(),
-            amount: 1000,
+            amount: Amount::sol(1000, Direction::Credit),
             timestamp: 1628500000,
+            ..Default::default()
         };
 
         let transaction2 = TransactionData {
             signature: "mock_sig_2".to_string(),
             sender: "mock_sender_2".to_string(),
             receiver: "mock_receiver_2".to_string(),
-            amount: 2000,
+            amount: Amount::sol(2000, Direction::Credit),
             timestamp: 1628501000,
+            ..Default::default()
         };
 
         // Add transactions to the in-memory database
@@ -165,7 +761,7 @@ mod tests {
             .await;
 
         // Create the API with the mocked database
-        let api = create_api(db.clone());
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
 
         // Query the API for the first transaction
         let response1 = request()
@@ -189,4 +785,630 @@ mod tests {
         assert_eq!(body2.len(), 1);
         assert_eq!(body2[0], transaction2);
     }
+
+    /// Test to verify that the `status` query parameter filters out transactions with
+    /// a different execution status.
+    #[tokio::test]
+    async fn test_api_filters_transactions_by_status() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let succeeded = TransactionData {
+            signature: "status_sig_ok".to_string(),
+            sender: "status_sender".to_string(),
+            receiver: "status_receiver".to_string(),
+            amount: Amount::sol(1000, Direction::Credit),
+            timestamp: 1628500000,
+            ..Default::default()
+        };
+        let failed = TransactionData {
+            signature: "status_sig_failed".to_string(),
+            status: TransactionStatus::Failed,
+            error: Some("Error processing Instruction 0: insufficient funds".to_string()),
+            ..succeeded.clone()
+        };
+
+        db.add_transaction("status_sender", succeeded).await;
+        db.add_transaction("status_sender", failed.clone()).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request()
+            .path("/transactions?pub_key=status_sender&status=failed")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, vec![failed]);
+    }
+
+    /// Test to verify that `units=ui` renders amounts as signed decimal strings while
+    /// keeping the raw amount.
+    #[tokio::test]
+    async fn test_api_renders_ui_amounts() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let transaction = TransactionData {
+            signature: "units_sig".to_string(),
+            sender: "units_sender".to_string(),
+            receiver: "units_receiver".to_string(),
+            amount: Amount::sol(1_500_000_000, Direction::Debit),
+            timestamp: 1628500000,
+            ..Default::default()
+        };
+        db.add_transaction("units_sender", transaction).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request()
+            .path("/transactions?pub_key=units_sender&units=ui")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body[0]["amount"]["ui_amount"], "-1.5");
+        assert_eq!(body[0]["amount"]["raw"], "1500000000");
+        assert_eq!(body[0]["amount"]["asset"], "SOL");
+    }
+
+    /// Test to verify that without `units`, amounts are signed integers in the asset's
+    /// smallest unit with the asset alongside, and that `units=raw` serves the typed amount.
+    #[tokio::test]
+    async fn test_api_renders_signed_amounts_by_default() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let transaction = TransactionData {
+            signature: "legacy_sig".to_string(),
+            sender: "legacy_sender".to_string(),
+            amount: Amount::sol(1_500_000_000, Direction::Debit),
+            timestamp: 1628500000,
+            ..Default::default()
+        };
+        db.add_transaction("legacy_sender", transaction).await;
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+
+        let response = request()
+            .path("/transactions?pub_key=legacy_sender")
+            .reply(&api)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body[0]["amount"], -1_500_000_000i64);
+        assert_eq!(body[0]["asset"], "SOL");
+
+        let response = request()
+            .path("/transactions?pub_key=legacy_sender&units=raw")
+            .reply(&api)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body[0]["amount"]["raw"], "1500000000");
+        assert_eq!(body[0]["amount"]["direction"], "debit");
+    }
+
+    /// Test to verify that transactions indexed under a program ID are served with
+    /// their invocations by the program route.
+    #[tokio::test]
+    async fn test_api_get_program_transactions() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let transaction = TransactionData {
+            signature: "program_sig".to_string(),
+            sender: "program_user".to_string(),
+            timestamp: 1628500000,
+            invocations: vec![ProgramInvocation {
+                program_id: "my_program".to_string(),
+                instruction_index: 0,
+                inner: false,
+                instruction_count: 2,
+                accounts: vec!["program_user".to_string(), "vault".to_string()],
+            }],
+            ..Default::default()
+        };
+        db.add_transaction("my_program", transaction.clone()).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request()
+            .path("/programs/my_program/transactions?units=raw")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, vec![transaction]);
+
+        let response = request()
+            .path("/programs/other_program/transactions")
+            .reply(&api)
+            .await;
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert!(body.is_empty());
+    }
+
+    /// Test to verify that the `instruction` query parameter keeps only transactions
+    /// containing a decoded instruction with that name.
+    #[tokio::test]
+    async fn test_api_filters_transactions_by_instruction() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let deposit = TransactionData {
+            signature: "deposit_sig".to_string(),
+            sender: "instruction_sender".to_string(),
+            timestamp: 1628500000,
+            events: vec![TransactionEvent::Instruction {
+                program_id: "vault_program".to_string(),
+                name: "depositFunds".to_string(),
+                args: serde_json::json!({ "amount": 1000 }),
+            }],
+            ..Default::default()
+        };
+        let transfer = TransactionData {
+            signature: "transfer_sig".to_string(),
+            events: vec![TransactionEvent::SolTransfer {
+                from: "instruction_sender".to_string(),
+                to: "receiver".to_string(),
+                lamports: 1000,
+            }],
+            ..deposit.clone()
+        };
+        db.add_transaction("instruction_sender", deposit.clone())
+            .await;
+        db.add_transaction("instruction_sender", transfer).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request()
+            .path("/transactions?pub_key=instruction_sender&instruction=depositFunds&units=raw")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, vec![deposit]);
+    }
+
+    /// Test to verify that the `memo` and `memo_prefix` query parameters match
+    /// transactions by exact memo and by memo prefix.
+    #[tokio::test]
+    async fn test_api_filters_transactions_by_memo() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let invoice_42 = TransactionData {
+            signature: "memo_sig_42".to_string(),
+            sender: "memo_sender".to_string(),
+            timestamp: 1628500000,
+            memos: vec!["INV-42".to_string()],
+            ..Default::default()
+        };
+        let invoice_420 = TransactionData {
+            signature: "memo_sig_420".to_string(),
+            memos: vec!["INV-420".to_string()],
+            ..invoice_42.clone()
+        };
+        db.add_transaction("memo_sender", invoice_42.clone()).await;
+        db.add_transaction("memo_sender", invoice_420.clone()).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+
+        let response = request()
+            .path("/transactions?pub_key=memo_sender&memo=INV-42&units=raw")
+            .reply(&api)
+            .await;
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, vec![invoice_42.clone()]);
+
+        let response = request()
+            .path("/transactions?pub_key=memo_sender&memo_prefix=INV-4&units=raw")
+            .reply(&api)
+            .await;
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, vec![invoice_42, invoice_420]);
+    }
+
+    /// Test to verify that the `kind` query parameter keeps only transactions tagged
+    /// with that kind.
+    #[tokio::test]
+    async fn test_api_filters_transactions_by_kind() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let swap = TransactionData {
+            signature: "kind_sig_swap".to_string(),
+            sender: "kind_sender".to_string(),
+            kinds: vec![TransactionKind::Swap, TransactionKind::ProgramCall],
+            ..Default::default()
+        };
+        let transfer = TransactionData {
+            signature: "kind_sig_transfer".to_string(),
+            sender: "kind_sender".to_string(),
+            kinds: vec![TransactionKind::SolTransfer],
+            ..Default::default()
+        };
+        db.add_transaction("kind_sender", swap.clone()).await;
+        db.add_transaction("kind_sender", transfer).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request()
+            .path("/transactions?pub_key=kind_sender&kind=swap&units=raw")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, vec![swap]);
+    }
+
+    /// Test to verify that the `finality` query parameter keeps only transactions at
+    /// least as final as the given commitment.
+    #[tokio::test]
+    async fn test_api_filters_transactions_by_finality() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let processed = TransactionData {
+            signature: "finality_sig_processed".to_string(),
+            sender: "finality_sender".to_string(),
+            commitment: Commitment::Processed,
+            ..Default::default()
+        };
+        let confirmed = TransactionData {
+            signature: "finality_sig_confirmed".to_string(),
+            commitment: Commitment::Confirmed,
+            ..processed.clone()
+        };
+        let finalized = TransactionData {
+            signature: "finality_sig_finalized".to_string(),
+            commitment: Commitment::Finalized,
+            ..processed.clone()
+        };
+        for transaction in [&processed, &confirmed, &finalized] {
+            db.add_transaction("finality_sender", transaction.clone())
+                .await;
+        }
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request()
+            .path("/transactions?pub_key=finality_sender&finality=confirmed&units=raw")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<TransactionData> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, vec![confirmed, finalized]);
+    }
+
+    /// Test to verify that inflation rewards of several stake accounts are grouped by epoch.
+    #[tokio::test]
+    async fn test_api_rewards_report() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        for (stake_account, epoch, lamports) in [
+            ("stake_a", 500, 1_000),
+            ("stake_b", 500, 2_000),
+            ("stake_a", 499, 900),
+        ] {
+            let transaction = TransactionData {
+                signature: format!("reward:{}:{}", epoch, stake_account),
+                receiver: stake_account.to_string(),
+                amount: Amount::sol(lamports, Direction::Credit),
+                events: vec![TransactionEvent::InflationReward {
+                    stake_account: stake_account.to_string(),
+                    epoch,
+                    lamports,
+                    post_balance: 10_000_000 + lamports,
+                    commission: Some(5),
+                }],
+                ..Default::default()
+            };
+            db.add_transaction(stake_account, transaction).await;
+        }
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request()
+            .path("/rewards?stake_accounts=stake_a,stake_b")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<EpochRewards> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body.len(), 2);
+        assert_eq!(body[0].epoch, 499);
+        assert_eq!(body[0].total_lamports, 900);
+        assert_eq!(body[1].epoch, 500);
+        assert_eq!(body[1].total_lamports, 3_000);
+        assert_eq!(body[1].rewards.len(), 2);
+    }
+
+    /// Test to verify that the swaps route only returns swaps signed by the account.
+    #[tokio::test]
+    async fn test_api_get_swaps() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let swap = |signer: &str| TransactionEvent::Swap {
+            signer: signer.to_string(),
+            venue: "orca_whirlpool".to_string(),
+            program_id: "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc".to_string(),
+            input_mint: "SOL".to_string(),
+            input_amount: 1_000_000_000,
+            input_decimals: 9,
+            output_mint: "usdc_mint".to_string(),
+            output_amount: 150_000_000,
+            output_decimals: 6,
+        };
+        for (signature, signer) in [("swap_sig", "wallet"), ("other_sig", "someone_else")] {
+            let transaction = TransactionData {
+                signature: signature.to_string(),
+                sender: signer.to_string(),
+                timestamp: 1700000000,
+                events: vec![swap(signer)],
+                ..Default::default()
+            };
+            db.add_transaction("wallet", transaction).await;
+        }
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request().path("/accounts/wallet/swaps").reply(&api).await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<EventRecord> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            vec![EventRecord {
+                signature: "swap_sig".to_string(),
+                timestamp: 1700000000,
+                event: swap("wallet"),
+            }]
+        );
+    }
+
+    /// Test to verify that the NFTs route returns the NFTs received and sent by the account.
+    #[tokio::test]
+    async fn test_api_get_nfts() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+
+        let received = TransactionEvent::NftTransfer {
+            mint: "nft_mint".to_string(),
+            from: "seller".to_string(),
+            to: "wallet".to_string(),
+            name: Some("Mad Lad #42".to_string()),
+            collection: Some("collection_mint".to_string()),
+        };
+        let transaction = TransactionData {
+            signature: "nft_sig".to_string(),
+            timestamp: 1700000000,
+            events: vec![
+                TransactionEvent::SolTransfer {
+                    from: "wallet".to_string(),
+                    to: "seller".to_string(),
+                    lamports: 1_000_000_000,
+                },
+                received.clone(),
+            ],
+            ..Default::default()
+        };
+        db.add_transaction("wallet", transaction).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = request().path("/accounts/wallet/nfts").reply(&api).await;
+
+        assert_eq!(response.status(), 200);
+        let body: Vec<EventRecord> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            vec![EventRecord {
+                signature: "nft_sig".to_string(),
+                timestamp: 1700000000,
+                event: received,
+            }]
+        );
+    }
+
+    /// Test to verify that the admin endpoint reports the recorded verifications.
+    #[tokio::test]
+    async fn test_api_reports_consistency() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let consistency = Arc::new(ConsistencyLog::default());
+        consistency.record("http://primary", vec![]);
+        consistency.record(
+            "http://primary",
+            vec![Mismatch {
+                signature: "stale_sig".to_string(),
+                endpoint: "http://primary".to_string(),
+                reference_endpoint: "http://secondary".to_string(),
+                field: "block_time".to_string(),
+                value: serde_json::json!(1700000000),
+                reference_value: serde_json::Value::Null,
+            }],
+        );
+
+        let api = create_api(db, consistency, Some("admin-token".to_string()), None);
+        let response = request()
+            .path("/admin/consistency")
+            .header("authorization", "admin-token")
+            .reply(&api)
+            .await;
+
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["checked"], 2);
+        assert_eq!(body["mismatched"], 1);
+        assert_eq!(body["endpoints"]["http://primary"]["mismatched"], 1);
+        assert_eq!(body["recent_mismatches"][0]["signature"], "stale_sig");
+    }
+
+    /// Test to verify that the admin endpoint rejects requests without the admin token,
+    /// and is not served at all while no admin token is set.
+    #[tokio::test]
+    async fn test_api_requires_admin_token() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let consistency = Arc::new(ConsistencyLog::default());
+
+        let api = create_api(
+            db.clone(),
+            consistency.clone(),
+            Some("admin-token".to_string()),
+            None,
+        );
+        let response = request().path("/admin/consistency").reply(&api).await;
+        assert_eq!(response.status(), 401);
+        let response = request()
+            .path("/admin/consistency")
+            .header("authorization", "wrong-token")
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), 401);
+
+        let api = create_api(db, consistency, None, None);
+        let response = request()
+            .path("/admin/consistency")
+            .header("authorization", "admin-token")
+            .reply(&api)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
+
+    /// Test to verify that subscribers of the retraction stream receive each retracted
+    /// transaction as a server-sent event.
+    #[tokio::test]
+    async fn test_api_streams_retractions() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let transaction = TransactionData {
+            signature: "rolled_back_sig".to_string(),
+            slot: 1500,
+            commitment: Commitment::Confirmed,
+            ..Default::default()
+        };
+        db.add_transaction("account", transaction).await;
+
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let (addr, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        // The subscription is made before the response headers are sent
+        let mut response = reqwest::get(format!("http://{}/retractions", addr))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        db.retract_transaction("rolled_back_sig", RetractionReason::RolledBack)
+            .await;
+
+        let chunk = response.chunk().await.unwrap().unwrap();
+        let event = String::from_utf8(chunk.to_vec()).unwrap();
+        let data = event
+            .lines()
+            .find_map(|line| line.strip_prefix("data:"))
+            .unwrap();
+        let retraction: Retraction = serde_json::from_str(data).unwrap();
+        assert!(event.starts_with("event:retraction"));
+        assert_eq!(retraction.pub_key, "account");
+        assert_eq!(retraction.signature, "rolled_back_sig");
+        assert_eq!(retraction.slot, 1500);
+        assert_eq!(retraction.reason, RetractionReason::RolledBack);
+    }
+
+    /// Test to verify that the webhook stores the raw and enhanced transactions pushed
+    /// for the watched addresses once, counts those already polled as duplicates, and
+    /// rejects malformed deliveries, deliveries without credentials, or any while
+    /// disabled.
+    #[tokio::test]
+    async fn test_api_ingests_webhook_transactions() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let polled = TransactionData {
+            signature: "polled_sig".to_string(),
+            ..Default::default()
+        };
+        db.add_transaction("watched", polled).await;
+
+        // No RPC call is needed, so the endpoint is never reached
+        let aggregator = Arc::new(Aggregator::new(
+            &["http://127.0.0.1:1".to_string()],
+            db.clone(),
+            AggregatorConfig::default(),
+        ));
+        let receiver = Arc::new(WebhookReceiver::new(
+            WebhookAuth {
+                auth_token: Some("token".to_string()),
+                hmac_secret: None,
+            },
+            aggregator,
+            vec!["watched".to_string()],
+        ));
+        let api = create_api(
+            db.clone(),
+            Arc::new(ConsistencyLog::default()),
+            None,
+            Some(receiver),
+        );
+        let payload = serde_json::json!([
+            mock_rpc::transaction("pushed_sig", "watched", "receiver"),
+            mock_rpc::transaction("polled_sig", "watched", "receiver"),
+            mock_rpc::transaction("other_sig", "sender", "receiver"),
+            {
+                "signature": "enhanced_sig",
+                "slot": 1500,
+                "timestamp": 1700000000,
+                "type": "TRANSFER",
+                "feePayer": "sender",
+                "nativeTransfers": [
+                    { "fromUserAccount": "sender", "toUserAccount": "watched", "amount": 250000 }
+                ],
+                "tokenTransfers": []
+            }
+        ]);
+        let deliver = |authorization: &str, payload: &serde_json::Value| {
+            request()
+                .method("POST")
+                .path("/ingest/webhook")
+                .header("authorization", authorization)
+                .json(payload)
+        };
+
+        let response = deliver("wrong", &payload).reply(&api).await;
+        assert_eq!(response.status(), 401);
+
+        let response = deliver("token", &payload).reply(&api).await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "received": 4, "stored": 2, "duplicates": 1 })
+        );
+
+        // A redelivery stores nothing new
+        let response = deliver("token", &payload).reply(&api).await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            (body["stored"].as_u64(), body["duplicates"].as_u64()),
+            (Some(0), Some(3))
+        );
+
+        // An entry in neither format rejects the whole delivery
+        let malformed = serde_json::json!([
+            mock_rpc::transaction("unseen_sig", "watched", "receiver"),
+            { "type": "TRANSFER", "signature": "truncated_sig" }
+        ]);
+        let response = deliver("token", &malformed).reply(&api).await;
+        assert_eq!(response.status(), 400);
+
+        let stored = db.get_transactions("watched").await;
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[1].signature, "pushed_sig");
+        assert_eq!(stored[1].endpoint.as_deref(), Some("webhook"));
+        assert_eq!(stored[1].commitment, Commitment::Confirmed);
+        assert_eq!(stored[2].signature, "enhanced_sig");
+        assert_eq!(stored[2].amount, Amount::sol(250000, Direction::Credit));
+
+        // Without credentials configured, the route does not exist
+        let api = create_api(db.clone(), Arc::new(ConsistencyLog::default()), None, None);
+        let response = deliver("token", &payload).reply(&api).await;
+        assert_eq!(response.status(), 404);
+    }
 }
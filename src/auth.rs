@@ -0,0 +1,34 @@
+/// Checks the `Authorization` header of a request against the expected token.
+///
+/// # Arguments
+///
+/// * `token` - The token the header must hold.
+/// * `authorization` - The `Authorization` header of the request, if any.
+///
+/// # Returns
+///
+/// `true` if the header holds exactly the token.
+pub fn token_matches(token: &str, authorization: Option<&str>) -> bool {
+    authorization
+        .is_some_and(|authorization| constant_time_eq(authorization.as_bytes(), token.as_bytes()))
+}
+
+/// Compares two byte strings in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test to verify that only a header holding exactly the token matches.
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", Some("secret")));
+        assert!(!token_matches("secret", Some("secreT")));
+        assert!(!token_matches("secret", Some("secret2")));
+        assert!(!token_matches("secret", Some("")));
+        assert!(!token_matches("secret", None));
+    }
+}
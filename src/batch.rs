@@ -0,0 +1,272 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::RpcClientConfig;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use thiserror::Error;
+use tokio::time::Duration;
+
+/// Errors that can occur while sending a JSON-RPC batch or reading one of its results.
+#[derive(Debug, Error)]
+pub enum BatchError {
+    /// The HTTP request carrying the batch failed.
+    #[error("Batch request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The node answered the batch with an unsuccessful HTTP status.
+    #[error("Batch request failed with HTTP status {status}")]
+    Status {
+        status: u16,                   // HTTP status code, e.g. 429 when rate limited
+        retry_after: Option<Duration>, // Delay requested in the `Retry-After` header, if any
+    },
+
+    /// The node returned an error for one request of the batch.
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+
+    /// The node returned a response that could not be read.
+    #[error("Invalid batch response: {0}")]
+    InvalidResponse(String),
+}
+
+/// A JSON-RPC error object.
+#[derive(Debug, Deserialize)]
+struct RpcErrorObject {
+    code: i64,       // Error code, e.g. -32009 for a missing slot
+    message: String, // Error message
+}
+
+/// A single response of a JSON-RPC batch.
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    id: usize,                     // Position of the request in the batch
+    result: Option<Value>,         // Result of the request, if it succeeded
+    error: Option<RpcErrorObject>, // Error of the request, if it failed
+}
+
+impl From<BatchError> for ClientError {
+    fn from(error: BatchError) -> Self {
+        match error {
+            BatchError::Http(err) => err.into(),
+            BatchError::Rpc { code, message } => RpcError::RpcResponseError {
+                code,
+                message,
+                data: RpcResponseErrorData::Empty,
+            }
+            .into(),
+            // Kept whole so that `classify_client_error` can read the `Retry-After` delay
+            BatchError::Status { .. } => ClientErrorKind::Io(std::io::Error::other(error)).into(),
+            BatchError::InvalidResponse(message) => RpcError::ParseError(message).into(),
+        }
+    }
+}
+
+/// A client sending JSON-RPC batch requests, which the Solana RPC client does not support.
+///
+/// It also serves as the transport of the Solana RPC client, through `rpc_client`, so that
+/// single calls are sent exactly once: unlike the client's own HTTP transport, it does
+/// not retry rate-limited requests behind the caller's back, leaving retries to
+/// `with_retry`.
+#[derive(Clone)]
+pub struct BatchRpcClient {
+    http: reqwest::Client, // HTTP client used to send the batches
+    url: String,           // URL of the RPC endpoint
+}
+
+impl BatchRpcClient {
+    /// Creates a batch client for the specified RPC endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the Solana RPC endpoint.
+    /// * `timeout` - The time allowed for a batch request before it fails.
+    pub fn new(url: &str, timeout: Duration) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .expect("Failed to build the HTTP client"),
+            url: url.to_string(),
+        }
+    }
+
+    /// Creates a Solana RPC client sending its calls through this client.
+    ///
+    /// # Arguments
+    ///
+    /// * `commitment` - The commitment of the client's calls.
+    pub fn rpc_client(&self, commitment: CommitmentConfig) -> RpcClient {
+        RpcClient::new_sender(self.clone(), RpcClientConfig::with_commitment(commitment))
+    }
+
+    /// Sends a JSON-RPC request, or batch of requests, in a single HTTP request.
+    ///
+    /// # Returns
+    ///
+    /// The HTTP response, or a `BatchError::Status` with the `Retry-After` delay if the
+    /// node answered with an unsuccessful status.
+    async fn post(&self, body: &Value) -> Result<reqwest::Response, BatchError> {
+        let response = self.http.post(&self.url).json(body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(BatchError::Status {
+                status: status.as_u16(),
+                retry_after: retry_after(response.headers()),
+            });
+        }
+        Ok(response)
+    }
+
+    /// Fetches the details of several transactions in a single HTTP request.
+    ///
+    /// # Arguments
+    ///
+    /// * `signatures` - The signatures of the transactions.
+    /// * `config` - The configuration sent with each `getTransaction` request.
+    ///
+    /// # Returns
+    ///
+    /// A result containing one entry per signature, in the order of `signatures`: the
+    /// transaction, `None` if the node does not know it, or the error returned for it.
+    /// The outer result fails only if the batch as a whole could not be sent or read.
+    pub async fn get_transactions(
+        &self,
+        signatures: &[String],
+        config: &RpcTransactionConfig,
+    ) -> Result<
+        Vec<Result<Option<EncodedConfirmedTransactionWithStatusMeta>, BatchError>>,
+        BatchError,
+    > {
+        let requests: Vec<Value> = signatures
+            .iter()
+            .enumerate()
+            .map(|(id, signature)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "getTransaction",
+                    "params": [signature, config],
+                })
+            })
+            .collect();
+
+        let responses: Vec<RpcResponse> = self.post(&Value::from(requests)).await?.json().await?;
+
+        // Responses may come back in any order, so they are matched to requests by ID
+        let mut results: Vec<Option<Result<_, BatchError>>> =
+            signatures.iter().map(|_| None).collect();
+        for response in responses {
+            let Some(slot) = results.get_mut(response.id) else {
+                return Err(BatchError::InvalidResponse(format!(
+                    "unknown response ID {}",
+                    response.id
+                )));
+            };
+            *slot = Some(match (response.error, response.result) {
+                (Some(error), _) => Err(BatchError::Rpc {
+                    code: error.code,
+                    message: error.message,
+                }),
+                (None, None | Some(Value::Null)) => Ok(None),
+                (None, Some(result)) => serde_json::from_value(result)
+                    .map(Some)
+                    .map_err(|err| BatchError::InvalidResponse(err.to_string())),
+            });
+        }
+
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    Err(BatchError::InvalidResponse("missing response".to_string()))
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl RpcSender for BatchRpcClient {
+    async fn send(&self, request: RpcRequest, params: Value) -> Result<Value, ClientError> {
+        // Each HTTP request carries a single call, so its ID only has to be echoed back
+        let response: RpcResponse = self
+            .post(&request.build_request_json(0, params))
+            .await?
+            .json()
+            .await?;
+        match (response.error, response.result) {
+            (Some(error), _) => Err(BatchError::Rpc {
+                code: error.code,
+                message: error.message,
+            }
+            .into()),
+            (None, result) => Ok(result.unwrap_or_default()),
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+/// Reads the delay in seconds of a `Retry-After` header. Dates are not supported and
+/// are ignored.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc::{self, MockReply, MockRpc};
+
+    /// Test to verify that a batch is sent in one HTTP request and that each signature
+    /// gets its own transaction, missing result or error.
+    #[tokio::test]
+    async fn test_get_transactions_in_one_batch() {
+        let node = MockRpc::start(|_, params| match params[0].as_str().unwrap() {
+            "found" => MockReply::ok(mock_rpc::transaction("found", "sender", "receiver")),
+            "missing" => MockReply::ok(Value::Null),
+            _ => MockReply::error(-32009, "Slot 1000 was skipped"),
+        })
+        .await;
+        let client = BatchRpcClient::new(&node.url, Duration::from_secs(5));
+
+        let signatures = vec![
+            "found".to_string(),
+            "missing".to_string(),
+            "failing".to_string(),
+        ];
+        let results = client
+            .get_transactions(&signatures, &RpcTransactionConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(node.http_requests(), 1);
+        assert_eq!(node.calls("getTransaction"), 3);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().as_ref().unwrap().slot, 1000);
+        assert!(results[1].as_ref().unwrap().is_none());
+        assert!(matches!(
+            results[2],
+            Err(BatchError::Rpc { code: -32009, .. })
+        ));
+    }
+}
@@ -0,0 +1,46 @@
+use std::fs::{self, File};
+use std::io::Write;
+
+/// The last slot processed by the block stream, persisted to a file so that the stream
+/// resumes where it stopped after a restart.
+#[derive(Debug)]
+pub struct SlotCheckpoint {
+    file_path: String, // File path for persisting the slot
+}
+
+impl SlotCheckpoint {
+    /// Creates a checkpoint persisted to the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path` - The path of the file holding the slot.
+    pub fn new(file_path: String) -> Self {
+        Self { file_path }
+    }
+
+    /// Reads the last processed slot from the file.
+    ///
+    /// # Returns
+    ///
+    /// The slot, or `None` if the file does not exist or does not hold a slot.
+    pub fn load(&self) -> Option<u64> {
+        fs::read_to_string(&self.file_path)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Records a slot as the last processed one, replacing the file at once so that a
+    /// crash never leaves it half written.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The last processed slot.
+    pub fn save(&self, slot: u64) {
+        let temp_path = format!("{}.tmp", self.file_path);
+        let mut file = File::create(&temp_path).expect("Unable to create file");
+        writeln!(file, "{}", slot).expect("Unable to write to file");
+        fs::rename(&temp_path, &self.file_path).expect("Unable to replace file");
+    }
+}
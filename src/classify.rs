@@ -0,0 +1,341 @@
+use crate::db::{TransactionData, TransactionStatus};
+use crate::decoder::{MEMO_PROGRAM_ID, MEMO_V1_PROGRAM_ID, TOKEN_2022_PROGRAM_ID};
+use crate::events::TransactionEvent;
+use crate::instructions::InstructionRecord;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{compute_budget, pubkey, stake, system_program, vote};
+use std::str::FromStr;
+
+/// Program ID of the SPL Associated Token Account program.
+const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// What a transaction did, as tagged by the classification stage.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    /// Moved SOL through the System program.
+    SolTransfer,
+    /// Moved SPL tokens or NFTs.
+    TokenTransfer,
+    /// Swapped one asset for another through a DEX.
+    Swap,
+    /// Managed a stake account or received a staking reward.
+    Stake,
+    /// Cast a validator vote.
+    Vote,
+    /// Called a program other than the native and SPL programs.
+    ProgramCall,
+    /// Failed on chain.
+    Failed,
+    /// Matched none of the rules.
+    Unknown,
+}
+
+/// A rule tagging transactions with a kind.
+pub trait ClassificationRule: Send + Sync {
+    /// Returns the kind of the transaction if the rule matches it.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The parsed transaction, with its decoded events.
+    /// * `instructions` - The top-level and inner instructions of the transaction.
+    fn classify(
+        &self,
+        transaction: &TransactionData,
+        instructions: &[InstructionRecord],
+    ) -> Option<TransactionKind>;
+}
+
+/// A built-in rule matching a single kind with a predicate.
+struct Rule {
+    kind: TransactionKind, // Kind tagged when the predicate matches
+    matches: fn(&TransactionData, &[InstructionRecord]) -> bool, // Predicate of the rule
+}
+
+impl ClassificationRule for Rule {
+    fn classify(
+        &self,
+        transaction: &TransactionData,
+        instructions: &[InstructionRecord],
+    ) -> Option<TransactionKind> {
+        (self.matches)(transaction, instructions).then_some(self.kind)
+    }
+}
+
+/// A rule tagging the transactions that invoke a program, directly or through CPI, with
+/// a kind, e.g. a DEX the built-in rules do not know as `swap`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramRule {
+    program_id: String,    // Program whose invocations are tagged
+    kind: TransactionKind, // Kind tagged on the transactions invoking the program
+}
+
+impl FromStr for ProgramRule {
+    type Err = String;
+
+    /// Parses a rule written `<program ID>=<kind>`, e.g.
+    /// `whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc=swap`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (program_id, kind) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected <program ID>=<kind>: {}", value))?;
+        let program_id: Pubkey = program_id
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid program ID: {}", program_id))?;
+        let kind = serde_json::from_value(serde_json::Value::String(kind.trim().to_string()))
+            .map_err(|_| format!("unknown transaction kind: {}", kind))?;
+        Ok(Self {
+            program_id: program_id.to_string(),
+            kind,
+        })
+    }
+}
+
+impl ClassificationRule for ProgramRule {
+    fn classify(
+        &self,
+        _transaction: &TransactionData,
+        instructions: &[InstructionRecord],
+    ) -> Option<TransactionKind> {
+        instructions
+            .iter()
+            .any(|instruction| instruction.program_id == self.program_id)
+            .then_some(self.kind)
+    }
+}
+
+/// Tags transactions with the kinds of every rule that matches them.
+#[derive(Default)]
+pub struct Classifier {
+    rules: Vec<Box<dyn ClassificationRule>>, // Rules applied in registration order
+}
+
+impl Classifier {
+    /// Creates a classifier with the built-in rules for every `TransactionKind`.
+    pub fn with_builtins() -> Self {
+        let mut classifier = Self::default();
+        classifier.register(Rule {
+            kind: TransactionKind::Failed,
+            matches: |transaction, _| transaction.status == TransactionStatus::Failed,
+        });
+        classifier.register(Rule {
+            kind: TransactionKind::SolTransfer,
+            matches: |transaction, _| {
+                succeeded(transaction)
+                    && has_event(transaction, |event| {
+                        matches!(event, TransactionEvent::SolTransfer { .. })
+                    })
+            },
+        });
+        classifier.register(Rule {
+            kind: TransactionKind::TokenTransfer,
+            matches: |transaction, _| {
+                succeeded(transaction)
+                    && has_event(transaction, |event| {
+                        matches!(
+                            event,
+                            TransactionEvent::TokenTransfer { .. }
+                                | TransactionEvent::NftTransfer { .. }
+                        )
+                    })
+            },
+        });
+        classifier.register(Rule {
+            kind: TransactionKind::Swap,
+            matches: |transaction, _| {
+                succeeded(transaction)
+                    && has_event(transaction, |event| {
+                        matches!(event, TransactionEvent::Swap { .. })
+                    })
+            },
+        });
+        classifier.register(Rule {
+            kind: TransactionKind::Stake,
+            matches: |transaction, instructions| {
+                invokes(instructions, &stake::program::id())
+                    || has_event(transaction, |event| {
+                        matches!(event, TransactionEvent::InflationReward { .. })
+                    })
+            },
+        });
+        classifier.register(Rule {
+            kind: TransactionKind::Vote,
+            matches: |_, instructions| invokes(instructions, &vote::program::id()),
+        });
+        classifier.register(Rule {
+            kind: TransactionKind::ProgramCall,
+            matches: |_, instructions| {
+                instructions
+                    .iter()
+                    .any(|instruction| !is_native_program(&instruction.program_id))
+            },
+        });
+        classifier
+    }
+
+    /// Registers a rule, applied after the rules registered before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `rule` - The rule to register.
+    pub fn register(&mut self, rule: impl ClassificationRule + 'static) {
+        self.rules.push(Box::new(rule));
+    }
+
+    /// Classifies a transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The parsed transaction, with its decoded events.
+    /// * `instructions` - The top-level and inner instructions of the transaction.
+    ///
+    /// # Returns
+    ///
+    /// The distinct kinds of the matching rules, or `[Unknown]` if no rule matched.
+    pub fn classify(
+        &self,
+        transaction: &TransactionData,
+        instructions: &[InstructionRecord],
+    ) -> Vec<TransactionKind> {
+        let mut kinds = Vec::new();
+        for kind in self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.classify(transaction, instructions))
+        {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        if kinds.is_empty() {
+            kinds.push(TransactionKind::Unknown);
+        }
+        kinds
+    }
+}
+
+/// Returns whether the transaction succeeded, so that the value its events describe
+/// actually moved.
+fn succeeded(transaction: &TransactionData) -> bool {
+    transaction.status == TransactionStatus::Success
+}
+
+/// Returns whether the transaction has an event matching the predicate.
+fn has_event(transaction: &TransactionData, predicate: fn(&TransactionEvent) -> bool) -> bool {
+    transaction.events.iter().any(predicate)
+}
+
+/// Returns whether any instruction invokes the program.
+fn invokes(instructions: &[InstructionRecord], program_id: &Pubkey) -> bool {
+    let program_id = program_id.to_string();
+    instructions
+        .iter()
+        .any(|instruction| instruction.program_id == program_id)
+}
+
+/// Returns whether the program is a native or SPL program whose instructions are
+/// covered by the other kinds.
+fn is_native_program(program_id: &str) -> bool {
+    [
+        system_program::id(),
+        compute_budget::id(),
+        stake::program::id(),
+        vote::program::id(),
+        spl_token::id(),
+        TOKEN_2022_PROGRAM_ID,
+        ASSOCIATED_TOKEN_PROGRAM_ID,
+        MEMO_PROGRAM_ID,
+        MEMO_V1_PROGRAM_ID,
+    ]
+    .iter()
+    .any(|native| native.to_string() == program_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a top-level instruction record invoking the given program.
+    fn invoke(program_id: &str) -> InstructionRecord {
+        InstructionRecord {
+            index: 0,
+            stack_height: 1,
+            program_id: program_id.to_string(),
+            accounts: vec![],
+            data: vec![],
+        }
+    }
+
+    /// Test to verify that a transaction is tagged with every matching kind, and that
+    /// a transaction matching no rule is tagged as unknown.
+    #[test]
+    fn test_classify_transactions() {
+        let classifier = Classifier::with_builtins();
+
+        let transfer_and_call = TransactionData {
+            events: vec![TransactionEvent::SolTransfer {
+                from: "alice".to_string(),
+                to: "bob".to_string(),
+                lamports: 5000,
+            }],
+            ..Default::default()
+        };
+        let instructions = vec![
+            invoke("ComputeBudget111111111111111111111111111111"),
+            invoke("11111111111111111111111111111111"),
+            invoke("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4"),
+        ];
+        assert_eq!(
+            classifier.classify(&transfer_and_call, &instructions),
+            vec![TransactionKind::SolTransfer, TransactionKind::ProgramCall]
+        );
+
+        let vote = vec![invoke("Vote111111111111111111111111111111111111111")];
+        assert_eq!(
+            classifier.classify(&TransactionData::default(), &vote),
+            vec![TransactionKind::Vote]
+        );
+
+        // No value moved in a failed transaction, whatever its instructions describe
+        let failed = TransactionData {
+            status: TransactionStatus::Failed,
+            ..transfer_and_call.clone()
+        };
+        assert_eq!(
+            classifier.classify(&failed, &[]),
+            vec![TransactionKind::Failed]
+        );
+        assert_eq!(
+            classifier.classify(&TransactionData::default(), &[]),
+            vec![TransactionKind::Unknown]
+        );
+    }
+
+    /// Test to verify that a program rule is parsed from its configuration and tags
+    /// the transactions invoking its program after the built-in kinds.
+    #[test]
+    fn test_program_rule() {
+        let program_id = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+        let mut classifier = Classifier::with_builtins();
+        classifier.register(
+            format!("{}=swap", program_id)
+                .parse::<ProgramRule>()
+                .unwrap(),
+        );
+
+        assert_eq!(
+            classifier.classify(&TransactionData::default(), &[invoke(program_id)]),
+            vec![TransactionKind::ProgramCall, TransactionKind::Swap]
+        );
+        assert_eq!(
+            classifier.classify(&TransactionData::default(), &[]),
+            vec![TransactionKind::Unknown]
+        );
+        assert!(format!("{}=trade", program_id)
+            .parse::<ProgramRule>()
+            .is_err());
+        assert!("not-a-program=swap".parse::<ProgramRule>().is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use log::warn;
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+/// Number of recent mismatches kept for the admin endpoint.
+const RECENT_MISMATCHES: usize = 100;
+
+/// A field of a transaction on which two endpoints disagree.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Mismatch {
+    pub signature: String,          // Signature of the transaction
+    pub endpoint: String,           // Endpoint the transaction was fetched from
+    pub reference_endpoint: String, // Endpoint the transaction was verified against
+    pub field: String,              // Field that differs, e.g. "post_balances"
+    pub value: Value,               // Value returned by the endpoint
+    pub reference_value: Value,     // Value returned by the reference endpoint
+}
+
+/// The verification results of a single endpoint.
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct EndpointConsistency {
+    pub checked: u64,    // Transactions of the endpoint verified
+    pub mismatched: u64, // Verified transactions with at least one mismatch
+}
+
+/// The summary of the verifications, served by the `/admin/consistency` endpoint.
+#[derive(Debug, Serialize)]
+pub struct ConsistencySummary {
+    pub checked: u64,                                     // Transactions verified
+    pub mismatched: u64, // Verified transactions with at least one mismatch
+    pub endpoints: BTreeMap<String, EndpointConsistency>, // Results per endpoint verified
+    pub recent_mismatches: Vec<Mismatch>, // Latest mismatches, oldest first
+}
+
+/// Records the results of verifying fetched transactions against a second endpoint.
+#[derive(Debug, Default)]
+pub struct ConsistencyLog {
+    endpoints: Mutex<BTreeMap<String, EndpointConsistency>>, // Results per endpoint verified
+    recent: Mutex<VecDeque<Mismatch>>,                       // Latest mismatches, oldest first
+}
+
+impl ConsistencyLog {
+    /// Records the verification of a transaction fetched from an endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The endpoint the transaction was fetched from.
+    /// * `mismatches` - The fields on which the reference endpoint disagreed, if any.
+    pub fn record(&self, endpoint: &str, mismatches: Vec<Mismatch>) {
+        {
+            let mut endpoints = self.endpoints.lock().unwrap();
+            let results = endpoints.entry(endpoint.to_string()).or_default();
+            results.checked += 1;
+            if !mismatches.is_empty() {
+                results.mismatched += 1;
+            }
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        for mismatch in mismatches {
+            warn!(
+                "Transaction {} differs in {} between {} ({}) and {} ({})",
+                mismatch.signature,
+                mismatch.field,
+                mismatch.endpoint,
+                mismatch.value,
+                mismatch.reference_endpoint,
+                mismatch.reference_value
+            );
+            if recent.len() == RECENT_MISMATCHES {
+                recent.pop_front();
+            }
+            recent.push_back(mismatch);
+        }
+    }
+
+    /// Returns the totals, the results per endpoint and the latest mismatches.
+    pub fn summary(&self) -> ConsistencySummary {
+        let endpoints = self.endpoints.lock().unwrap().clone();
+        ConsistencySummary {
+            checked: endpoints.values().map(|e| e.checked).sum(),
+            mismatched: endpoints.values().map(|e| e.mismatched).sum(),
+            endpoints,
+            recent_mismatches: self.recent.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+}
+
+/// Compares the block time, status and balances of a transaction as returned by two
+/// endpoints.
+///
+/// # Arguments
+///
+/// * `signature` - The signature of the transaction.
+/// * `endpoint` - The endpoint the transaction was fetched from.
+/// * `transaction` - The transaction as returned by `endpoint`.
+/// * `reference_endpoint` - The endpoint the transaction is verified against.
+/// * `reference` - The transaction as returned by `reference_endpoint`, `None` if it
+///   does not know the transaction.
+///
+/// # Returns
+///
+/// The fields on which the endpoints disagree.
+pub fn compare_transactions(
+    signature: &str,
+    endpoint: &str,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    reference_endpoint: &str,
+    reference: Option<&EncodedConfirmedTransactionWithStatusMeta>,
+) -> Vec<Mismatch> {
+    let mismatch = |field: &str, value: Value, reference_value: Value| Mismatch {
+        signature: signature.to_string(),
+        endpoint: endpoint.to_string(),
+        reference_endpoint: reference_endpoint.to_string(),
+        field: field.to_string(),
+        value,
+        reference_value,
+    };
+    let Some(reference) = reference else {
+        return vec![mismatch("transaction", "found".into(), "missing".into())];
+    };
+
+    let fields = |transaction: &EncodedConfirmedTransactionWithStatusMeta| {
+        let meta = transaction.transaction.meta.as_ref();
+        [
+            ("block_time", json!(transaction.block_time)),
+            ("status", json!(meta.map(|m| &m.err))),
+            ("pre_balances", json!(meta.map(|m| &m.pre_balances))),
+            ("post_balances", json!(meta.map(|m| &m.post_balances))),
+            (
+                "pre_token_balances",
+                json!(meta.map(|m| &m.pre_token_balances)),
+            ),
+            (
+                "post_token_balances",
+                json!(meta.map(|m| &m.post_token_balances)),
+            ),
+        ]
+    };
+
+    fields(transaction)
+        .into_iter()
+        .zip(fields(reference))
+        .filter(|((_, value), (_, reference_value))| value != reference_value)
+        .map(|((field, value), (_, reference_value))| mismatch(field, value, reference_value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc;
+
+    /// Test to verify that differing balances and a transaction missing from the
+    /// reference endpoint are reported, and that identical transactions are not.
+    #[test]
+    fn test_compare_transactions() {
+        let fetched: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_value(mock_rpc::transaction("sig", "sender", "receiver")).unwrap();
+        let mut stale = mock_rpc::transaction("sig", "sender", "receiver");
+        stale["meta"]["postBalances"] = json!([1000000, 0, 1]);
+        let stale: EncodedConfirmedTransactionWithStatusMeta =
+            serde_json::from_value(stale).unwrap();
+
+        assert!(compare_transactions("sig", "a", &fetched, "b", Some(&fetched)).is_empty());
+
+        let mismatches = compare_transactions("sig", "a", &fetched, "b", Some(&stale));
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].field, "post_balances");
+        assert_eq!(mismatches[0].value, json!([495000, 500000, 1]));
+        assert_eq!(mismatches[0].reference_value, json!([1000000, 0, 1]));
+
+        let mismatches = compare_transactions("sig", "a", &fetched, "b", None);
+        assert_eq!(mismatches[0].field, "transaction");
+
+        let log = ConsistencyLog::default();
+        log.record("a", mismatches);
+        log.record("a", vec![]);
+        let summary = log.summary();
+        assert_eq!((summary.checked, summary.mismatched), (2, 1));
+        assert_eq!(summary.recent_mismatches.len(), 1);
+    }
+}
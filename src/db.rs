@@ -1,25 +1,160 @@
+use crate::amount::Amount;
+use crate::classify::TransactionKind;
+use crate::events::TransactionEvent;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::TransactionConfirmationStatus;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
+
+/// Retractions buffered for each subscriber before the slowest one starts missing them.
+const RETRACTION_CHANNEL_CAPACITY: usize = 1024;
 
 /// Represents a transaction on the Solana blockchain.
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+///
+/// Fields added after the first release are marked `#[serde(default)]` so that
+/// transactions persisted by older versions can still be loaded.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 pub struct TransactionData {
     pub signature: String, // Signature of the transaction
     pub sender: String,    // Public key of the sender
     pub receiver: String,  // Public key of the receiver
-    pub amount: u64,       // Amount transferred in the transaction
+    pub amount: Amount,    // Balance change of the indexed account in the transaction
     pub timestamp: u64,    // Timestamp of the transaction
+    #[serde(default)]
+    pub status: TransactionStatus, // Whether the transaction succeeded or failed
+    #[serde(default)]
+    pub error: Option<String>, // Error reported by the runtime for failed transactions
+    #[serde(default)]
+    pub fee: u64, // Fee paid by the fee payer, in lamports
+    #[serde(default)]
+    pub compute_units_consumed: Option<u64>, // Compute units consumed, if reported by the node
+    #[serde(default)]
+    pub slot: u64, // Slot the transaction was processed in
+    #[serde(default)]
+    pub recent_blockhash: String, // Recent blockhash the transaction was signed with
+    #[serde(default)]
+    pub invocations: Vec<ProgramInvocation>, // Invocations of the indexed program, if any
+    #[serde(default)]
+    pub events: Vec<TransactionEvent>, // Events decoded from the transaction's instructions
+    #[serde(default)]
+    pub memos: Vec<String>, // Memos attached to the transaction through the Memo program
+    #[serde(default)]
+    pub kinds: Vec<TransactionKind>, // Kinds the transaction was classified as
+    #[serde(default)]
+    pub endpoint: Option<String>, // URL of the RPC endpoint the transaction was fetched from
+    #[serde(default)]
+    pub commitment: Commitment, // Commitment the transaction was last observed at
+}
+
+/// A single invocation of a watched program within a transaction.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct ProgramInvocation {
+    pub program_id: String,       // Program that was invoked
+    pub instruction_index: usize, // Index of the top-level instruction containing the invocation
+    pub inner: bool,              // Whether the program was invoked through CPI
+    pub instruction_count: usize, // Instructions executed, including those invoked through CPI
+    pub accounts: Vec<String>,    // Distinct accounts touched by the invocation
+}
+
+/// A transaction as persisted to the file, along with the public key it is indexed under.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredTransaction {
+    #[serde(default)]
+    pub_key: Option<String>, // Missing in files written before the key was persisted
+    #[serde(flatten)]
+    transaction: TransactionData,
+}
+
+/// Execution status of a transaction.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionStatus {
+    /// The transaction executed successfully.
+    #[default]
+    Success,
+    /// The transaction was included in a block but its execution failed.
+    Failed,
+}
+
+/// How final a transaction is, from the least to the most final.
+///
+/// Transactions persisted before commitments were tracked were fetched at the client's
+/// default commitment, so they default to finalized.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Commitment {
+    /// Processed by the node, but possibly on a fork that gets abandoned.
+    Processed,
+    /// Voted on by a supermajority of the cluster.
+    Confirmed,
+    /// Rooted, so it can no longer be rolled back.
+    #[default]
+    Finalized,
+}
+
+impl std::str::FromStr for Commitment {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "processed" => Ok(Self::Processed),
+            "confirmed" => Ok(Self::Confirmed),
+            "finalized" => Ok(Self::Finalized),
+            _ => Err(format!("unknown commitment: {}", value)),
+        }
+    }
+}
+
+impl From<TransactionConfirmationStatus> for Commitment {
+    fn from(status: TransactionConfirmationStatus) -> Self {
+        match status {
+            TransactionConfirmationStatus::Processed => Self::Processed,
+            TransactionConfirmationStatus::Confirmed => Self::Confirmed,
+            TransactionConfirmationStatus::Finalized => Self::Finalized,
+        }
+    }
+}
+
+impl From<Commitment> for CommitmentConfig {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Processed => CommitmentConfig::processed(),
+            Commitment::Confirmed => CommitmentConfig::confirmed(),
+            Commitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+/// Why a stored transaction was retracted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetractionReason {
+    /// The cluster no longer knows the transaction, so it was orphaned on an abandoned fork.
+    Orphaned,
+    /// The slot of the transaction was skipped or rolled back, so it is not part of the
+    /// finalized chain.
+    RolledBack,
+}
+
+/// A transaction retracted from the database, as sent to subscribers.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Retraction {
+    pub pub_key: String,          // Public key the transaction was stored under
+    pub signature: String,        // Signature of the transaction
+    pub slot: u64,                // Slot the transaction was observed in
+    pub reason: RetractionReason, // Why the transaction was retracted
 }
 
 /// An in-memory database that stores transaction data, with persistence capabilities.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct InMemoryDatabase {
     transactions: Mutex<HashMap<String, Vec<TransactionData>>>, // Stores transactions by public key
     file_path: String, // File path for persisting transactions
+    retractions: broadcast::Sender<Retraction>, // Notifies subscribers of retracted transactions
 }
 
 impl InMemoryDatabase {
@@ -36,10 +171,24 @@ impl InMemoryDatabase {
         Self {
             transactions: Mutex::new(HashMap::new()),
             file_path,
+            retractions: broadcast::channel(RETRACTION_CHANNEL_CAPACITY).0,
         }
     }
 
-    /// Adds a new transaction to the in-memory database and saves it to a file.
+    /// Creates a database persisting to a file in a new temporary directory, which is
+    /// removed once the returned `TempDir` is dropped.
+    #[cfg(test)]
+    pub fn temporary() -> (Self, tempfile::TempDir) {
+        let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let file_path = dir.path().join("transactions.txt");
+        (Self::new(file_path.to_string_lossy().into_owned()), dir)
+    }
+
+    /// Adds a transaction to the in-memory database and saves it to a file.
+    ///
+    /// A transaction already stored under the public key with the same signature is
+    /// replaced, e.g. when it is observed again at a higher commitment, and nothing is
+    /// written if it did not change.
     ///
     /// # Arguments
     ///
@@ -47,10 +196,15 @@ impl InMemoryDatabase {
     /// * `transaction` - The transaction data to be added.
     pub async fn add_transaction(&self, pub_key: &str, transaction: TransactionData) {
         let mut transactions = self.transactions.lock().await;
-        transactions
-            .entry(pub_key.to_string())
-            .or_insert_with(Vec::new)
-            .push(transaction.clone());
+        let stored = transactions.entry(pub_key.to_string()).or_default();
+        match stored
+            .iter_mut()
+            .find(|stored| stored.signature == transaction.signature)
+        {
+            Some(existing) if *existing == transaction => return,
+            Some(existing) => *existing = transaction.clone(),
+            None => stored.push(transaction.clone()),
+        }
 
         // Append the transaction to the text file for persistence
         let mut file = OpenOptions::new()
@@ -59,8 +213,11 @@ impl InMemoryDatabase {
             .open(&self.file_path)
             .expect("Unable to open file");
 
-        let serialized_transaction =
-            serde_json::to_string(&transaction).expect("Failed to serialize transaction");
+        let serialized_transaction = serde_json::to_string(&StoredTransaction {
+            pub_key: Some(pub_key.to_string()),
+            transaction,
+        })
+        .expect("Failed to serialize transaction");
 
         writeln!(file, "{}", serialized_transaction).expect("Unable to write to file");
     }
@@ -69,28 +226,166 @@ impl InMemoryDatabase {
     ///
     /// This method reads each line from the specified file and attempts to deserialize
     /// it into a `TransactionData` struct. If successful, the transaction is added to
-    /// the in-memory database under the corresponding sender's public key.
+    /// the in-memory database under the public key it was stored with, or under the
+    /// sender's public key for lines written before the key was persisted. A later line
+    /// for the same public key and signature replaces the earlier one.
     pub async fn load_from_file(&self) {
         if Path::new(&self.file_path).exists() {
             let file = File::open(&self.file_path).expect("Unable to open file");
             let reader = BufReader::new(file);
             let mut transactions = self.transactions.lock().await;
 
-            for line in reader.lines() {
-                if let Ok(transaction_str) = line {
-                    if let Ok(transaction) =
-                        serde_json::from_str::<TransactionData>(&transaction_str)
+            for transaction_str in reader.lines().map_while(Result::ok) {
+                if let Ok(stored) = serde_json::from_str::<StoredTransaction>(&transaction_str) {
+                    let pub_key = stored
+                        .pub_key
+                        .unwrap_or_else(|| stored.transaction.sender.clone());
+                    let stored_transactions = transactions.entry(pub_key).or_default();
+                    match stored_transactions
+                        .iter_mut()
+                        .find(|tx| tx.signature == stored.transaction.signature)
                     {
-                        transactions
-                            .entry(transaction.sender.clone())
-                            .or_insert_with(Vec::new)
-                            .push(transaction);
+                        Some(existing) => *existing = stored.transaction,
+                        None => stored_transactions.push(stored.transaction),
                     }
                 }
             }
         }
     }
 
+    /// Checks whether a transaction is already stored for a given public key.
+    ///
+    /// # Arguments
+    ///
+    /// * `pub_key` - The public key the transaction is associated with.
+    /// * `signature` - The signature of the transaction.
+    ///
+    /// # Returns
+    ///
+    /// `true` if a transaction with the signature is stored under the public key.
+    pub async fn contains_transaction(&self, pub_key: &str, signature: &str) -> bool {
+        let transactions = self.transactions.lock().await;
+        transactions
+            .get(pub_key)
+            .is_some_and(|txs| txs.iter().any(|tx| tx.signature == signature))
+    }
+
+    /// Lists the stored transactions that are not finalized yet.
+    ///
+    /// # Returns
+    ///
+    /// The distinct signatures of the transactions below the finalized commitment, each
+    /// with the slot and commitment it was last observed at.
+    pub async fn unfinalized_transactions(&self) -> Vec<(String, u64, Commitment)> {
+        let transactions = self.transactions.lock().await;
+        let mut seen = HashSet::new();
+        transactions
+            .values()
+            .flatten()
+            .filter(|tx| tx.commitment < Commitment::Finalized)
+            .filter(|tx| seen.insert(tx.signature.as_str()))
+            .map(|tx| (tx.signature.clone(), tx.slot, tx.commitment))
+            .collect()
+    }
+
+    /// Updates the commitment and slot of transactions under every public key they are
+    /// stored with, and rewrites the file once for all of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `updates` - The signatures of the transactions, each with the slot and
+    ///   commitment it is now observed at.
+    pub async fn update_commitments(&self, updates: &[(String, u64, Commitment)]) {
+        if updates.is_empty() {
+            return;
+        }
+        let updates: HashMap<&str, (u64, Commitment)> = updates
+            .iter()
+            .map(|(signature, slot, commitment)| (signature.as_str(), (*slot, *commitment)))
+            .collect();
+        let mut transactions = self.transactions.lock().await;
+        for transaction in transactions.values_mut().flatten() {
+            if let Some(&(slot, commitment)) = updates.get(transaction.signature.as_str()) {
+                transaction.commitment = commitment;
+                transaction.slot = slot;
+            }
+        }
+        self.rewrite_file(&transactions);
+    }
+
+    /// Retracts a transaction from every public key it is stored under, e.g. when it
+    /// was orphaned on an abandoned fork, rewrites the file and notifies subscribers of
+    /// each retraction.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the transaction.
+    /// * `reason` - Why the transaction is retracted.
+    ///
+    /// # Returns
+    ///
+    /// The retractions, one per public key the transaction was stored under.
+    pub async fn retract_transaction(
+        &self,
+        signature: &str,
+        reason: RetractionReason,
+    ) -> Vec<Retraction> {
+        let mut transactions = self.transactions.lock().await;
+        let mut retractions = Vec::new();
+        for (pub_key, stored) in transactions.iter_mut() {
+            stored.retain(|tx| {
+                if tx.signature != signature {
+                    return true;
+                }
+                retractions.push(Retraction {
+                    pub_key: pub_key.clone(),
+                    signature: signature.to_string(),
+                    slot: tx.slot,
+                    reason,
+                });
+                false
+            });
+        }
+        if !retractions.is_empty() {
+            self.rewrite_file(&transactions);
+        }
+        drop(transactions);
+
+        // Sending fails only when nobody is subscribed, which is fine
+        for retraction in &retractions {
+            let _ = self.retractions.send(retraction.clone());
+        }
+        retractions
+    }
+
+    /// Subscribes to the transactions retracted from now on.
+    ///
+    /// # Returns
+    ///
+    /// A receiver of the retractions. A subscriber falling more than
+    /// `RETRACTION_CHANNEL_CAPACITY` retractions behind misses the oldest ones.
+    pub fn subscribe_retractions(&self) -> broadcast::Receiver<Retraction> {
+        self.retractions.subscribe()
+    }
+
+    /// Rewrites the file from the in-memory transactions, replacing it atomically so
+    /// that a crash never leaves a truncated file behind.
+    fn rewrite_file(&self, transactions: &HashMap<String, Vec<TransactionData>>) {
+        let temp_path = format!("{}.tmp", self.file_path);
+        let mut file = File::create(&temp_path).expect("Unable to create file");
+        for (pub_key, stored) in transactions {
+            for transaction in stored {
+                let serialized_transaction = serde_json::to_string(&StoredTransaction {
+                    pub_key: Some(pub_key.clone()),
+                    transaction: transaction.clone(),
+                })
+                .expect("Failed to serialize transaction");
+                writeln!(file, "{}", serialized_transaction).expect("Unable to write to file");
+            }
+        }
+        fs::rename(&temp_path, &self.file_path).expect("Unable to replace file");
+    }
+
     /// Retrieves all transactions associated with a given public key.
     ///
     /// # Arguments
@@ -110,19 +405,22 @@ impl InMemoryDatabase {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::amount::Direction;
     use std::sync::Arc;
 
     /// Test to verify that a transaction can be added to the database and retrieved.
     #[tokio::test]
     async fn test_add_and_get_transaction() {
-        let db = Arc::new(InMemoryDatabase::new("test_transactions.txt".to_string()));
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
 
         let transaction = TransactionData {
             signature: "test_sig".to_string(),
             sender: "sender1".to_string(),
             receiver: "receiver1".to_string(),
-            amount: 100,
+            amount: Amount::sol(100, Direction::Credit),
             timestamp: 1628500000,
+            ..Default::default()
         };
 
         db.add_transaction("sender1", transaction.clone()).await;
@@ -135,26 +433,23 @@ mod tests {
     /// Test to verify that transactions can be loaded from a file into the in-memory database.
     #[tokio::test]
     async fn test_load_from_file() {
-        // Ensure the file is clean before the test
-        std::fs::write("persistence_test_transactions.txt", "").expect("Failed to clear file");
-
-        let db = Arc::new(InMemoryDatabase::new(
-            "persistence_test_transactions.txt".to_string(),
-        ));
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
 
         let transaction = TransactionData {
             signature: "persist_test_sig".to_string(),
             sender: "persist_sender".to_string(),
             receiver: "persist_receiver".to_string(),
-            amount: 600,
+            amount: Amount::sol(600, Direction::Credit),
             timestamp: 1628500000,
+            ..Default::default()
         };
 
         // Write transaction directly to file
         let mut file = std::fs::OpenOptions::new()
             .create(true)
             .append(true)
-            .open("persistence_test_transactions.txt")
+            .open(&db.file_path)
             .expect("Unable to open file");
         writeln!(file, "{}", serde_json::to_string(&transaction).unwrap())
             .expect("Unable to write to file");
@@ -167,4 +462,82 @@ mod tests {
         assert_eq!(transactions.len(), 1);
         assert_eq!(transactions[0], transaction);
     }
+
+    /// Test to verify that transactions are reloaded under the public key they were
+    /// stored with, rather than under their sender.
+    #[tokio::test]
+    async fn test_load_from_file_restores_pub_key() {
+        let transaction = TransactionData {
+            signature: "program_sig".to_string(),
+            sender: "fee_payer".to_string(),
+            receiver: "program_account".to_string(),
+            timestamp: 1628500000,
+            ..Default::default()
+        };
+
+        let (db, _dir) = InMemoryDatabase::temporary();
+        db.add_transaction("my_program", transaction.clone()).await;
+
+        let reloaded = InMemoryDatabase::new(db.file_path.clone());
+        reloaded.load_from_file().await;
+
+        assert_eq!(
+            reloaded.get_transactions("my_program").await,
+            vec![transaction]
+        );
+        assert!(reloaded.get_transactions("fee_payer").await.is_empty());
+    }
+
+    /// Test to verify that a transaction stored again with the same signature replaces
+    /// the stored one, and that promotions and retractions survive a reload.
+    #[tokio::test]
+    async fn test_update_and_retract_transactions() {
+        let (db, _dir) = InMemoryDatabase::temporary();
+
+        let kept = TransactionData {
+            signature: "kept_sig".to_string(),
+            slot: 1000,
+            commitment: Commitment::Processed,
+            ..Default::default()
+        };
+        let orphaned = TransactionData {
+            signature: "orphaned_sig".to_string(),
+            ..kept.clone()
+        };
+        db.add_transaction("account", kept.clone()).await;
+        db.add_transaction("account", orphaned.clone()).await;
+        db.add_transaction("program", orphaned).await;
+
+        let confirmed = TransactionData {
+            commitment: Commitment::Confirmed,
+            ..kept.clone()
+        };
+        db.add_transaction("account", confirmed).await;
+        assert_eq!(db.get_transactions("account").await.len(), 2);
+        assert_eq!(db.unfinalized_transactions().await.len(), 2);
+
+        db.update_commitments(&[("kept_sig".to_string(), 1001, Commitment::Finalized)])
+            .await;
+        // The orphaned transaction is retracted from both public keys it is stored under,
+        // and subscribers are notified of each retraction
+        let mut subscriber = db.subscribe_retractions();
+        let retractions = db
+            .retract_transaction("orphaned_sig", RetractionReason::Orphaned)
+            .await;
+        assert_eq!(retractions.len(), 2);
+        assert_eq!(subscriber.recv().await.unwrap().signature, "orphaned_sig");
+        assert_eq!(
+            subscriber.recv().await.unwrap().reason,
+            RetractionReason::Orphaned
+        );
+
+        let reloaded = InMemoryDatabase::new(db.file_path.clone());
+        reloaded.load_from_file().await;
+        let transactions = reloaded.get_transactions("account").await;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].commitment, Commitment::Finalized);
+        assert_eq!(transactions[0].slot, 1001);
+        assert!(reloaded.get_transactions("program").await.is_empty());
+        assert!(reloaded.unfinalized_transactions().await.is_empty());
+    }
 }
@@ -0,0 +1,545 @@
+use crate::events::TransactionEvent;
+use crate::instructions::InstructionRecord;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use solana_sdk::program_utils::limited_deserialize;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::stake::instruction::StakeInstruction;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::{pubkey, stake, system_program};
+use spl_token::instruction::TokenInstruction;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Program ID of the SPL Token-2022 program, which shares the base instruction layout
+/// of the SPL Token program.
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+/// Program ID of the SPL Memo program.
+pub const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// Program ID of the first version of the SPL Memo program.
+pub const MEMO_V1_PROGRAM_ID: Pubkey = pubkey!("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo");
+
+/// Decodes the instructions of a single program into typed events.
+///
+/// Implement this trait to teach the aggregator about a custom program, then add it
+/// to the `DecoderRegistry` used by the `Aggregator`.
+pub trait InstructionDecoder: Send + Sync {
+    /// Returns the program ID whose instructions this decoder understands.
+    fn program_id(&self) -> Pubkey;
+
+    /// Decodes an instruction sent to the program.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw instruction data.
+    /// * `accounts` - The accounts passed to the instruction, in order.
+    ///
+    /// # Returns
+    ///
+    /// `Some(TransactionEvent)` if the instruction was recognized, `None` otherwise.
+    fn decode(&self, data: &[u8], accounts: &[String]) -> Option<TransactionEvent>;
+
+    /// Decodes a payload the program emitted through a `Program data:` log line, such
+    /// as an Anchor event. Programs that do not emit such payloads keep the default.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The base64-decoded payload of the log line.
+    ///
+    /// # Returns
+    ///
+    /// `Some(TransactionEvent)` if the payload was recognized, `None` otherwise.
+    fn decode_log(&self, _data: &[u8]) -> Option<TransactionEvent> {
+        None
+    }
+}
+
+/// A registry of instruction decoders, keyed by the program ID each decoder claims.
+#[derive(Clone, Default)]
+pub struct DecoderRegistry {
+    decoders: HashMap<Pubkey, Arc<dyn InstructionDecoder>>, // Decoders by program ID
+}
+
+impl DecoderRegistry {
+    /// Creates a registry with the built-in decoders for the System, SPL Token,
+    /// SPL Token-2022, SPL Memo and Stake programs.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `DecoderRegistry`.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.register(SystemDecoder);
+        registry.register(TokenDecoder(spl_token::id()));
+        registry.register(TokenDecoder(TOKEN_2022_PROGRAM_ID));
+        registry.register(MemoDecoder(MEMO_PROGRAM_ID));
+        registry.register(MemoDecoder(MEMO_V1_PROGRAM_ID));
+        registry.register(StakeDecoder);
+        registry
+    }
+
+    /// Registers a decoder, replacing any decoder previously registered for the same
+    /// program ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - The decoder to register.
+    pub fn register(&mut self, decoder: impl InstructionDecoder + 'static) {
+        self.decoders
+            .insert(decoder.program_id(), Arc::new(decoder));
+    }
+
+    /// Decodes every instruction of a transaction that a registered decoder understands.
+    ///
+    /// # Arguments
+    ///
+    /// * `instructions` - The instructions of the transaction, in execution order.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `TransactionEvent`, in execution order.
+    pub fn decode_all(&self, instructions: &[InstructionRecord]) -> Vec<TransactionEvent> {
+        instructions
+            .iter()
+            .filter_map(|instruction| {
+                let program_id = instruction.program_id.parse::<Pubkey>().ok()?;
+                self.decoders
+                    .get(&program_id)?
+                    .decode(&instruction.data, &instruction.accounts)
+            })
+            .collect()
+    }
+
+    /// Decodes the `Program data:` payloads found in the log messages of a transaction.
+    ///
+    /// Each payload is attributed to the program executing when it was logged, by
+    /// following the `invoke` and `success`/`failed` lines of the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `logs` - The log messages of the transaction.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `TransactionEvent`, in log order.
+    pub fn decode_logs(&self, logs: &[String]) -> Vec<TransactionEvent> {
+        let mut events = Vec::new();
+        for (program_id, log) in program_log_lines(logs) {
+            let Some(payload) = log.strip_prefix("Program data: ") else {
+                continue;
+            };
+            let decoder = program_id
+                .parse::<Pubkey>()
+                .ok()
+                .and_then(|program_id| self.decoders.get(&program_id));
+            if let (Some(decoder), Ok(data)) = (decoder, STANDARD.decode(payload)) {
+                events.extend(decoder.decode_log(&data));
+            }
+        }
+        events
+    }
+}
+
+/// Pairs each log line of a transaction with the program executing when it was logged.
+///
+/// The executing program is tracked by following the `invoke` and `success`/`failed`
+/// lines of the log. These lines, and lines logged outside of any invocation, are not
+/// returned.
+///
+/// # Arguments
+///
+/// * `logs` - The log messages of the transaction.
+///
+/// # Returns
+///
+/// A vector of `(program_id, log)` pairs, in log order.
+pub fn program_log_lines(logs: &[String]) -> Vec<(&str, &str)> {
+    let mut invocation_stack: Vec<&str> = Vec::new();
+    let mut lines = Vec::new();
+
+    for log in logs {
+        if let Some(rest) = log.strip_prefix("Program ") {
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(program_id), Some("invoke")) => {
+                    invocation_stack.push(program_id);
+                    continue;
+                }
+                (Some(_), Some("success" | "failed:")) => {
+                    invocation_stack.pop();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        if let Some(program_id) = invocation_stack.last() {
+            lines.push((*program_id, log.as_str()));
+        }
+    }
+    lines
+}
+
+/// Returns the account at the given position, or `"unknown"` if it is missing.
+fn account(accounts: &[String], index: usize) -> String {
+    accounts
+        .get(index)
+        .cloned()
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Builds a generic event for an instruction that has no dedicated event type.
+fn instruction(program_id: Pubkey, name: &str) -> TransactionEvent {
+    TransactionEvent::Instruction {
+        program_id: program_id.to_string(),
+        name: name.to_string(),
+        args: serde_json::Value::Null,
+    }
+}
+
+/// Decoder for the System program.
+struct SystemDecoder;
+
+impl InstructionDecoder for SystemDecoder {
+    fn program_id(&self) -> Pubkey {
+        system_program::id()
+    }
+
+    fn decode(&self, data: &[u8], accounts: &[String]) -> Option<TransactionEvent> {
+        let event = match limited_deserialize(data).ok()? {
+            SystemInstruction::Transfer { lamports } => TransactionEvent::SolTransfer {
+                from: account(accounts, 0),
+                to: account(accounts, 1),
+                lamports,
+            },
+            SystemInstruction::TransferWithSeed { lamports, .. } => TransactionEvent::SolTransfer {
+                from: account(accounts, 0),
+                to: account(accounts, 2),
+                lamports,
+            },
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            }
+            | SystemInstruction::CreateAccountWithSeed {
+                lamports,
+                space,
+                owner,
+                ..
+            } => TransactionEvent::CreateAccount {
+                from: account(accounts, 0),
+                to: account(accounts, 1),
+                lamports,
+                space,
+                owner: owner.to_string(),
+            },
+            SystemInstruction::Assign { .. } => instruction(self.program_id(), "assign"),
+            SystemInstruction::AssignWithSeed { .. } => {
+                instruction(self.program_id(), "assign_with_seed")
+            }
+            SystemInstruction::Allocate { .. } => instruction(self.program_id(), "allocate"),
+            SystemInstruction::AllocateWithSeed { .. } => {
+                instruction(self.program_id(), "allocate_with_seed")
+            }
+            SystemInstruction::AdvanceNonceAccount => {
+                instruction(self.program_id(), "advance_nonce_account")
+            }
+            SystemInstruction::WithdrawNonceAccount(_) => {
+                instruction(self.program_id(), "withdraw_nonce_account")
+            }
+            SystemInstruction::InitializeNonceAccount(_) => {
+                instruction(self.program_id(), "initialize_nonce_account")
+            }
+            SystemInstruction::AuthorizeNonceAccount(_) => {
+                instruction(self.program_id(), "authorize_nonce_account")
+            }
+            SystemInstruction::UpgradeNonceAccount => {
+                instruction(self.program_id(), "upgrade_nonce_account")
+            }
+        };
+        Some(event)
+    }
+}
+
+/// Decoder for the SPL Token program and programs sharing its instruction layout.
+struct TokenDecoder(Pubkey);
+
+impl InstructionDecoder for TokenDecoder {
+    fn program_id(&self) -> Pubkey {
+        self.0
+    }
+
+    fn decode(&self, data: &[u8], accounts: &[String]) -> Option<TransactionEvent> {
+        let event = match TokenInstruction::unpack(data).ok()? {
+            TokenInstruction::Transfer { amount } => TransactionEvent::TokenTransfer {
+                source: account(accounts, 0),
+                destination: account(accounts, 1),
+                authority: account(accounts, 2),
+                mint: None,
+                amount,
+                decimals: None,
+            },
+            TokenInstruction::TransferChecked { amount, decimals } => {
+                TransactionEvent::TokenTransfer {
+                    source: account(accounts, 0),
+                    destination: account(accounts, 2),
+                    authority: account(accounts, 3),
+                    mint: Some(account(accounts, 1)),
+                    amount,
+                    decimals: Some(decimals),
+                }
+            }
+            TokenInstruction::MintTo { amount } => TransactionEvent::TokenMint {
+                mint: account(accounts, 0),
+                destination: account(accounts, 1),
+                authority: account(accounts, 2),
+                amount,
+                decimals: None,
+            },
+            TokenInstruction::MintToChecked { amount, decimals } => TransactionEvent::TokenMint {
+                mint: account(accounts, 0),
+                destination: account(accounts, 1),
+                authority: account(accounts, 2),
+                amount,
+                decimals: Some(decimals),
+            },
+            TokenInstruction::Burn { amount } => TransactionEvent::TokenBurn {
+                account: account(accounts, 0),
+                mint: account(accounts, 1),
+                authority: account(accounts, 2),
+                amount,
+                decimals: None,
+            },
+            TokenInstruction::BurnChecked { amount, decimals } => TransactionEvent::TokenBurn {
+                account: account(accounts, 0),
+                mint: account(accounts, 1),
+                authority: account(accounts, 2),
+                amount,
+                decimals: Some(decimals),
+            },
+            other => instruction(self.0, token_instruction_name(&other)),
+        };
+        Some(event)
+    }
+}
+
+/// Returns the name of a token instruction without a dedicated event type.
+fn token_instruction_name(instruction: &TokenInstruction) -> &'static str {
+    match instruction {
+        TokenInstruction::InitializeMint { .. } => "initialize_mint",
+        TokenInstruction::InitializeMint2 { .. } => "initialize_mint2",
+        TokenInstruction::InitializeAccount => "initialize_account",
+        TokenInstruction::InitializeAccount2 { .. } => "initialize_account2",
+        TokenInstruction::InitializeAccount3 { .. } => "initialize_account3",
+        TokenInstruction::InitializeMultisig { .. } => "initialize_multisig",
+        TokenInstruction::InitializeMultisig2 { .. } => "initialize_multisig2",
+        TokenInstruction::Approve { .. } => "approve",
+        TokenInstruction::ApproveChecked { .. } => "approve_checked",
+        TokenInstruction::Revoke => "revoke",
+        TokenInstruction::SetAuthority { .. } => "set_authority",
+        TokenInstruction::CloseAccount => "close_account",
+        TokenInstruction::FreezeAccount => "freeze_account",
+        TokenInstruction::ThawAccount => "thaw_account",
+        TokenInstruction::SyncNative => "sync_native",
+        TokenInstruction::GetAccountDataSize => "get_account_data_size",
+        TokenInstruction::InitializeImmutableOwner => "initialize_immutable_owner",
+        TokenInstruction::AmountToUiAmount { .. } => "amount_to_ui_amount",
+        TokenInstruction::UiAmountToAmount { .. } => "ui_amount_to_amount",
+        TokenInstruction::Transfer { .. } | TokenInstruction::TransferChecked { .. } => "transfer",
+        TokenInstruction::MintTo { .. } | TokenInstruction::MintToChecked { .. } => "mint_to",
+        TokenInstruction::Burn { .. } | TokenInstruction::BurnChecked { .. } => "burn",
+    }
+}
+
+/// Decoder for the SPL Memo program, whose instruction data is the UTF-8 memo itself.
+struct MemoDecoder(Pubkey);
+
+impl InstructionDecoder for MemoDecoder {
+    fn program_id(&self) -> Pubkey {
+        self.0
+    }
+
+    fn decode(&self, data: &[u8], _accounts: &[String]) -> Option<TransactionEvent> {
+        Some(TransactionEvent::Memo {
+            memo: String::from_utf8_lossy(data).into_owned(),
+        })
+    }
+}
+
+/// Decoder for the Stake program.
+struct StakeDecoder;
+
+impl InstructionDecoder for StakeDecoder {
+    fn program_id(&self) -> Pubkey {
+        stake::program::id()
+    }
+
+    fn decode(&self, data: &[u8], accounts: &[String]) -> Option<TransactionEvent> {
+        let event = match limited_deserialize(data).ok()? {
+            StakeInstruction::DelegateStake => TransactionEvent::StakeDelegate {
+                stake_account: account(accounts, 0),
+                vote_account: account(accounts, 1),
+                authority: account(accounts, 5),
+            },
+            StakeInstruction::Deactivate => TransactionEvent::StakeDeactivate {
+                stake_account: account(accounts, 0),
+                authority: account(accounts, 2),
+            },
+            StakeInstruction::Withdraw(lamports) => TransactionEvent::StakeWithdraw {
+                stake_account: account(accounts, 0),
+                destination: account(accounts, 1),
+                lamports,
+            },
+            StakeInstruction::Split(lamports) => TransactionEvent::StakeSplit {
+                stake_account: account(accounts, 0),
+                new_stake_account: account(accounts, 1),
+                lamports,
+            },
+            StakeInstruction::Merge => TransactionEvent::StakeMerge {
+                destination: account(accounts, 0),
+                source: account(accounts, 1),
+            },
+            StakeInstruction::Initialize(..) => instruction(self.program_id(), "initialize"),
+            StakeInstruction::InitializeChecked => {
+                instruction(self.program_id(), "initialize_checked")
+            }
+            StakeInstruction::Authorize(..)
+            | StakeInstruction::AuthorizeChecked(_)
+            | StakeInstruction::AuthorizeWithSeed(_)
+            | StakeInstruction::AuthorizeCheckedWithSeed(_) => {
+                instruction(self.program_id(), "authorize")
+            }
+            StakeInstruction::SetLockup(_) | StakeInstruction::SetLockupChecked(_) => {
+                instruction(self.program_id(), "set_lockup")
+            }
+            StakeInstruction::GetMinimumDelegation => {
+                instruction(self.program_id(), "get_minimum_delegation")
+            }
+            StakeInstruction::DeactivateDelinquent => {
+                instruction(self.program_id(), "deactivate_delinquent")
+            }
+            #[allow(deprecated)]
+            StakeInstruction::Redelegate => instruction(self.program_id(), "redelegate"),
+        };
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a top-level instruction record for the given program.
+    fn record(program_id: Pubkey, data: Vec<u8>, accounts: &[&str]) -> InstructionRecord {
+        InstructionRecord {
+            index: 0,
+            stack_height: 1,
+            program_id: program_id.to_string(),
+            accounts: accounts.iter().map(|a| a.to_string()).collect(),
+            data,
+        }
+    }
+
+    /// Test to verify that the built-in decoders turn System, SPL Token, Memo and Stake
+    /// instructions into typed events, and skip programs without a decoder.
+    #[test]
+    fn test_builtin_decoders() {
+        let registry = DecoderRegistry::with_builtins();
+
+        let sender = Pubkey::new_unique();
+        let receiver = Pubkey::new_unique();
+        let transfer = solana_sdk::system_instruction::transfer(&sender, &receiver, 5000);
+        let transfer_checked = TokenInstruction::TransferChecked {
+            amount: 1_500_000,
+            decimals: 6,
+        }
+        .pack();
+        let delegate = stake::instruction::delegate_stake(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+
+        let instructions = vec![
+            record(system_program::id(), transfer.data, &["sender", "receiver"]),
+            record(
+                spl_token::id(),
+                transfer_checked,
+                &["source", "mint", "destination", "owner"],
+            ),
+            record(MEMO_PROGRAM_ID, b"INV-42".to_vec(), &["owner"]),
+            record(
+                stake::program::id(),
+                delegate.data,
+                &["stake", "vote", "clock", "history", "config", "staker"],
+            ),
+            record(Pubkey::new_unique(), vec![1, 2, 3], &["owner"]),
+        ];
+
+        let events = registry.decode_all(&instructions);
+
+        assert_eq!(
+            events,
+            vec![
+                TransactionEvent::SolTransfer {
+                    from: "sender".to_string(),
+                    to: "receiver".to_string(),
+                    lamports: 5000,
+                },
+                TransactionEvent::TokenTransfer {
+                    source: "source".to_string(),
+                    destination: "destination".to_string(),
+                    authority: "owner".to_string(),
+                    mint: Some("mint".to_string()),
+                    amount: 1_500_000,
+                    decimals: Some(6),
+                },
+                TransactionEvent::Memo {
+                    memo: "INV-42".to_string(),
+                },
+                TransactionEvent::StakeDelegate {
+                    stake_account: "stake".to_string(),
+                    vote_account: "vote".to_string(),
+                    authority: "staker".to_string(),
+                },
+            ]
+        );
+    }
+
+    /// Test to verify that a custom decoder can be registered for a program.
+    #[test]
+    fn test_register_custom_decoder() {
+        struct CounterDecoder(Pubkey);
+
+        impl InstructionDecoder for CounterDecoder {
+            fn program_id(&self) -> Pubkey {
+                self.0
+            }
+
+            fn decode(&self, data: &[u8], _accounts: &[String]) -> Option<TransactionEvent> {
+                Some(TransactionEvent::Instruction {
+                    program_id: self.0.to_string(),
+                    name: "increment".to_string(),
+                    args: serde_json::json!({ "by": data.first()? }),
+                })
+            }
+        }
+
+        let program_id = Pubkey::new_unique();
+        let mut registry = DecoderRegistry::with_builtins();
+        registry.register(CounterDecoder(program_id));
+
+        let events = registry.decode_all(&[record(program_id, vec![7], &["counter"])]);
+
+        assert_eq!(
+            events,
+            vec![TransactionEvent::Instruction {
+                program_id: program_id.to_string(),
+                name: "increment".to_string(),
+                args: serde_json::json!({ "by": 7 }),
+            }]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use crate::batch::BatchRpcClient;
+use crate::db::Commitment;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::source::TransactionSource;
+use futures::future::join_all;
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How the endpoint serving each RPC call is picked from the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailoverPolicy {
+    /// Calls go to the first healthy endpoint in the configured order, so later
+    /// endpoints are only used while the earlier ones are failing.
+    #[default]
+    Primary,
+    /// Calls are spread over the healthy endpoints in turn.
+    RoundRobin,
+}
+
+impl FromStr for FailoverPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "primary" => Ok(Self::Primary),
+            "round_robin" => Ok(Self::RoundRobin),
+            _ => Err(format!("unknown failover policy: {}", value)),
+        }
+    }
+}
+
+/// A single RPC endpoint of the pool, with its clients and request budget.
+pub struct Endpoint {
+    pub url: String,                        // URL of the RPC endpoint
+    pub client: Arc<RpcClient>,             // Solana RPC client for the endpoint
+    pub source: Arc<dyn TransactionSource>, // Source of the signatures, transactions and clock
+    pub batch_client: BatchRpcClient,       // Client sending JSON-RPC batches to the endpoint
+    limiter: Option<RateLimiter>,           // Request budget of the endpoint, if limited
+    healthy: AtomicBool,                    // Whether the last health check or call succeeded
+}
+
+impl Endpoint {
+    /// Creates an endpoint, assumed healthy until a health check or call says otherwise.
+    fn new(
+        url: &str,
+        rate_limit: Option<RateLimit>,
+        commitment: Commitment,
+        request_timeout: Duration,
+    ) -> Self {
+        let batch_client = BatchRpcClient::new(url, request_timeout);
+        let client = Arc::new(batch_client.rpc_client(commitment.into()));
+        Self {
+            url: url.to_string(),
+            client: client.clone(),
+            source: client,
+            batch_client,
+            limiter: rate_limit.and_then(RateLimiter::new),
+            healthy: AtomicBool::new(true),
+        }
+    }
+
+    /// Waits until the given number of requests fits in the budget of the endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The number of requests about to be sent, e.g. the size of a batch.
+    pub async fn acquire(&self, requests: u32) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(requests).await;
+        }
+    }
+
+    /// Returns whether the endpoint is currently considered healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Records the health of the endpoint, logging when it changes.
+    fn set_healthy(&self, healthy: bool, reason: impl Display) {
+        if self.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+            match healthy {
+                true => info!("RPC endpoint {} is healthy again", self.url),
+                false => warn!("RPC endpoint {} is unhealthy: {}", self.url, reason),
+            }
+        }
+    }
+}
+
+/// A pool of RPC endpoints with health checks and failover.
+pub struct EndpointPool {
+    endpoints: Vec<Endpoint>, // Endpoints in the configured order, the first being the primary
+    policy: FailoverPolicy,   // How endpoints are picked for each call
+    max_slot_lag: u64,        // Slots an endpoint may lag behind the others and stay healthy
+    next: AtomicUsize,        // Endpoint the next call starts from under round-robin
+}
+
+impl EndpointPool {
+    /// Creates a pool of endpoints, each with its own request budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - The URLs of the RPC endpoints, the first being the primary.
+    /// * `policy` - How endpoints are picked for each call.
+    /// * `rate_limit` - The request budget of each endpoint, if limited.
+    /// * `max_slot_lag` - The slots an endpoint may lag behind the others and stay healthy.
+    /// * `commitment` - The default commitment of the calls to each endpoint.
+    /// * `request_timeout` - The time allowed for a single request to each endpoint.
+    pub fn new(
+        urls: &[String],
+        policy: FailoverPolicy,
+        rate_limit: Option<RateLimit>,
+        max_slot_lag: u64,
+        commitment: Commitment,
+        request_timeout: Duration,
+    ) -> Self {
+        Self {
+            endpoints: urls
+                .iter()
+                .map(|url| Endpoint::new(url, rate_limit, commitment, request_timeout))
+                .collect(),
+            policy,
+            max_slot_lag,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the endpoints to try for a call, in order: the healthy endpoints as
+    /// picked by the policy, then the unhealthy ones as a last resort.
+    pub fn candidates(&self) -> Vec<&Endpoint> {
+        let mut ordered: Vec<&Endpoint> = self.endpoints.iter().collect();
+        if self.policy == FailoverPolicy::RoundRobin && !ordered.is_empty() {
+            let start = self.next.fetch_add(1, Ordering::Relaxed) % ordered.len();
+            ordered.rotate_left(start);
+        }
+        // The sort is stable, so each group keeps the order picked above
+        ordered.sort_by_key(|endpoint| !endpoint.is_healthy());
+        ordered
+    }
+
+    /// Returns the endpoint to verify the data of another endpoint against: the first
+    /// other endpoint in the configured order, preferring healthy ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the endpoint whose data is verified.
+    ///
+    /// # Returns
+    ///
+    /// The reference endpoint, or `None` if the pool has a single endpoint.
+    pub fn reference_for(&self, url: &str) -> Option<&Endpoint> {
+        let mut others = self.endpoints.iter().filter(|endpoint| endpoint.url != url);
+        others
+            .clone()
+            .find(|endpoint| endpoint.is_healthy())
+            .or_else(|| others.next())
+    }
+
+    /// Replaces the source of the signatures, transactions and clock of every endpoint,
+    /// which otherwise is the RPC client of the endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `replace` - Returns the new source of an endpoint given its current source.
+    pub fn map_sources(
+        &mut self,
+        replace: impl Fn(Arc<dyn TransactionSource>) -> Arc<dyn TransactionSource>,
+    ) {
+        for endpoint in &mut self.endpoints {
+            endpoint.source = replace(endpoint.source.clone());
+        }
+    }
+
+    /// Replaces the RPC client of every endpoint, e.g. with a client that cannot reach
+    /// the network while a recording is replayed. The sources are left as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `replace` - Returns the new client of an endpoint given its current client.
+    pub fn map_clients(&mut self, replace: impl Fn(&RpcClient) -> RpcClient) {
+        for endpoint in &mut self.endpoints {
+            endpoint.client = Arc::new(replace(&endpoint.client));
+        }
+    }
+
+    /// Marks an endpoint as unhealthy after a call to it failed, until the next
+    /// health check finds it healthy again.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The endpoint that failed.
+    /// * `reason` - The error of the failed call.
+    pub fn mark_unhealthy(&self, endpoint: &Endpoint, reason: impl Display) {
+        endpoint.set_healthy(false, reason);
+    }
+
+    /// Checks the health of every endpoint with `getHealth` and `getSlot`.
+    ///
+    /// An endpoint is healthy if it reports itself healthy and its slot is within the
+    /// allowed lag of the highest slot reported by the pool.
+    pub async fn check_health(&self) {
+        let reports = join_all(self.endpoints.iter().map(|endpoint| async move {
+            endpoint.acquire(2).await;
+            let health = endpoint.client.get_health().await;
+            let slot = endpoint.client.get_slot().await;
+            (health, slot)
+        }))
+        .await;
+
+        let highest_slot = reports
+            .iter()
+            .filter_map(|(_, slot)| slot.as_ref().ok())
+            .copied()
+            .max()
+            .unwrap_or_default();
+        for (endpoint, (health, slot)) in self.endpoints.iter().zip(reports) {
+            match (health, slot) {
+                (Err(err), _) | (_, Err(err)) => endpoint.set_healthy(false, err),
+                (Ok(()), Ok(slot)) if highest_slot - slot > self.max_slot_lag => endpoint
+                    .set_healthy(
+                        false,
+                        format!("slot {} lags {} slots behind", slot, highest_slot - slot),
+                    ),
+                (Ok(()), Ok(_)) => endpoint.set_healthy(true, "healthy"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_rpc::{MockReply, MockRpc};
+    use serde_json::json;
+    use solana_client::rpc_config::RpcTransactionConfig;
+
+    /// Starts a mock node reporting the given health and slot.
+    async fn node(healthy: bool, slot: u64) -> MockRpc {
+        MockRpc::start(move |method, _| match (method, healthy) {
+            ("getHealth", true) => MockReply::ok(json!("ok")),
+            ("getHealth", false) => MockReply::error(-32005, "Node is behind by 100 slots"),
+            _ => MockReply::ok(json!(slot)),
+        })
+        .await
+    }
+
+    /// Test to verify that endpoints reporting themselves unhealthy or lagging behind
+    /// the pool are tried last, and recover once they catch up.
+    #[tokio::test]
+    async fn test_health_checks_reorder_candidates() {
+        let unhealthy = node(false, 1000).await;
+        let lagging = node(true, 900).await;
+        let healthy = node(true, 1000).await;
+        let urls = vec![
+            unhealthy.url.clone(),
+            lagging.url.clone(),
+            healthy.url.clone(),
+        ];
+        let pool = EndpointPool::new(
+            &urls,
+            FailoverPolicy::Primary,
+            None,
+            50,
+            Commitment::Finalized,
+            Duration::from_secs(5),
+        );
+
+        let order = |pool: &EndpointPool| -> Vec<String> {
+            pool.candidates().iter().map(|e| e.url.clone()).collect()
+        };
+        assert_eq!(order(&pool), urls);
+
+        pool.check_health().await;
+        assert_eq!(
+            order(&pool),
+            vec![
+                healthy.url.clone(),
+                unhealthy.url.clone(),
+                lagging.url.clone()
+            ]
+        );
+
+        // Within the allowed lag, the lagging endpoint is healthy again
+        let pool = EndpointPool::new(
+            &urls,
+            FailoverPolicy::Primary,
+            None,
+            100,
+            Commitment::Finalized,
+            Duration::from_secs(5),
+        );
+        pool.check_health().await;
+        assert_eq!(
+            order(&pool),
+            vec![
+                lagging.url.clone(),
+                healthy.url.clone(),
+                unhealthy.url.clone()
+            ]
+        );
+    }
+
+    /// Test to verify that a request to a node that stops answering fails after the
+    /// request timeout, through both the RPC client and the batch client.
+    #[tokio::test]
+    async fn test_requests_time_out() {
+        let hung = MockRpc::start(|_, _| MockReply::ok(json!(1000)).delayed(10_000)).await;
+        let endpoint = Endpoint::new(
+            &hung.url,
+            None,
+            Commitment::Finalized,
+            Duration::from_millis(100),
+        );
+
+        let (signatures, config) = (["signature".to_string()], RpcTransactionConfig::default());
+        let (slot, batch) = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join(
+                endpoint.client.get_slot(),
+                endpoint.batch_client.get_transactions(&signatures, &config),
+            ),
+        )
+        .await
+        .expect("Requests did not time out");
+        assert!(slot.is_err());
+        assert!(batch.is_err());
+    }
+
+    /// Test to verify that the round-robin policy starts each call from the next
+    /// healthy endpoint.
+    #[test]
+    fn test_round_robin_candidates() {
+        let urls = vec![
+            "http://a".to_string(),
+            "http://b".to_string(),
+            "http://c".to_string(),
+        ];
+        let pool = EndpointPool::new(
+            &urls,
+            FailoverPolicy::RoundRobin,
+            None,
+            50,
+            Commitment::Finalized,
+            Duration::from_secs(5),
+        );
+        pool.mark_unhealthy(&pool.endpoints[1], "connection refused");
+
+        let first: Vec<&str> = pool.candidates().iter().map(|e| e.url.as_str()).collect();
+        let second: Vec<&str> = pool.candidates().iter().map(|e| e.url.as_str()).collect();
+        let third: Vec<&str> = pool.candidates().iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(first, vec!["http://a", "http://c", "http://b"]);
+        assert_eq!(second, vec!["http://c", "http://a", "http://b"]);
+        assert_eq!(third, vec!["http://c", "http://a", "http://b"]);
+    }
+}
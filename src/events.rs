@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+/// A typed event extracted from a transaction, such as a decoded instruction.
+///
+/// Events are serialized with a `type` tag, e.g.
+/// `{ "type": "sol_transfer", "from": "...", "to": "...", "lamports": 5000 }`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransactionEvent {
+    /// Lamports transferred by the System program.
+    SolTransfer {
+        from: String,  // Funding account
+        to: String,    // Recipient account
+        lamports: u64, // Lamports transferred
+    },
+    /// A new account created by the System program.
+    CreateAccount {
+        from: String,  // Funding account
+        to: String,    // New account
+        lamports: u64, // Lamports transferred to the new account
+        space: u64,    // Bytes of data allocated for the new account
+        owner: String, // Program assigned as the owner of the new account
+    },
+    /// Tokens transferred by the SPL Token program.
+    TokenTransfer {
+        source: String,       // Source token account
+        destination: String,  // Destination token account
+        authority: String,    // Owner or delegate of the source account
+        mint: Option<String>, // Mint, only known for checked transfers
+        amount: u64,          // Raw token amount
+        decimals: Option<u8>, // Decimals of the mint, only known for checked transfers
+    },
+    /// Tokens minted by the SPL Token program.
+    TokenMint {
+        mint: String,         // Mint the tokens were minted from
+        destination: String,  // Token account receiving the tokens
+        authority: String,    // Mint authority
+        amount: u64,          // Raw token amount
+        decimals: Option<u8>, // Decimals of the mint, only known for checked mints
+    },
+    /// Tokens burned by the SPL Token program.
+    TokenBurn {
+        account: String,      // Token account the tokens were burned from
+        mint: String,         // Mint of the burned tokens
+        authority: String,    // Owner or delegate of the token account
+        amount: u64,          // Raw token amount
+        decimals: Option<u8>, // Decimals of the mint, only known for checked burns
+    },
+    /// A memo attached by the SPL Memo program.
+    Memo {
+        memo: String, // Memo text
+    },
+    /// A stake account delegated to a validator.
+    StakeDelegate {
+        stake_account: String, // Delegated stake account
+        vote_account: String,  // Vote account of the validator
+        authority: String,     // Stake authority
+    },
+    /// A stake account deactivated.
+    StakeDeactivate {
+        stake_account: String, // Deactivated stake account
+        authority: String,     // Stake authority
+    },
+    /// Lamports withdrawn from a stake account.
+    StakeWithdraw {
+        stake_account: String, // Stake account withdrawn from
+        destination: String,   // Recipient account
+        lamports: u64,         // Lamports withdrawn
+    },
+    /// A stake account split into a new stake account.
+    StakeSplit {
+        stake_account: String,     // Stake account that was split
+        new_stake_account: String, // Stake account receiving the split lamports
+        lamports: u64,             // Lamports moved to the new stake account
+    },
+    /// Two stake accounts merged into one.
+    StakeMerge {
+        destination: String, // Stake account merged into
+        source: String,      // Stake account merged from, closed by the merge
+    },
+    /// Any other instruction a decoder understood, described by its name and arguments.
+    Instruction {
+        program_id: String,      // Program the instruction was sent to
+        name: String,            // Name of the instruction, e.g. "close_account"
+        args: serde_json::Value, // Decoded arguments, if any
+    },
+    /// An inflation reward credited to a stake account at the start of an epoch.
+    InflationReward {
+        stake_account: String,  // Stake account the reward was credited to
+        epoch: u64,             // Epoch the reward was earned in
+        lamports: u64,          // Reward amount
+        post_balance: u64,      // Balance of the stake account after the reward
+        commission: Option<u8>, // Commission of the vote account when the reward was credited
+    },
+    /// A swap of one asset for another made by the signer through a DEX.
+    Swap {
+        signer: String,      // Signer that made the swap
+        venue: String,       // Name of the AMM or aggregator, e.g. "jupiter"
+        program_id: String,  // Program of the venue
+        input_mint: String,  // Mint of the asset sold, or "SOL" for native SOL
+        input_amount: u64,   // Raw amount sold
+        input_decimals: u8,  // Decimals of the asset sold
+        output_mint: String, // Mint of the asset bought, or "SOL" for native SOL
+        output_amount: u64,  // Raw amount bought
+        output_decimals: u8, // Decimals of the asset bought
+    },
+    /// An NFT minted to an owner.
+    NftMint {
+        mint: String,               // Mint of the NFT
+        owner: String,              // Owner the NFT was minted to
+        name: Option<String>,       // Name from the Metaplex metadata, if any
+        collection: Option<String>, // Collection mint from the Metaplex metadata, if any
+    },
+    /// An NFT transferred between owners.
+    NftTransfer {
+        mint: String,               // Mint of the NFT
+        from: String,               // Previous owner
+        to: String,                 // New owner
+        name: Option<String>,       // Name from the Metaplex metadata, if any
+        collection: Option<String>, // Collection mint from the Metaplex metadata, if any
+    },
+    /// An event emitted by an Anchor program, decoded with the program's IDL.
+    AnchorEvent {
+        program_id: String,      // Program that emitted the event
+        name: String,            // Name of the event as declared in the IDL
+        data: serde_json::Value, // Decoded fields of the event
+    },
+}
+
+impl TransactionEvent {
+    /// Returns the name of the instruction the event was decoded from, e.g. `"transfer"`,
+    /// or `None` for events that were not decoded from an instruction.
+    pub fn instruction_name(&self) -> Option<&str> {
+        let name = match self {
+            TransactionEvent::SolTransfer { .. } | TransactionEvent::TokenTransfer { .. } => {
+                "transfer"
+            }
+            TransactionEvent::CreateAccount { .. } => "create_account",
+            TransactionEvent::TokenMint { .. } => "mint_to",
+            TransactionEvent::TokenBurn { .. } => "burn",
+            TransactionEvent::Memo { .. } => "memo",
+            TransactionEvent::StakeDelegate { .. } => "delegate",
+            TransactionEvent::StakeDeactivate { .. } => "deactivate",
+            TransactionEvent::StakeWithdraw { .. } => "withdraw",
+            TransactionEvent::StakeSplit { .. } => "split",
+            TransactionEvent::StakeMerge { .. } => "merge",
+            TransactionEvent::Instruction { name, .. } => name,
+            TransactionEvent::InflationReward { .. }
+            | TransactionEvent::Swap { .. }
+            | TransactionEvent::NftMint { .. }
+            | TransactionEvent::NftTransfer { .. }
+            | TransactionEvent::AnchorEvent { .. } => return None,
+        };
+        Some(name)
+    }
+}
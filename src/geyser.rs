@@ -0,0 +1,335 @@
+use crate::aggregator::Aggregator;
+use crate::db::Commitment;
+use log::{error, info, warn};
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::io;
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::{lookup_host, TcpListener, UnixListener};
+
+/// Largest frame accepted, in bytes. A longer frame ends the connection, since the
+/// stream cannot be trusted to be in sync anymore.
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Name recorded as the endpoint of the transactions received from the stream.
+const GEYSER_SOURCE: &str = "geyser";
+
+/// The socket the Geyser stream is received on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeyserAddress {
+    /// A loopback TCP address, written `tcp://127.0.0.1:10000`.
+    Tcp(String),
+    /// The path of a Unix socket, written `unix:///var/run/geyser.sock`.
+    Unix(PathBuf),
+}
+
+impl FromStr for GeyserAddress {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(address) = value.strip_prefix("tcp://") {
+            Ok(Self::Tcp(address.to_string()))
+        } else if let Some(path) = value.strip_prefix("unix://") {
+            Ok(Self::Unix(PathBuf::from(path)))
+        } else {
+            Err(format!("unknown Geyser socket address: {}", value))
+        }
+    }
+}
+
+/// A listening socket accepting Geyser streams.
+pub enum GeyserListener {
+    Tcp(TcpListener),   // Listener of a TCP socket
+    Unix(UnixListener), // Listener of a Unix socket
+}
+
+impl GeyserListener {
+    /// Binds the socket the stream is received on, replacing a stale Unix socket left
+    /// by a previous run.
+    ///
+    /// Streams are not authenticated, so a TCP address must be a loopback address, and
+    /// an existing file at the path of a Unix socket is only replaced if it is a socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the socket.
+    pub async fn bind(address: &GeyserAddress) -> io::Result<Self> {
+        match address {
+            GeyserAddress::Tcp(address) => {
+                let resolved: Vec<SocketAddr> = lookup_host(address.as_str()).await?.collect();
+                if let Some(exposed) = resolved.iter().find(|addr| !addr.ip().is_loopback()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "Geyser streams are not authenticated, refusing to listen on {}",
+                            exposed
+                        ),
+                    ));
+                }
+                Ok(Self::Tcp(TcpListener::bind(resolved.as_slice()).await?))
+            }
+            GeyserAddress::Unix(path) => {
+                match std::fs::symlink_metadata(path) {
+                    Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+                    Ok(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a socket", path.display()),
+                        ))
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+                Ok(Self::Unix(UnixListener::bind(path)?))
+            }
+        }
+    }
+
+    /// Accepts connections until the task running it is aborted, serving each
+    /// connection concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source storing the transactions of each stream.
+    pub async fn run(self, source: Arc<GeyserSource>) {
+        loop {
+            let accepted = match &self {
+                Self::Tcp(listener) => listener
+                    .accept()
+                    .await
+                    .map(|(stream, _)| Box::new(stream) as Box<dyn AsyncRead + Send + Unpin>),
+                Self::Unix(listener) => listener
+                    .accept()
+                    .await
+                    .map(|(stream, _)| Box::new(stream) as Box<dyn AsyncRead + Send + Unpin>),
+            };
+            match accepted {
+                Ok(stream) => {
+                    let source = source.clone();
+                    tokio::spawn(async move { source.serve(stream).await });
+                }
+                Err(err) => error!("Error accepting a Geyser connection: {}", err),
+            }
+        }
+    }
+}
+
+/// Stores the transactions notified by a Geyser plugin over a framed stream.
+///
+/// Each frame is a 4-byte big-endian length followed by that many bytes of JSON: a
+/// transaction in the `getTransaction` JSON format, including its `slot` and
+/// `blockTime`. Frames in other formats are skipped. Transactions are stored as
+/// `processed`, the commitment plugins notify at, and promoted or retracted later.
+pub struct GeyserSource {
+    aggregator: Arc<Aggregator>, // Aggregator parsing and storing the transactions
+    addresses: Vec<String>,      // Addresses whose transactions are stored
+}
+
+impl GeyserSource {
+    /// Creates a source storing the notified transactions of the given addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregator` - The aggregator parsing and storing the transactions.
+    /// * `addresses` - The public keys of the accounts and programs to index.
+    pub fn new(aggregator: Arc<Aggregator>, addresses: Vec<String>) -> Self {
+        Self {
+            aggregator,
+            addresses,
+        }
+    }
+
+    /// Stores the transactions of a stream until it ends, logging why it ended.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The connection the frames are read from.
+    pub async fn serve(&self, mut stream: impl AsyncRead + Unpin) {
+        info!("Geyser stream connected");
+        let mut stored = 0;
+        loop {
+            let frame = match read_frame(&mut stream).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(err) => {
+                    error!("Error reading the Geyser stream: {}", err);
+                    break;
+                }
+            };
+            let transaction =
+                match serde_json::from_slice::<EncodedConfirmedTransactionWithStatusMeta>(&frame) {
+                    Ok(transaction) => transaction,
+                    Err(err) => {
+                        warn!("Skipping a Geyser frame that is not a transaction: {}", err);
+                        continue;
+                    }
+                };
+            let (frame_stored, _) = self
+                .aggregator
+                .ingest_pushed_transaction(
+                    &self.addresses,
+                    &transaction,
+                    GEYSER_SOURCE,
+                    Commitment::Processed,
+                )
+                .await;
+            stored += frame_stored;
+        }
+        info!("Geyser stream closed after storing {} transactions", stored);
+    }
+}
+
+/// Reads the next frame of a stream.
+///
+/// # Arguments
+///
+/// * `reader` - The stream to read from.
+///
+/// # Returns
+///
+/// The payload of the frame, `None` if the stream ended cleanly between frames, or an
+/// error if it ended within a frame or the frame is longer than `MAX_FRAME_LEN`.
+async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Vec<u8>>> {
+    // The stream may only end before the first byte of the length
+    let mut length = [0u8; 4];
+    if reader.read(&mut length[..1]).await? == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut length[1..]).await?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the limit", length),
+        ));
+    }
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::AggregatorConfig;
+    use crate::db::InMemoryDatabase;
+    use crate::mock_rpc;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpStream, UnixStream};
+    use tokio::time::Duration;
+
+    /// Encodes a payload as a frame.
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Test to verify that frames are read up to a clean end of the stream, and that
+    /// truncated and oversized frames are errors.
+    #[tokio::test]
+    async fn test_read_frame() {
+        let mut bytes = frame(b"{}");
+        bytes.extend(frame(b""));
+        let mut stream = bytes.as_slice();
+        assert_eq!(read_frame(&mut stream).await.unwrap(), Some(b"{}".to_vec()));
+        assert_eq!(read_frame(&mut stream).await.unwrap(), Some(vec![]));
+        assert_eq!(read_frame(&mut stream).await.unwrap(), None);
+
+        for truncated in [&frame(b"{}")[..2], &frame(b"{}")[..5]] {
+            assert!(read_frame(&mut &truncated[..]).await.is_err());
+        }
+
+        let oversized = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes();
+        assert!(read_frame(&mut &oversized[..]).await.is_err());
+    }
+
+    /// Test to verify that only loopback TCP addresses are bound, and that a stale Unix
+    /// socket is replaced while a regular file at its path is left alone.
+    #[tokio::test]
+    async fn test_bind_refuses_exposed_addresses() {
+        let exposed = GeyserAddress::Tcp("0.0.0.0:0".to_string());
+        let err = GeyserListener::bind(&exposed).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        let loopback = GeyserAddress::Tcp("127.0.0.1:0".to_string());
+        assert!(GeyserListener::bind(&loopback).await.is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("stale.sock");
+        let stale = GeyserAddress::Unix(socket_path.clone());
+        drop(GeyserListener::bind(&stale).await.unwrap());
+        assert!(GeyserListener::bind(&stale).await.is_ok());
+
+        let file_path = dir.path().join("file");
+        std::fs::write(&file_path, "keep me").unwrap();
+        let err = GeyserListener::bind(&GeyserAddress::Unix(file_path.clone()))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "keep me");
+    }
+
+    /// Test to verify that a stand-in plugin streaming over TCP and over a Unix socket
+    /// has the transactions of the watched address stored, skipping other frames.
+    #[tokio::test]
+    async fn test_geyser_stream_stores_watched_transactions() {
+        let (db, dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        // No RPC call is needed, so the endpoint is never reached
+        let aggregator = Arc::new(Aggregator::new(
+            &["http://127.0.0.1:1".to_string()],
+            db.clone(),
+            AggregatorConfig::default(),
+        ));
+        let source = Arc::new(GeyserSource::new(aggregator, vec!["watched".to_string()]));
+
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_address = tcp.local_addr().unwrap();
+        tokio::spawn(GeyserListener::Tcp(tcp).run(source.clone()));
+        let socket_path = dir.path().join("geyser.sock");
+        let unix = GeyserListener::bind(&GeyserAddress::Unix(socket_path.clone()))
+            .await
+            .unwrap();
+        tokio::spawn(unix.run(source));
+
+        let frames = |signature: &str| {
+            let mut bytes = frame(b"{\"not\": \"a transaction\"}");
+            for (signature, sender) in [(signature, "watched"), ("other_sig", "sender")] {
+                let transaction = mock_rpc::transaction(signature, sender, "receiver");
+                bytes.extend(frame(transaction.to_string().as_bytes()));
+            }
+            bytes
+        };
+        let mut stream = TcpStream::connect(tcp_address).await.unwrap();
+        stream.write_all(&frames("tcp_sig")).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+        stream.write_all(&frames("unix_sig")).await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        let stored = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let stored = db.get_transactions("watched").await;
+                if stored.len() == 2 {
+                    return stored;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("Transactions were not stored");
+
+        let mut signatures: Vec<&str> = stored.iter().map(|tx| tx.signature.as_str()).collect();
+        signatures.sort();
+        assert_eq!(signatures, vec!["tcp_sig", "unix_sig"]);
+        assert!(stored
+            .iter()
+            .all(|tx| tx.commitment == Commitment::Processed
+                && tx.endpoint.as_deref() == Some("geyser")));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::db::ProgramInvocation;
+use solana_sdk::bs58;
+use solana_transaction_status::{
+    UiInnerInstructions, UiInstruction, UiMessage, UiParsedInstruction, UiTransactionStatusMeta,
+};
+
+/// Stack height of top-level instructions, as reported by the runtime.
+const TOP_LEVEL_STACK_HEIGHT: u32 = 1;
+
+/// An instruction executed by a transaction, either a top-level instruction or one
+/// invoked through a cross-program invocation (CPI).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionRecord {
+    pub index: usize, // Index of the top-level instruction this instruction belongs to
+    pub stack_height: u32, // 1 for top-level instructions, greater for inner instructions
+    pub program_id: String, // Program invoked by the instruction
+    pub accounts: Vec<String>, // Accounts passed to the instruction, in order
+    pub data: Vec<u8>, // Raw instruction data
+}
+
+/// Flattens the top-level and inner instructions of a transaction into execution order.
+///
+/// # Arguments
+///
+/// * `message` - The message of the transaction.
+/// * `meta` - The status metadata of the transaction, which carries the inner instructions.
+/// * `account_keys` - The account keys of the transaction, including loaded addresses.
+///
+/// # Returns
+///
+/// A vector of `InstructionRecord`, each top-level instruction followed by the inner
+/// instructions it invoked.
+pub fn flatten_instructions(
+    message: &UiMessage,
+    meta: &UiTransactionStatusMeta,
+    account_keys: &[String],
+) -> Vec<InstructionRecord> {
+    let top_level = match message {
+        UiMessage::Parsed(parsed_message) => parsed_message
+            .instructions
+            .iter()
+            .map(|instruction| to_record(instruction, account_keys))
+            .collect::<Vec<_>>(),
+        UiMessage::Raw(raw_message) => raw_message
+            .instructions
+            .iter()
+            .map(|instruction| {
+                to_record(&UiInstruction::Compiled(instruction.clone()), account_keys)
+            })
+            .collect::<Vec<_>>(),
+    };
+    let inner_instructions: Option<&Vec<UiInnerInstructions>> =
+        meta.inner_instructions.as_ref().into();
+
+    let mut instructions = Vec::new();
+    for (index, (program_id, accounts, data, _)) in top_level.into_iter().enumerate() {
+        instructions.push(InstructionRecord {
+            index,
+            stack_height: TOP_LEVEL_STACK_HEIGHT,
+            program_id,
+            accounts,
+            data,
+        });
+
+        let inner = inner_instructions
+            .into_iter()
+            .flatten()
+            .filter(|inner| usize::from(inner.index) == index)
+            .flat_map(|inner| inner.instructions.iter());
+        for instruction in inner {
+            let (program_id, accounts, data, stack_height) = to_record(instruction, account_keys);
+            instructions.push(InstructionRecord {
+                index,
+                // Nodes before v1.14 do not report stack heights; treat those as direct CPIs
+                stack_height: stack_height.unwrap_or(TOP_LEVEL_STACK_HEIGHT + 1),
+                program_id,
+                accounts,
+                data,
+            });
+        }
+    }
+    instructions
+}
+
+/// Converts a UI instruction into its program ID, accounts, data and stack height.
+fn to_record(
+    instruction: &UiInstruction,
+    account_keys: &[String],
+) -> (String, Vec<String>, Vec<u8>, Option<u32>) {
+    let key = |index: u8| {
+        account_keys
+            .get(usize::from(index))
+            .cloned()
+            .unwrap_or_else(|| "unknown".to_string())
+    };
+    let decode = |data: &str| bs58::decode(data).into_vec().unwrap_or_default();
+
+    match instruction {
+        UiInstruction::Compiled(compiled) => (
+            key(compiled.program_id_index),
+            compiled.accounts.iter().map(|index| key(*index)).collect(),
+            decode(&compiled.data),
+            compiled.stack_height,
+        ),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(decoded)) => (
+            decoded.program_id.clone(),
+            decoded.accounts.clone(),
+            decode(&decoded.data),
+            decoded.stack_height,
+        ),
+        // Fully parsed instructions do not expose their raw accounts and data
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => (
+            parsed.program_id.clone(),
+            Vec::new(),
+            Vec::new(),
+            parsed.stack_height,
+        ),
+    }
+}
+
+/// Collects the invocations of a program within a transaction.
+///
+/// Each instruction that invokes the program counts as one invocation. The instruction
+/// count of an invocation includes the instruction itself and every instruction it
+/// invoked through CPI, and the accounts touched are the distinct accounts passed to
+/// any of those instructions.
+///
+/// # Arguments
+///
+/// * `program_id` - The program to collect invocations for.
+/// * `instructions` - The instructions of the transaction, in execution order.
+///
+/// # Returns
+///
+/// A vector of `ProgramInvocation`, empty if the program was not invoked.
+pub fn program_invocations(
+    program_id: &str,
+    instructions: &[InstructionRecord],
+) -> Vec<ProgramInvocation> {
+    let mut invocations = Vec::new();
+    for (position, instruction) in instructions.iter().enumerate() {
+        if instruction.program_id != program_id {
+            continue;
+        }
+
+        let nested = instructions[position + 1..]
+            .iter()
+            .take_while(|nested| {
+                nested.index == instruction.index && nested.stack_height > instruction.stack_height
+            })
+            .collect::<Vec<_>>();
+
+        let mut accounts: Vec<String> = Vec::new();
+        for touched in std::iter::once(instruction).chain(nested.iter().copied()) {
+            for account in &touched.accounts {
+                if !accounts.contains(account) {
+                    accounts.push(account.clone());
+                }
+            }
+        }
+
+        invocations.push(ProgramInvocation {
+            program_id: program_id.to_string(),
+            instruction_index: instruction.index,
+            inner: instruction.stack_height > TOP_LEVEL_STACK_HEIGHT,
+            instruction_count: 1 + nested.len(),
+            accounts,
+        });
+    }
+    invocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an instruction record with the given position and program.
+    fn record(
+        index: usize,
+        stack_height: u32,
+        program_id: &str,
+        accounts: &[&str],
+    ) -> InstructionRecord {
+        InstructionRecord {
+            index,
+            stack_height,
+            program_id: program_id.to_string(),
+            accounts: accounts.iter().map(|a| a.to_string()).collect(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Test to verify that an invocation counts the instructions it invoked through CPI
+    /// and collects the distinct accounts they touched.
+    #[test]
+    fn test_program_invocations_include_nested_instructions() {
+        let instructions = vec![
+            record(0, 1, "compute_budget", &[]),
+            record(1, 1, "my_program", &["user", "vault"]),
+            record(1, 2, "token_program", &["vault", "user_ata", "user"]),
+            record(1, 2, "my_program", &["vault"]),
+            record(1, 3, "system_program", &["vault", "fee_account"]),
+            record(2, 1, "memo_program", &["user"]),
+        ];
+
+        let invocations = program_invocations("my_program", &instructions);
+
+        assert_eq!(invocations.len(), 2);
+        assert_eq!(invocations[0].instruction_index, 1);
+        assert!(!invocations[0].inner);
+        assert_eq!(invocations[0].instruction_count, 4);
+        assert_eq!(
+            invocations[0].accounts,
+            vec!["user", "vault", "user_ata", "fee_account"]
+        );
+        assert!(invocations[1].inner);
+        assert_eq!(invocations[1].instruction_count, 2);
+        assert!(program_invocations("unknown_program", &instructions).is_empty());
+    }
+}
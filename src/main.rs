@@ -1,19 +1,50 @@
 mod aggregator;
+mod amount;
+mod anchor;
 mod api;
+mod auth;
+mod batch;
+mod checkpoint;
+mod classify;
+mod consistency;
 mod db;
+mod decoder;
+mod endpoint;
+mod events;
+mod geyser;
+mod instructions;
+mod memo;
+mod metrics;
+#[cfg(test)]
+mod mock_rpc;
+mod nft;
+mod rate_limit;
+mod replay;
+mod retry;
+mod source;
+mod subscription;
+mod swap;
+mod webhook;
 
-use aggregator::Aggregator;
+use aggregator::{Aggregator, AggregatorConfig};
+use anchor::AnchorDecoder;
 use api::create_api;
+use checkpoint::SlotCheckpoint;
+use classify::ProgramRule;
 use db::InMemoryDatabase;
+use decoder::InstructionDecoder;
 use dotenv::dotenv;
 use env_logger::Env;
+use geyser::{GeyserAddress, GeyserListener, GeyserSource};
 use log::{error, info};
+use replay::Recording;
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use subscription::{websocket_url, IngestionMode, LogsSubscriber};
 use tokio::signal;
-use tokio::sync::Mutex;
 use tokio::time::Duration;
+use webhook::{WebhookAuth, WebhookReceiver};
 
 #[tokio::main]
 async fn main() {
@@ -23,26 +54,100 @@ async fn main() {
     // Load environment variables from a .env file, if present
     dotenv().ok();
 
-    // Retrieve the RPC URL and public key from environment variables
-    let rpc_url = env::var("SOLANA_RPC_URL").expect("SOLANA_RPC_URL must be set");
+    // Retrieve the RPC URLs and public key from environment variables. SOLANA_RPC_URL
+    // may list several comma-separated endpoints, the first being the primary
+    let rpc_urls = env_list("SOLANA_RPC_URL");
+    assert!(!rpc_urls.is_empty(), "SOLANA_RPC_URL must be set");
     let pub_key = env::var("SOLANA_PUBLIC_KEY").expect("SOLANA_PUBLIC_KEY must be set");
 
+    // Optional comma-separated lists of program IDs and stake accounts to index
+    // alongside the public key
+    let program_ids = env_list("SOLANA_PROGRAM_IDS");
+    let stake_accounts = env_list("SOLANA_STAKE_ACCOUNTS");
+
     // Initialize the in-memory database with a file path for persistence
     let db = Arc::new(InMemoryDatabase::new("transactions.txt".to_string()));
 
     // Load data from the file into the in-memory database
     db.load_from_file().await;
 
-    // Initialize the aggregator with the RPC URL and the database reference
-    let aggregator = Arc::new(Mutex::new(Aggregator::new(&rpc_url, db.clone())));
+    // Initialize the aggregator with the RPC URLs and the database reference
+    let config = AggregatorConfig::from_env();
+    let health_check_interval = config.health_check_interval;
+    let finality_check_interval = config.finality_check_interval;
+
+    // In subscribe mode, the transactions of the public key, watched programs and stake
+    // accounts are announced over the PubSub WebSocket of the primary endpoint, or
+    // SOLANA_WS_URL. In blocks mode, they are found by walking every block
+    let ingestion = config.ingestion;
+    let mut watched_addresses = vec![pub_key.clone()];
+    watched_addresses.extend(program_ids.iter().cloned());
+    watched_addresses.extend(stake_accounts.iter().cloned());
+    let subscriber = (ingestion == IngestionMode::Subscribe).then(|| {
+        let ws_url = env::var("SOLANA_WS_URL").unwrap_or_else(|_| websocket_url(&rpc_urls[0]));
+        LogsSubscriber::new(
+            &ws_url,
+            watched_addresses.clone(),
+            config.commitment,
+            config.retry.clone(),
+        )
+    });
+    let mut aggregator = Aggregator::new(&rpc_urls, db.clone(), config);
+
+    // Replay the RPC responses recorded in RPC_REPLAY_DIR without a network, or record
+    // the responses to RPC_RECORD_DIR
+    if let Ok(dir) = env::var("RPC_REPLAY_DIR") {
+        info!("Replaying the RPC responses recorded in {}", dir);
+        aggregator = aggregator.replaying(Recording::new(dir));
+    } else if let Ok(dir) = env::var("RPC_RECORD_DIR") {
+        info!("Recording the RPC responses to {}", dir);
+        aggregator = aggregator.recording_to(Recording::new(dir));
+    }
+
+    // Register a decoder for each Anchor IDL listed in the comma-separated ANCHOR_IDL_PATHS
+    for path in env_list("ANCHOR_IDL_PATHS") {
+        match AnchorDecoder::from_file(&path) {
+            Ok(decoder) => {
+                info!("Loaded Anchor IDL for program {}", decoder.program_id());
+                aggregator.register_decoder(decoder);
+            }
+            Err(err) => error!("Failed to load Anchor IDL {}: {}", path, err),
+        }
+    }
+
+    // Tag the transactions invoking each program listed in the comma-separated
+    // CLASSIFY_PROGRAM_RULES, written <program ID>=<kind>
+    for entry in env_list("CLASSIFY_PROGRAM_RULES") {
+        match entry.parse::<ProgramRule>() {
+            Ok(rule) => aggregator.register_rule(rule),
+            Err(err) => error!("Invalid classification rule {}: {}", entry, err),
+        }
+    }
+
+    // The aggregator only needs shared access, since the RPC client is nonblocking
+    let aggregator = Arc::new(aggregator);
 
     info!("Starting Solana Data Aggregator...");
 
     // Set up a one-shot channel for shutdown signaling
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
 
+    // Accept the transactions pushed by a provider once the webhook has credentials
+    let webhook_auth = WebhookAuth::from_env();
+    let webhook = webhook_auth.is_configured().then(|| {
+        Arc::new(WebhookReceiver::new(
+            webhook_auth,
+            aggregator.clone(),
+            watched_addresses.clone(),
+        ))
+    });
+
     // Create the API and bind it to the specified address
-    let api = create_api(db.clone());
+    // The admin endpoints are only served once ADMIN_AUTH_TOKEN is set
+    let admin_token = env::var("ADMIN_AUTH_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    let api = create_api(db.clone(), aggregator.consistency(), admin_token, webhook);
     let addr: SocketAddr = ([127, 0, 0, 1], 3030).into();
 
     // Start the Warp server with graceful shutdown capability
@@ -53,17 +158,131 @@ async fn main() {
     // Spawn the Warp server task
     let warp_server_task = tokio::spawn(warp_server_future);
 
+    // Task to periodically check the health of the RPC endpoints
+    let health_aggregator = aggregator.clone();
+    let health_task = tokio::spawn(async move {
+        loop {
+            health_aggregator.check_endpoints().await;
+            tokio::time::sleep(health_check_interval).await;
+        }
+    });
+
+    // Task to verify the sampled transactions against a second endpoint
+    let verification_aggregator = aggregator.clone();
+    let verification_task =
+        tokio::spawn(async move { verification_aggregator.run_verifications().await });
+
+    // Task to periodically promote stored transactions to finalized or retract those
+    // orphaned or rolled back
+    let finality_aggregator = aggregator.clone();
+    let finality_task = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(finality_check_interval).await;
+            match finality_aggregator.track_finality().await {
+                Ok(update) if update != Default::default() => info!(
+                    "Promoted {} transactions and retracted {} orphaned or rolled back transactions",
+                    update.promoted, update.retracted
+                ),
+                Ok(_) => {}
+                Err(err) => error!("Error tracking transaction finality: {:?}", err),
+            }
+        }
+    });
+
+    // Task to ingest the transactions announced over the PubSub WebSocket, which also
+    // fills the gaps left by disconnects
+    let subscription_task = subscriber.map(|subscriber| {
+        let subscription_aggregator = aggregator.clone();
+        tokio::spawn(async move { subscriber.run(&subscription_aggregator).await })
+    });
+
+    // Task to store the transactions streamed by a Geyser plugin to GEYSER_SOCKET
+    let geyser_task = match ingestion {
+        IngestionMode::Geyser => {
+            let address: GeyserAddress = env::var("GEYSER_SOCKET")
+                .expect("GEYSER_SOCKET must be set in geyser mode")
+                .parse()
+                .expect("GEYSER_SOCKET must be a tcp:// or unix:// address");
+            let listener = GeyserListener::bind(&address)
+                .await
+                .expect("Unable to bind GEYSER_SOCKET");
+            info!("Listening for Geyser streams on {:?}", address);
+            let source = Arc::new(GeyserSource::new(
+                aggregator.clone(),
+                watched_addresses.clone(),
+            ));
+            Some(tokio::spawn(listener.run(source)))
+        }
+        _ => None,
+    };
+
+    // Task to walk the blocks since the last processed slot, without pausing while
+    // catching up after downtime
+    let block_task = (ingestion == IngestionMode::Blocks).then(|| {
+        let block_aggregator = aggregator.clone();
+        let checkpoint = SlotCheckpoint::new("block_checkpoint.txt".to_string());
+        tokio::spawn(async move {
+            loop {
+                match block_aggregator
+                    .stream_blocks(&watched_addresses, &checkpoint)
+                    .await
+                {
+                    Ok(progress) if !progress.caught_up => continue,
+                    Ok(_) => {}
+                    Err(err) => error!("Error streaming blocks: {:?}", err),
+                }
+                // About five slots
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        })
+    });
+
     // Task to periodically fetch recent transactions from the Solana blockchain
     let fetch_task = tokio::spawn(async move {
         loop {
-            let locked_aggregator = aggregator.lock().await;
-            match locked_aggregator.fetch_recent_transactions(&pub_key).await {
-                Ok(transactions) => {
-                    let limited_transactions =
-                        &transactions[..std::cmp::min(5, transactions.len())];
-                    info!("Fetched {} transactions", limited_transactions.len());
+            // Unless they are subscribed to, found in blocks or streamed, poll the public
+            // key, the watched programs and the stake instructions of the watched stake
+            // accounts
+            if ingestion == IngestionMode::Poll {
+                match aggregator.fetch_recent_transactions(&pub_key).await {
+                    Ok(transactions) => {
+                        let limited_transactions =
+                            &transactions[..std::cmp::min(5, transactions.len())];
+                        info!("Fetched {} transactions", limited_transactions.len());
+                    }
+                    Err(err) => error!("Error fetching transactions: {:?}", err),
+                }
+
+                // Index the transactions invoking each watched program
+                for program_id in &program_ids {
+                    match aggregator.fetch_recent_transactions(program_id).await {
+                        Ok(transactions) => info!(
+                            "Fetched {} transactions for program {}",
+                            transactions.len(),
+                            program_id
+                        ),
+                        Err(err) => error!(
+                            "Error fetching transactions for program {}: {:?}",
+                            program_id, err
+                        ),
+                    }
+                }
+
+                for stake_account in &stake_accounts {
+                    if let Err(err) = aggregator.fetch_recent_transactions(stake_account).await {
+                        error!(
+                            "Error fetching transactions for stake account {}: {:?}",
+                            stake_account, err
+                        );
+                    }
+                }
+            }
+
+            // Inflation rewards are not transactions, so they are polled in every mode
+            if !stake_accounts.is_empty() {
+                if let Err(err) = aggregator.fetch_inflation_rewards(&stake_accounts).await {
+                    error!("Error fetching inflation rewards: {:?}", err);
                 }
-                Err(err) => error!("Error fetching transactions: {:?}", err),
             }
             tokio::time::sleep(Duration::from_secs(10)).await;
         }
@@ -81,6 +300,16 @@ async fn main() {
                 task.abort();
                 info!("Fetch task aborted");
             }
+            health_task.abort();
+            verification_task.abort();
+            finality_task.abort();
+            for task in subscription_task
+                .into_iter()
+                .chain(block_task)
+                .chain(geyser_task)
+            {
+                task.abort();
+            }
 
             // Send a shutdown signal to the Warp server
             let _ = shutdown_tx.send(());
@@ -98,3 +327,23 @@ async fn main() {
 
     info!("Shutdown process finished.");
 }
+
+/// Reads a comma-separated list from an environment variable.
+///
+/// # Arguments
+///
+/// * `name` - The name of the environment variable.
+///
+/// # Returns
+///
+/// The trimmed, non-empty entries of the list, or an empty vector if the variable is not set.
+fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|list| {
+            list.split(',')
+                .map(|entry| entry.trim().to_string())
+                .filter(|entry| !entry.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
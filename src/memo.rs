@@ -0,0 +1,121 @@
+use crate::decoder::{program_log_lines, MEMO_PROGRAM_ID, MEMO_V1_PROGRAM_ID};
+use crate::events::TransactionEvent;
+
+/// Prefix of the log line the SPL Memo program writes for each memo.
+const MEMO_LOG_PREFIX: &str = "Program log: Memo (len ";
+
+/// Extracts the memos attached to a transaction.
+///
+/// Memos are taken from the decoded Memo program instructions and from the log lines
+/// the Memo program writes, since the instruction data is not available for every
+/// transaction encoding. Memos found in both places are only reported once.
+///
+/// Log lines are only read while a Memo program is the executing program, since any
+/// other program can log the same text.
+///
+/// # Arguments
+///
+/// * `events` - The events decoded from the transaction's instructions.
+/// * `logs` - The log messages of the transaction.
+///
+/// # Returns
+///
+/// A vector of memo strings, in execution order.
+pub fn extract_memos(events: &[TransactionEvent], logs: &[String]) -> Vec<String> {
+    let mut memos: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            TransactionEvent::Memo { memo } => Some(memo.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let memo_programs = [MEMO_PROGRAM_ID.to_string(), MEMO_V1_PROGRAM_ID.to_string()];
+    for (program_id, log) in program_log_lines(logs) {
+        if !memo_programs.iter().any(|memo| memo == program_id) {
+            continue;
+        }
+        if let Some(memo) = parse_memo_log(log) {
+            if !memos.contains(&memo) {
+                memos.push(memo);
+            }
+        }
+    }
+    memos
+}
+
+/// Parses a Memo program log line of the form `Program log: Memo (len 6): "INV-42"`.
+///
+/// The Memo program logs the memo with Rust's `Debug` formatting, so the quoted string
+/// is unescaped before it is returned.
+fn parse_memo_log(log: &str) -> Option<String> {
+    let rest = log.strip_prefix(MEMO_LOG_PREFIX)?;
+    let (_, quoted) = rest.split_once("): ")?;
+    let quoted = quoted.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut memo = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            memo.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => memo.push('\n'),
+            'r' => memo.push('\r'),
+            't' => memo.push('\t'),
+            '0' => memo.push('\0'),
+            'u' => {
+                // Escaped code points are written as \u{XXXX}
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                memo.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+            }
+            other => memo.push(other),
+        }
+    }
+    Some(memo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test to verify that memos are extracted from decoded instructions and log lines
+    /// without duplicates, with escaped characters restored.
+    #[test]
+    fn test_extract_memos() {
+        let events = vec![TransactionEvent::Memo {
+            memo: "INV-42".to_string(),
+        }];
+        let logs = vec![
+            "Program MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr invoke [1]".to_string(),
+            "Program log: Memo (len 6): \"INV-42\"".to_string(),
+            "Program log: Memo (len 14): \"say \\\"hi\\\"\\n\\u{1f600}\"".to_string(),
+            "Program MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr success".to_string(),
+            "Program log: Instruction: Transfer".to_string(),
+        ];
+
+        assert_eq!(
+            extract_memos(&events, &logs),
+            vec!["INV-42".to_string(), "say \"hi\"\n\u{1f600}".to_string()]
+        );
+    }
+
+    /// Test to verify that a memo line logged by a program other than the Memo
+    /// program, including one invoked by the Memo program's caller, is ignored.
+    #[test]
+    fn test_extract_memos_ignores_forged_log_lines() {
+        let logs = vec![
+            "Program FakeProgram11111111111111111111111111111111 invoke [1]".to_string(),
+            "Program log: Memo (len 6): \"INV-99\"".to_string(),
+            "Program Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo invoke [2]".to_string(),
+            "Program log: Memo (len 6): \"INV-42\"".to_string(),
+            "Program Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo success".to_string(),
+            "Program log: Memo (len 6): \"INV-77\"".to_string(),
+            "Program FakeProgram11111111111111111111111111111111 success".to_string(),
+            "Program log: Memo (len 6): \"INV-88\"".to_string(),
+        ];
+
+        assert_eq!(extract_memos(&[], &logs), vec!["INV-42".to_string()]);
+    }
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters describing the health of the aggregator's RPC traffic.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub rpc_retries: AtomicU64, // RPC calls retried after a transient error
+    pub rpc_retries_exhausted: AtomicU64, // RPC calls that still failed after the last retry
+    pub rpc_permanent_errors: AtomicU64, // RPC calls that failed with an error not worth retrying
+}
+
+/// A point-in-time copy of the metrics, served by the `/metrics` endpoint.
+#[derive(Debug, Serialize)]
+pub struct MetricsSnapshot {
+    pub rpc_retries: u64,
+    pub rpc_retries_exhausted: u64,
+    pub rpc_permanent_errors: u64,
+}
+
+/// The metrics of the process.
+pub static METRICS: Metrics = Metrics {
+    rpc_retries: AtomicU64::new(0),
+    rpc_retries_exhausted: AtomicU64::new(0),
+    rpc_permanent_errors: AtomicU64::new(0),
+};
+
+impl Metrics {
+    /// Increments a counter by one.
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of every counter.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            rpc_retries: self.rpc_retries.load(Ordering::Relaxed),
+            rpc_retries_exhausted: self.rpc_retries_exhausted.load(Ordering::Relaxed),
+            rpc_permanent_errors: self.rpc_permanent_errors.load(Ordering::Relaxed),
+        }
+    }
+}
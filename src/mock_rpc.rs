@@ -0,0 +1,396 @@
+//! A local stand-in for a Solana JSON-RPC node and its PubSub WebSocket, used by tests
+//! that exercise the aggregator's RPC calls and subscriptions end to end.
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+use warp::Filter;
+
+/// The reply of the mock node to a single JSON-RPC request.
+pub struct MockReply {
+    pub result: Value,            // Value returned as the `result` of the request
+    pub error: Option<Value>,     // JSON-RPC error returned instead of the result, if any
+    pub delay: Duration,          // Time to wait before replying
+    pub status: u16,              // HTTP status of the reply
+    pub retry_after: Option<u64>, // Seconds sent in a `Retry-After` header, if any
+}
+
+impl MockReply {
+    /// Replies with the given result right away.
+    pub fn ok(result: Value) -> Self {
+        Self {
+            result,
+            error: None,
+            delay: Duration::ZERO,
+            status: 200,
+            retry_after: None,
+        }
+    }
+
+    /// Replies with a JSON-RPC error right away.
+    pub fn error(code: i64, message: &str) -> Self {
+        Self {
+            error: Some(json!({ "code": code, "message": message })),
+            ..Self::ok(Value::Null)
+        }
+    }
+
+    /// Delays the reply by the given number of milliseconds.
+    pub fn delayed(mut self, millis: u64) -> Self {
+        self.delay = Duration::from_millis(millis);
+        self
+    }
+
+    /// Fails the HTTP request carrying the reply with the given status, optionally
+    /// asking the client to retry after a number of seconds.
+    pub fn http_status(status: u16, retry_after: Option<u64>) -> Self {
+        Self {
+            status,
+            retry_after,
+            ..Self::ok(Value::Null)
+        }
+    }
+}
+
+/// Handler mapping a JSON-RPC method and its params to the reply of the mock node.
+type Handler = dyn Fn(&str, &Value) -> MockReply + Send + Sync;
+
+/// A mock JSON-RPC node listening on a local port.
+pub struct MockRpc {
+    pub url: String, // URL of the mock node
+    state: State,    // State shared with the server
+}
+
+/// State shared between the mock node and its server.
+#[derive(Clone)]
+struct State {
+    handler: Arc<Handler>,            // Replies to each request
+    methods: Arc<Mutex<Vec<String>>>, // Methods of the requests received, in order
+    http_requests: Arc<AtomicUsize>,  // HTTP requests received, excluding `getVersion`
+    in_flight: Arc<AtomicUsize>,      // HTTP requests currently being answered
+    max_in_flight: Arc<AtomicUsize>,  // Highest number of HTTP requests answered at once
+}
+
+impl MockRpc {
+    /// Starts a mock node answering each request with the handler. Batch requests are
+    /// answered with one response per request, after the longest delay of the batch.
+    /// `getVersion`, which the RPC client queries before some requests, is answered by
+    /// the node itself.
+    pub async fn start(
+        handler: impl Fn(&str, &Value) -> MockReply + Send + Sync + 'static,
+    ) -> Self {
+        let state = State {
+            handler: Arc::new(handler),
+            methods: Arc::new(Mutex::new(Vec::new())),
+            http_requests: Arc::new(AtomicUsize::new(0)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let server_state = state.clone();
+        let route = warp::post()
+            .and(warp::body::json())
+            .and(warp::any().map(move || server_state.clone()))
+            .then(|body: Value, state: State| async move {
+                let (body, status, retry_after) = state.answer(body).await;
+                let reply = warp::reply::with_status(
+                    warp::reply::json(&body),
+                    warp::http::StatusCode::from_u16(status).unwrap(),
+                );
+                warp::reply::with_header(
+                    reply,
+                    "Retry-After",
+                    retry_after.map(|s| s.to_string()).unwrap_or_default(),
+                )
+            });
+
+        let (addr, server): (SocketAddr, _) =
+            warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        Self {
+            url: format!("http://{}", addr),
+            state,
+        }
+    }
+
+    /// Returns the number of HTTP requests received, excluding `getVersion`.
+    pub fn http_requests(&self) -> usize {
+        self.state.http_requests.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of requests received for a method.
+    pub fn calls(&self, method: &str) -> usize {
+        self.state
+            .methods
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|m| *m == method)
+            .count()
+    }
+
+    /// Returns the highest number of requests the node was answering at once.
+    pub fn max_in_flight(&self) -> usize {
+        self.state.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+impl State {
+    /// Answers the body of an HTTP request, either a single request or a batch, with
+    /// the response body, the HTTP status and the `Retry-After` seconds. A failing HTTP
+    /// status of any reply fails the whole request.
+    async fn answer(&self, body: Value) -> (Value, u16, Option<u64>) {
+        let (requests, batch) = match body {
+            Value::Array(requests) => (requests, true),
+            request => (vec![request], false),
+        };
+
+        let mut delay = Duration::ZERO;
+        let (mut status, mut retry_after) = (200, None);
+        let mut responses = Vec::new();
+        for request in &requests {
+            let method = request["method"].as_str().unwrap_or_default();
+            let reply = if method == "getVersion" {
+                MockReply::ok(json!({ "solana-core": "1.18.22", "feature-set": 0 }))
+            } else {
+                self.methods.lock().unwrap().push(method.to_string());
+                (self.handler)(method, &request["params"])
+            };
+            delay = delay.max(reply.delay);
+            if reply.status != 200 {
+                (status, retry_after) = (reply.status, reply.retry_after);
+            }
+
+            let mut response = json!({ "jsonrpc": "2.0", "id": request["id"] });
+            match reply.error {
+                Some(error) => response["error"] = error,
+                None => response["result"] = reply.result,
+            }
+            responses.push(response);
+        }
+
+        if requests.iter().any(|r| r["method"] != "getVersion") {
+            self.http_requests.fetch_add(1, Ordering::SeqCst);
+            let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(delay).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        let body = match batch {
+            true => Value::Array(responses),
+            false => responses.pop().unwrap_or_default(),
+        };
+        (body, status, retry_after)
+    }
+}
+
+/// A mock PubSub WebSocket listening on a local port.
+pub struct MockPubsub {
+    pub url: String,               // URL of the mock WebSocket
+    connections: Arc<AtomicUsize>, // Connections accepted so far
+}
+
+impl MockPubsub {
+    /// Starts a mock WebSocket answering each subscription request. Each connection
+    /// follows a session of the script: once the first subscription is made, a
+    /// `logsNotification` is sent for each signature of the session. Every session but
+    /// the last one is then closed by the server, while the last one stays open.
+    pub async fn start(sessions: Vec<Vec<String>>) -> Self {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let sessions = Arc::new(sessions);
+
+        let server_connections = connections.clone();
+        let route = warp::ws().map(move |ws: warp::ws::Ws| {
+            let session = server_connections.fetch_add(1, Ordering::SeqCst);
+            let sessions = sessions.clone();
+            ws.on_upgrade(move |socket| async move {
+                let signatures = sessions.get(session).cloned().unwrap_or_default();
+                let last = session + 1 >= sessions.len();
+                serve_pubsub(socket, signatures, last).await
+            })
+        });
+
+        let (addr, server): (SocketAddr, _) =
+            warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        Self {
+            url: format!("ws://{}", addr),
+            connections,
+        }
+    }
+
+    /// Returns the number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+/// Serves a single connection of the mock WebSocket, announcing the given signatures
+/// once the first subscription is made and closing afterwards unless `last`.
+async fn serve_pubsub(mut socket: warp::ws::WebSocket, signatures: Vec<String>, last: bool) {
+    let mut subscriptions = 0;
+    while let Some(Ok(message)) = socket.next().await {
+        let Ok(text) = message.to_str() else {
+            continue;
+        };
+        let request: Value = serde_json::from_str(text).unwrap_or_default();
+        subscriptions += 1;
+        let reply = json!({ "jsonrpc": "2.0", "result": subscriptions, "id": request["id"] });
+        if socket
+            .send(warp::ws::Message::text(reply.to_string()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        if subscriptions > 1 {
+            continue;
+        }
+
+        for signature in &signatures {
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "logsNotification",
+                "params": {
+                    "result": {
+                        "context": { "slot": 1000 },
+                        "value": { "signature": signature, "err": null, "logs": [] }
+                    },
+                    "subscription": subscriptions
+                }
+            });
+            let _ = socket
+                .send(warp::ws::Message::text(notification.to_string()))
+                .await;
+        }
+        if !last {
+            let _ = socket.close().await;
+            return;
+        }
+    }
+}
+
+/// Returns the result of `getEpochInfo` for an epoch that started at slot 1000.
+pub fn epoch_info() -> Value {
+    json!({
+        "absoluteSlot": 1000,
+        "blockHeight": 1000,
+        "epoch": 500,
+        "slotIndex": 0,
+        "slotsInEpoch": 432000,
+        "transactionCount": null
+    })
+}
+
+/// Returns the result of `getSignaturesForAddress` listing the given signatures.
+pub fn signatures(signatures: &[String]) -> Value {
+    Value::Array(
+        signatures
+            .iter()
+            .map(|signature| {
+                json!({
+                    "signature": signature,
+                    "slot": 1000,
+                    "err": null,
+                    "memo": null,
+                    "blockTime": 1700000000,
+                    "confirmationStatus": "finalized"
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Returns a handler answering the calls made to fetch the recent transactions of an
+/// address: the epoch of `epoch_info`, its block time, the listing of the given
+/// signatures, and for each of them a transfer from `sender` to `receiver`. Other
+/// methods are answered with `null`, so tests only match the calls they change.
+///
+/// # Arguments
+///
+/// * `signatures` - The signatures listed by `getSignaturesForAddress`, newest first.
+/// * `sender` - The fee payer of the transfers.
+pub fn transfer_handler(
+    signatures: Vec<String>,
+    sender: &str,
+) -> impl Fn(&str, &Value) -> MockReply + Send + Sync + 'static {
+    let sender = sender.to_string();
+    move |method, params| match method {
+        "getEpochInfo" => MockReply::ok(epoch_info()),
+        "getBlockTime" => MockReply::ok(json!(1700000000)),
+        "getSignaturesForAddress" => MockReply::ok(self::signatures(&signatures)),
+        "getTransaction" => MockReply::ok(transaction(
+            params[0].as_str().unwrap_or_default(),
+            &sender,
+            "receiver",
+        )),
+        _ => MockReply::ok(Value::Null),
+    }
+}
+
+/// Returns the result of `getTransaction` for a transfer of 500000 lamports from the
+/// fee payer `sender` to `receiver`.
+pub fn transaction(signature: &str, sender: &str, receiver: &str) -> Value {
+    json!({
+        "slot": 1000,
+        "blockTime": 1700000000,
+        "version": "legacy",
+        "transaction": {
+            "signatures": [signature],
+            "message": {
+                "header": {
+                    "numRequiredSignatures": 1,
+                    "numReadonlySignedAccounts": 0,
+                    "numReadonlyUnsignedAccounts": 1
+                },
+                "accountKeys": [sender, receiver, "11111111111111111111111111111111"],
+                "recentBlockhash": "mock_blockhash",
+                "instructions": [{
+                    "programIdIndex": 2,
+                    "accounts": [0, 1],
+                    "data": "3Bxs46KChmhFZqno",
+                    "stackHeight": null
+                }]
+            }
+        },
+        "meta": {
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 5000,
+            "preBalances": [1000000, 0, 1],
+            "postBalances": [495000, 500000, 1],
+            "innerInstructions": [],
+            "logMessages": [],
+            "preTokenBalances": [],
+            "postTokenBalances": [],
+            "rewards": [],
+            "computeUnitsConsumed": 150
+        }
+    })
+}
+
+/// Returns the result of `getBlock` for the block at the given slot, holding the given
+/// results of `getTransaction`.
+pub fn block(slot: u64, transactions: &[Value]) -> Value {
+    json!({
+        "previousBlockhash": "mock_previous_blockhash",
+        "blockhash": "mock_blockhash",
+        "parentSlot": slot - 1,
+        "transactions": transactions
+            .iter()
+            .map(|transaction| json!({
+                "transaction": transaction["transaction"],
+                "meta": transaction["meta"],
+                "version": transaction["version"]
+            }))
+            .collect::<Vec<_>>(),
+        "blockTime": 1700000000,
+        "blockHeight": slot
+    })
+}
@@ -0,0 +1,298 @@
+use crate::events::TransactionEvent;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{UiTransactionStatusMeta, UiTransactionTokenBalance};
+use std::str::FromStr;
+
+/// Program ID of the Metaplex Token Metadata program.
+pub const METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+/// The fields of a Metaplex metadata account used to describe an NFT.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NftMetadata {
+    pub name: Option<String>,       // Name of the NFT
+    pub collection: Option<String>, // Mint of the collection the NFT belongs to, if any
+}
+
+/// Derives the address of the Metaplex metadata account of a mint.
+///
+/// # Arguments
+///
+/// * `mint` - The mint of the NFT.
+///
+/// # Returns
+///
+/// The program derived address `["metadata", program ID, mint]` of the metadata program.
+pub fn metadata_address(mint: &Pubkey) -> Pubkey {
+    let program_id =
+        Pubkey::from_str(METADATA_PROGRAM_ID).expect("metadata program ID is a valid pubkey");
+    Pubkey::find_program_address(
+        &[b"metadata", program_id.as_ref(), mint.as_ref()],
+        &program_id,
+    )
+    .0
+}
+
+/// Parses the name and collection out of the data of a Metaplex metadata account.
+///
+/// The account starts with a key byte, the update authority and the mint, followed by
+/// the name, symbol and URI as Borsh strings padded with NUL bytes. The collection
+/// comes after the creators and a few flags, and is missing from accounts written
+/// before collections were introduced.
+///
+/// # Arguments
+///
+/// * `data` - The raw data of the metadata account.
+///
+/// # Returns
+///
+/// The `NftMetadata`, or `None` if the data is not a metadata account.
+pub fn parse_metadata(data: &[u8]) -> Option<NftMetadata> {
+    let mut reader = Reader { data };
+    reader.take(1 + 32 + 32)?; // key, update authority, mint
+    let name = reader.read_string()?;
+    reader.read_string()?; // symbol
+    reader.read_string()?; // uri
+    reader.take(2)?; // seller fee basis points
+
+    // The rest is optional on older accounts
+    let collection = (|| {
+        if reader.read_bool()? {
+            let creators = reader.read_u32()? as usize;
+            reader.take(creators.checked_mul(32 + 1 + 1)?)?; // address, verified, share
+        }
+        reader.take(2)?; // primary sale happened, is mutable
+        if reader.read_bool()? {
+            reader.take(1)?; // edition nonce
+        }
+        if reader.read_bool()? {
+            reader.take(1)?; // token standard
+        }
+        if !reader.read_bool()? {
+            return None;
+        }
+        reader.take(1)?; // verified
+        Some(Pubkey::try_from(reader.take(32)?).ok()?.to_string())
+    })();
+
+    Some(NftMetadata {
+        name: Some(name).filter(|name| !name.is_empty()),
+        collection,
+    })
+}
+
+/// Detects the NFTs minted or transferred by a transaction from its token balances.
+///
+/// A token is treated as a candidate NFT when its mint has 0 decimals and no account
+/// holds more than one unit of it. Whether the supply of the mint is exactly one can
+/// only be checked against the chain, so the events returned here are candidates that
+/// the aggregator confirms and enriches with the metadata of the mint.
+///
+/// # Arguments
+///
+/// * `meta` - The status metadata of the transaction.
+///
+/// # Returns
+///
+/// A vector of `NftMint` and `NftTransfer` events without name or collection.
+pub fn detect_nft_movements(meta: &UiTransactionStatusMeta) -> Vec<TransactionEvent> {
+    let pre_token_balances: Option<&Vec<UiTransactionTokenBalance>> =
+        meta.pre_token_balances.as_ref().into();
+    let post_token_balances: Option<&Vec<UiTransactionTokenBalance>> =
+        meta.post_token_balances.as_ref().into();
+
+    // Per mint, the balance change of each owner
+    let mut mints: Vec<(String, Vec<(String, i64)>)> = Vec::new();
+    let mut fungible: Vec<String> = Vec::new();
+    let pre = pre_token_balances.into_iter().flatten().map(|b| (b, -1));
+    let post = post_token_balances.into_iter().flatten().map(|b| (b, 1));
+    for (balance, sign) in pre.chain(post) {
+        let raw = balance.ui_token_amount.amount.parse::<u64>().unwrap_or(0);
+        if balance.ui_token_amount.decimals != 0 || raw > 1 {
+            fungible.push(balance.mint.clone());
+            continue;
+        }
+        let owner: Option<&String> = balance.owner.as_ref().into();
+        let Some(owner) = owner else {
+            continue;
+        };
+        let index = match mints.iter().position(|(mint, _)| *mint == balance.mint) {
+            Some(index) => index,
+            None => {
+                mints.push((balance.mint.clone(), Vec::new()));
+                mints.len() - 1
+            }
+        };
+        let owners = &mut mints[index].1;
+        match owners.iter_mut().find(|(o, _)| o == owner) {
+            Some((_, delta)) => *delta += sign * raw as i64,
+            None => owners.push((owner.clone(), sign * raw as i64)),
+        }
+    }
+
+    mints
+        .into_iter()
+        .filter(|(mint, _)| !fungible.contains(mint))
+        .filter_map(|(mint, owners)| {
+            let from = owners.iter().find(|(_, delta)| *delta < 0);
+            let to = owners.iter().find(|(_, delta)| *delta > 0)?;
+            Some(match from {
+                Some((from, _)) => TransactionEvent::NftTransfer {
+                    mint,
+                    from: from.clone(),
+                    to: to.0.clone(),
+                    name: None,
+                    collection: None,
+                },
+                None => TransactionEvent::NftMint {
+                    mint,
+                    owner: to.0.clone(),
+                    name: None,
+                    collection: None,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Reads the Borsh-encoded fields of a metadata account.
+struct Reader<'a> {
+    data: &'a [u8], // Remaining bytes to read
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.data.split_at_checked(len)?;
+        self.data = rest;
+        Some(taken)
+    }
+
+    fn read_bool(&mut self) -> Option<bool> {
+        Some(self.take(1)?[0] != 0)
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    /// Reads a string, dropping the NUL padding Metaplex adds to fixed-size fields.
+    fn read_string(&mut self) -> Option<String> {
+        let len = self.read_u32()? as usize;
+        let bytes = self.take(len)?;
+        Some(
+            String::from_utf8_lossy(bytes)
+                .trim_end_matches('\0')
+                .to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Borsh-encodes a string padded with NUL bytes to `len` bytes.
+    fn padded_string(value: &str, len: usize) -> Vec<u8> {
+        let mut bytes = (len as u32).to_le_bytes().to_vec();
+        bytes.extend(value.as_bytes());
+        bytes.resize(4 + len, 0);
+        bytes
+    }
+
+    /// Test to verify that the name and collection are read from a metadata account.
+    #[test]
+    fn test_parse_metadata() {
+        let collection = Pubkey::new_unique();
+        let mut data = vec![4];
+        data.extend([1; 32]); // update authority
+        data.extend([2; 32]); // mint
+        data.extend(padded_string("Mad Lad #42", 32));
+        data.extend(padded_string("MAD", 10));
+        data.extend(padded_string("https://example.com/42.json", 200));
+        data.extend(500u16.to_le_bytes());
+        data.extend([1, 1, 0, 0, 0]); // one creator
+        data.extend([3; 32]);
+        data.extend([1, 100]);
+        data.extend([1, 1]); // primary sale happened, is mutable
+        data.extend([1, 254]); // edition nonce
+        data.extend([1, 0]); // token standard
+        data.extend([1, 1]); // verified collection
+        data.extend(collection.to_bytes());
+
+        assert_eq!(
+            parse_metadata(&data),
+            Some(NftMetadata {
+                name: Some("Mad Lad #42".to_string()),
+                collection: Some(collection.to_string()),
+            })
+        );
+
+        // Accounts written before collections existed end after the flags
+        let legacy = &data[..data.len() - (2 + 32) - 2 - 2];
+        assert_eq!(
+            parse_metadata(legacy),
+            Some(NftMetadata {
+                name: Some("Mad Lad #42".to_string()),
+                collection: None,
+            })
+        );
+    }
+
+    /// Test to verify that a 0-decimal token moving between owners is detected as an
+    /// NFT transfer, while fungible tokens are ignored.
+    #[test]
+    fn test_detect_nft_movements() {
+        let meta: UiTransactionStatusMeta = serde_json::from_value(serde_json::json!({
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 5000,
+            "preBalances": [],
+            "postBalances": [],
+            "preTokenBalances": [
+                {
+                    "accountIndex": 1,
+                    "mint": "nft_mint",
+                    "owner": "alice",
+                    "uiTokenAmount": { "amount": "1", "decimals": 0, "uiAmount": 1.0, "uiAmountString": "1" }
+                },
+                {
+                    "accountIndex": 3,
+                    "mint": "usdc_mint",
+                    "owner": "alice",
+                    "uiTokenAmount": { "amount": "5000000", "decimals": 6, "uiAmount": 5.0, "uiAmountString": "5" }
+                }
+            ],
+            "postTokenBalances": [
+                {
+                    "accountIndex": 1,
+                    "mint": "nft_mint",
+                    "owner": "alice",
+                    "uiTokenAmount": { "amount": "0", "decimals": 0, "uiAmount": null, "uiAmountString": "0" }
+                },
+                {
+                    "accountIndex": 2,
+                    "mint": "nft_mint",
+                    "owner": "bob",
+                    "uiTokenAmount": { "amount": "1", "decimals": 0, "uiAmount": 1.0, "uiAmountString": "1" }
+                },
+                {
+                    "accountIndex": 3,
+                    "mint": "usdc_mint",
+                    "owner": "alice",
+                    "uiTokenAmount": { "amount": "4000000", "decimals": 6, "uiAmount": 4.0, "uiAmountString": "4" }
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            detect_nft_movements(&meta),
+            vec![TransactionEvent::NftTransfer {
+                mint: "nft_mint".to_string(),
+                from: "alice".to_string(),
+                to: "bob".to_string(),
+                name: None,
+                collection: None,
+            }]
+        );
+    }
+}
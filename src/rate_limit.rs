@@ -0,0 +1,143 @@
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// The request budget of an RPC endpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: f64, // Sustained rate of requests allowed
+    pub burst: u32,               // Requests allowed at once after a quiet period
+}
+
+/// The tokens left in a bucket. The count goes negative when callers have reserved
+/// tokens that are not refilled yet.
+struct Bucket {
+    tokens: f64,          // Tokens available, or owed when negative
+    refilled_at: Instant, // Time of the last refill
+}
+
+/// A token-bucket rate limiter applying backpressure to the calls of one endpoint.
+///
+/// Each request takes one token. Tokens are refilled at the configured rate up to the
+/// burst size, and a caller finding the bucket empty waits for its tokens instead of
+/// failing, so a busy poll loop slows down to the budget of the endpoint.
+pub struct RateLimiter {
+    limit: RateLimit,      // Rate and burst size of the bucket
+    bucket: Mutex<Bucket>, // Tokens left, shared by all callers
+}
+
+impl RateLimiter {
+    /// Creates a limiter with a full bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The request budget of the endpoint.
+    ///
+    /// # Returns
+    ///
+    /// The limiter, or `None` if the rate is not a positive number, which means that
+    /// there is no limit.
+    pub fn new(limit: RateLimit) -> Option<Self> {
+        if !(limit.requests_per_second.is_finite() && limit.requests_per_second > 0.0) {
+            return None;
+        }
+        Some(Self {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(limit.burst.max(1)),
+                refilled_at: Instant::now(),
+            }),
+        })
+    }
+
+    /// Waits until the given number of requests fits in the budget.
+    ///
+    /// Tokens are reserved before waiting, so concurrent callers are served in the
+    /// order they arrived. A batch larger than the burst size is let through once the
+    /// tokens it owes have been refilled.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The number of requests about to be sent, e.g. the size of a batch.
+    pub async fn acquire(&self, requests: u32) {
+        let wait = self.reserve(requests, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes tokens from the bucket and returns how long the caller must wait before
+    /// sending its requests.
+    fn reserve(&self, requests: u32, now: Instant) -> Duration {
+        let rate = self.limit.requests_per_second;
+        let burst = f64::from(self.limit.burst.max(1));
+
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst);
+        bucket.refilled_at = now;
+        bucket.tokens -= f64::from(requests);
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test to verify that a burst is let through at once and that the requests after
+    /// it wait for tokens refilled at the configured rate.
+    #[test]
+    fn test_reserve_tokens() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 4.0,
+            burst: 2,
+        })
+        .unwrap();
+        let start = Instant::now();
+
+        assert_eq!(limiter.reserve(1, start), Duration::ZERO);
+        assert_eq!(limiter.reserve(1, start), Duration::ZERO);
+        // The bucket is empty, so each request waits for its own token
+        assert_eq!(limiter.reserve(1, start), Duration::from_millis(250));
+        assert_eq!(limiter.reserve(1, start), Duration::from_millis(500));
+
+        // After a quiet period the bucket is full again, but holds no more than a burst
+        let later = start + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(2, later), Duration::ZERO);
+        assert_eq!(limiter.reserve(3, later), Duration::from_millis(750));
+    }
+
+    /// Test to verify that concurrent callers are slowed down to the configured rate.
+    #[tokio::test]
+    async fn test_acquire_applies_backpressure() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 50.0,
+            burst: 1,
+        })
+        .unwrap();
+
+        let start = Instant::now();
+        futures::future::join_all((0..6).map(|_| limiter.acquire(1))).await;
+
+        // The first request uses the burst, the other five wait 20ms each
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    /// Test to verify that a rate that is not a positive number means there is no limit,
+    /// rather than a limiter that cannot compute its delays.
+    #[test]
+    fn test_non_positive_rate_is_unlimited() {
+        for requests_per_second in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let limit = RateLimit {
+                requests_per_second,
+                burst: 1,
+            };
+            assert!(RateLimiter::new(limit).is_none());
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use crate::source::TransactionSource;
+use async_trait::async_trait;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClientConfig};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::account::Account;
+use solana_sdk::clock::{Slot, UnixTimestamp};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::epoch_info::EpochInfo;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+
+/// A directory of recorded RPC responses, one JSON file per response:
+///
+/// * `epoch_info.json` - The latest result of `getEpochInfo`.
+/// * `block_time/<slot>.json` - The result of `getBlockTime` for each slot.
+/// * `signatures/<address>/<before>-<until>-<limit>[-<n>].json` - The result of
+///   `getSignaturesForAddress` for each address and bounds, `none` standing for an
+///   unset bound. The same listing made again, e.g. by a later poll, is numbered from
+///   1, so that each listing of a run is replayed in order.
+/// * `transactions/<signature>.json` - The result of `getTransaction` for each
+///   signature.
+/// * `token_supply/<mint>.json` - The raw supply returned by `getTokenSupply` for each
+///   mint.
+/// * `accounts/<address>.json` - The result of `getAccountInfo` for each address.
+///
+/// Clones share the count of listings made, so that the sources of all endpoints
+/// record or replay one sequence.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    dir: PathBuf,                                 // Directory holding the responses
+    listings: Arc<Mutex<HashMap<String, usize>>>, // Listings made so far per address and bounds
+}
+
+impl Recording {
+    /// Opens the recording held by a directory, which is created on the first write.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the responses.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            listings: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the key of the next signature listing of an address with the given
+    /// bounds, counting it as made.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key the signatures are listed for.
+    /// * `config` - The bounds of the listing.
+    ///
+    /// # Returns
+    ///
+    /// The key of the listing, `<address>/<before>-<until>-<limit>` followed by `-<n>`
+    /// for the n-th repeat of the same listing.
+    fn next_listing(
+        &self,
+        address: &Pubkey,
+        config: &GetConfirmedSignaturesForAddress2Config,
+    ) -> String {
+        let bound = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+        let key = format!(
+            "{}/{}-{}-{}",
+            address,
+            bound(config.before.map(|signature| signature.to_string())),
+            bound(config.until.map(|signature| signature.to_string())),
+            bound(config.limit.map(|limit| limit.to_string())),
+        );
+        let mut listings = self.listings.lock().unwrap();
+        let repeats = listings.entry(key.clone()).or_default();
+        let key = match *repeats {
+            0 => key,
+            n => format!("{}-{}", key, n),
+        };
+        *repeats += 1;
+        key
+    }
+
+    /// Returns the path of the file holding a response.
+    fn path(&self, kind: &str, key: Option<&str>) -> PathBuf {
+        match key {
+            Some(key) => self.dir.join(kind).join(format!("{}.json", key)),
+            None => self.dir.join(format!("{}.json", kind)),
+        }
+    }
+
+    /// Writes a response, replacing any response previously recorded for the same
+    /// call.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of response, naming its file or directory.
+    /// * `key` - The address, signature, slot or listing the response is for, if any.
+    /// * `response` - The response to write.
+    async fn write(
+        &self,
+        kind: &str,
+        key: Option<&str>,
+        response: &impl Serialize,
+    ) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(response)?;
+        let path = self.path(kind, key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, json).await
+    }
+
+    /// Reads a recorded response.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of response, naming its file or directory.
+    /// * `key` - The address, signature, slot or listing the response is for, if any.
+    ///
+    /// # Returns
+    ///
+    /// The response, or a `ClientError` if it was not recorded or is not valid JSON.
+    async fn read<T: DeserializeOwned>(
+        &self,
+        kind: &str,
+        key: Option<&str>,
+    ) -> Result<T, ClientError> {
+        let path = self.path(kind, key);
+        let bytes = fs::read(&path).await.map_err(|err| {
+            ClientErrorKind::Custom(format!(
+                "no recorded response at {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Records a response, logging rather than failing the call if it cannot be
+    /// written.
+    async fn record(&self, kind: &str, key: Option<&str>, response: &(impl Serialize + Sync)) {
+        if let Err(err) = self.write(kind, key, response).await {
+            warn!(
+                "Failed to record {} to {}: {}",
+                kind,
+                self.dir.display(),
+                err
+            );
+        }
+    }
+}
+
+/// A source passing the calls to another source and recording each response it
+/// returns, so that the responses can be replayed offline.
+pub struct RecordingSource {
+    inner: Arc<dyn TransactionSource>, // Source answering the calls
+    recording: Recording,              // Recording the responses are written to
+}
+
+impl RecordingSource {
+    /// Creates a source recording the responses of another.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The source answering the calls, e.g. an RPC client.
+    /// * `recording` - The recording the responses are written to.
+    pub fn new(inner: Arc<dyn TransactionSource>, recording: Recording) -> Self {
+        Self { inner, recording }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for RecordingSource {
+    async fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, ClientError> {
+        let listing = self.recording.next_listing(address, &config);
+        let signatures = self
+            .inner
+            .get_signatures_for_address(address, config)
+            .await?;
+        self.recording
+            .record("signatures", Some(&listing), &signatures)
+            .await;
+        Ok(signatures)
+    }
+
+    async fn get_transaction(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, ClientError> {
+        let transaction = self.inner.get_transaction(signature, config).await?;
+        self.recording
+            .record("transactions", Some(&signature.to_string()), &transaction)
+            .await;
+        Ok(transaction)
+    }
+
+    async fn get_epoch_info(&self) -> Result<EpochInfo, ClientError> {
+        let epoch_info = self.inner.get_epoch_info().await?;
+        self.recording.record("epoch_info", None, &epoch_info).await;
+        Ok(epoch_info)
+    }
+
+    async fn get_block_time(&self, slot: Slot) -> Result<UnixTimestamp, ClientError> {
+        let block_time = self.inner.get_block_time(slot).await?;
+        self.recording
+            .record("block_time", Some(&slot.to_string()), &block_time)
+            .await;
+        Ok(block_time)
+    }
+
+    async fn get_token_supply(&self, mint: &Pubkey) -> Result<u64, ClientError> {
+        let supply = self.inner.get_token_supply(mint).await?;
+        self.recording
+            .record("token_supply", Some(&mint.to_string()), &supply)
+            .await;
+        Ok(supply)
+    }
+
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, ClientError> {
+        let account = self.inner.get_account(address).await?;
+        self.recording
+            .record("accounts", Some(&address.to_string()), &account)
+            .await;
+        Ok(account)
+    }
+}
+
+/// A source answering the calls with the responses of a recording, without a network.
+/// A call whose response was not recorded fails.
+pub struct ReplaySource {
+    recording: Recording, // Recording the responses are read from
+}
+
+impl ReplaySource {
+    /// Creates a source replaying a recording.
+    ///
+    /// # Arguments
+    ///
+    /// * `recording` - The recording the responses are read from.
+    pub fn new(recording: Recording) -> Self {
+        Self { recording }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for ReplaySource {
+    async fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, ClientError> {
+        let listing = self.recording.next_listing(address, &config);
+        self.recording.read("signatures", Some(&listing)).await
+    }
+
+    async fn get_transaction(
+        &self,
+        signature: &Signature,
+        _config: RpcTransactionConfig,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, ClientError> {
+        self.recording
+            .read("transactions", Some(&signature.to_string()))
+            .await
+    }
+
+    async fn get_epoch_info(&self) -> Result<EpochInfo, ClientError> {
+        self.recording.read("epoch_info", None).await
+    }
+
+    async fn get_block_time(&self, slot: Slot) -> Result<UnixTimestamp, ClientError> {
+        self.recording
+            .read("block_time", Some(&slot.to_string()))
+            .await
+    }
+
+    async fn get_token_supply(&self, mint: &Pubkey) -> Result<u64, ClientError> {
+        self.recording
+            .read("token_supply", Some(&mint.to_string()))
+            .await
+    }
+
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, ClientError> {
+        self.recording
+            .read("accounts", Some(&address.to_string()))
+            .await
+    }
+}
+
+/// A transport failing every RPC call, for the RPC clients of a replayed recording, so
+/// that the calls a recording does not hold, e.g. of the block stream, the finality
+/// job or the health checks, fail rather than reach the network.
+struct OfflineSender;
+
+#[async_trait]
+impl RpcSender for OfflineSender {
+    async fn send(&self, request: RpcRequest, _params: Value) -> Result<Value, ClientError> {
+        Err(ClientErrorKind::Custom(format!(
+            "{} is not recorded, so it cannot be replayed",
+            request
+        ))
+        .into())
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        "replay".to_string()
+    }
+}
+
+/// Creates an RPC client failing every call, standing in for the client of an
+/// endpoint while a recording is replayed.
+///
+/// # Arguments
+///
+/// * `commitment` - The default commitment of the client.
+pub fn offline_client(commitment: CommitmentConfig) -> RpcClient {
+    RpcClient::new_sender(OfflineSender, RpcClientConfig::with_commitment(commitment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{Aggregator, AggregatorConfig};
+    use crate::checkpoint::SlotCheckpoint;
+    use crate::db::InMemoryDatabase;
+    use crate::mock_rpc;
+    use crate::source::fixture::FixtureSource;
+    use std::path::Path;
+
+    /// Creates an aggregator on a fresh database persisted in a temporary directory,
+    /// with an endpoint that is never reached.
+    fn aggregator(dir: &Path) -> Aggregator {
+        let db = InMemoryDatabase::new(dir.join("transactions.txt").to_string_lossy().into_owned());
+        Aggregator::new(
+            &["replay".to_string()],
+            Arc::new(db),
+            AggregatorConfig::default(),
+        )
+    }
+
+    /// Test to verify that the responses recorded while fetching the transactions of
+    /// an address are replayed to the same transactions, that repeated listings are
+    /// replayed in order, and that replaying a listing that was not recorded fails.
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let address = Pubkey::new_unique().to_string();
+        let [sent, received] = [(); 2].map(|_| Signature::new_unique().to_string());
+        let fixture = FixtureSource::default()
+            .with_transaction(
+                &[&address],
+                mock_rpc::transaction(&sent, &address, "receiver"),
+            )
+            .with_transaction(
+                &[&address],
+                mock_rpc::transaction(&received, "sender", &address),
+            );
+        let dir = tempfile::tempdir().unwrap();
+        let recording_dir = dir.path().join("recording");
+
+        let recording = aggregator(dir.path())
+            .with_source(Arc::new(fixture))
+            .recording_to(Recording::new(&recording_dir));
+        let recorded = recording.fetch_recent_transactions(&address).await.unwrap();
+        // A second poll lists the signatures again, without fetching them again
+        recording.fetch_recent_transactions(&address).await.unwrap();
+        let listings = recording_dir.join("signatures").join(&address);
+        assert!(listings.join("none-none-none.json").exists());
+        assert!(listings.join("none-none-none-1.json").exists());
+
+        let replaying = aggregator(dir.path()).replaying(Recording::new(&recording_dir));
+        let replayed = replaying.fetch_recent_transactions(&address).await.unwrap();
+        replaying.fetch_recent_transactions(&address).await.unwrap();
+        let unrecorded = replaying.fetch_recent_transactions(&address).await;
+        let missing = replaying
+            .fetch_recent_transactions(&Pubkey::new_unique().to_string())
+            .await;
+
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(replayed, recorded);
+        assert!(unrecorded.is_err());
+        assert!(missing.is_err());
+    }
+
+    /// Test to verify that the mint supplies and accounts read to enrich NFT transfers
+    /// are recorded and replayed, and that replaying an unrecorded mint fails.
+    #[tokio::test]
+    async fn test_record_and_replay_accounts() {
+        let (mint, metadata) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let fixture = FixtureSource::default()
+            .with_mint(&mint.to_string(), 1)
+            .with_account(&metadata.to_string(), vec![4, 2]);
+        let dir = tempfile::tempdir().unwrap();
+        let recording = Recording::new(dir.path());
+
+        let recorder = RecordingSource::new(Arc::new(fixture), recording.clone());
+        let recorded = (
+            recorder.get_token_supply(&mint).await.unwrap(),
+            recorder.get_account(&metadata).await.unwrap(),
+        );
+        let replayer = ReplaySource::new(recording);
+        let replayed = (
+            replayer.get_token_supply(&mint).await.unwrap(),
+            replayer.get_account(&metadata).await.unwrap(),
+        );
+        let missing = replayer.get_token_supply(&Pubkey::new_unique()).await;
+
+        assert_eq!(recorded.0, 1);
+        assert_eq!(recorded.1.as_ref().unwrap().data, vec![4, 2]);
+        assert_eq!(replayed, recorded);
+        assert!(missing.is_err());
+    }
+
+    /// Test to verify that the calls a recording does not hold, such as those of the
+    /// block stream, fail while replaying instead of reaching the network.
+    #[tokio::test]
+    async fn test_replay_fails_unrecorded_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let replaying = aggregator(dir.path()).replaying(Recording::new(dir.path()));
+        let checkpoint_path = dir.path().join("checkpoint.txt");
+        let checkpoint = SlotCheckpoint::new(checkpoint_path.to_string_lossy().into_owned());
+
+        let result = replaying.stream_blocks(&[], &checkpoint).await;
+
+        let err = result.expect_err("the block stream was replayed");
+        assert!(err.to_string().contains("not recorded"), "{}", err);
+    }
+
+    /// Test to verify that the recordings under `testdata/replay` are replayed to the
+    /// transactions of their golden files, `golden/<address>.json` next to each
+    /// recording, for each address whose signatures were listed. Run with
+    /// `UPDATE_GOLDEN=1` to rewrite the golden files after an intended change, then
+    /// review their diff.
+    #[tokio::test]
+    async fn test_replay_matches_golden_files() {
+        let update = std::env::var("UPDATE_GOLDEN").is_ok();
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/replay");
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut checked = 0;
+        for case in std::fs::read_dir(&root).unwrap() {
+            let dir = case.unwrap().path();
+            let replaying = aggregator(db_dir.path()).replaying(Recording::new(&dir));
+            for listings in std::fs::read_dir(dir.join("signatures")).unwrap() {
+                let listings = listings.unwrap().path();
+                let address = listings.file_name().unwrap().to_str().unwrap();
+                let transactions = replaying.fetch_recent_transactions(address).await.unwrap();
+                let actual = serde_json::to_value(&transactions).unwrap();
+
+                let golden = dir.join("golden").join(format!("{}.json", address));
+                if update {
+                    std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+                    let json = serde_json::to_string_pretty(&actual).unwrap();
+                    std::fs::write(&golden, json + "\n").unwrap();
+                }
+                let expected: Value =
+                    serde_json::from_slice(&std::fs::read(&golden).unwrap()).unwrap();
+                assert_eq!(
+                    actual,
+                    expected,
+                    "Transactions replayed from {} differ from {}",
+                    dir.display(),
+                    golden.display()
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "No recording found under {}", root.display());
+    }
+}
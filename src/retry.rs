@@ -0,0 +1,260 @@
+use crate::batch::BatchError;
+use crate::metrics::{Metrics, METRICS};
+use log::warn;
+use rand::Rng;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_custom_error::{
+    JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+    JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET,
+    JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+};
+use solana_client::rpc_request::RpcError;
+use std::fmt::Display;
+use std::future::Future;
+use tokio::time::Duration;
+
+/// How failed RPC calls are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,     // Retries after the first attempt; 0 disables retrying
+    pub base_delay: Duration, // Delay before the first retry, doubled for each retry after it
+    pub max_delay: Duration,  // Upper bound of the backoff delay
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the jittered delay before a retry.
+    ///
+    /// The delay doubles with each attempt up to `max_delay`, and a random delay between
+    /// half of it and all of it is picked so that clients do not retry in lockstep.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - The number of the retry, starting at 0.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        rand::thread_rng().gen_range(delay / 2..=delay)
+    }
+}
+
+/// Whether a failed call is worth retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The call may succeed if retried, e.g. after a rate limit or a server error.
+    Transient {
+        retry_after: Option<Duration>, // Delay requested by the server, if any
+    },
+    /// The call will keep failing, e.g. for an invalid request.
+    Permanent,
+}
+
+/// Classifies an error returned by the Solana RPC client.
+///
+/// Rate limits (429), server errors (5xx), timeouts, connection failures and the node
+/// errors reporting that it is behind or that a block is not available yet are
+/// transient. Anything else is permanent. The `Retry-After` delay of a rate-limited call
+/// is kept when the client sends its calls through the `BatchRpcClient`.
+pub fn classify_client_error(error: &ClientError) -> ErrorClass {
+    let transient = ErrorClass::Transient { retry_after: None };
+    match error.kind() {
+        // The batch client's HTTP status errors are wrapped whole as I/O errors
+        ClientErrorKind::Io(err) => match err.get_ref().and_then(|err| err.downcast_ref()) {
+            Some(err) => classify_batch_error(err),
+            None => transient,
+        },
+        ClientErrorKind::Reqwest(err) => classify_reqwest_error(err),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            classify_rpc_code(*code)
+        }
+        _ => ErrorClass::Permanent,
+    }
+}
+
+/// Classifies an error returned by the JSON-RPC batch client, honoring the
+/// `Retry-After` header of rate-limited batches.
+pub fn classify_batch_error(error: &BatchError) -> ErrorClass {
+    match error {
+        BatchError::Http(err) => classify_reqwest_error(err),
+        BatchError::Status {
+            status,
+            retry_after,
+        } => match *status {
+            429 | 500..=599 => ErrorClass::Transient {
+                retry_after: *retry_after,
+            },
+            _ => ErrorClass::Permanent,
+        },
+        BatchError::Rpc { code, .. } => classify_rpc_code(*code),
+        BatchError::InvalidResponse(_) => ErrorClass::Permanent,
+    }
+}
+
+/// Classifies an HTTP-level error.
+fn classify_reqwest_error(error: &reqwest::Error) -> ErrorClass {
+    let transient = ErrorClass::Transient { retry_after: None };
+    match error.status() {
+        Some(status) if status.as_u16() == 429 || status.is_server_error() => transient,
+        Some(_) => ErrorClass::Permanent,
+        None if error.is_timeout() || error.is_connect() || error.is_request() => transient,
+        None => ErrorClass::Permanent,
+    }
+}
+
+/// Classifies a JSON-RPC error code returned by the node.
+fn classify_rpc_code(code: i64) -> ErrorClass {
+    match code {
+        JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+        | JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+        | JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET
+        | JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED => {
+            ErrorClass::Transient { retry_after: None }
+        }
+        _ => ErrorClass::Permanent,
+    }
+}
+
+/// Runs an RPC call, retrying it while it fails with a transient error.
+///
+/// Each retry waits for the delay requested by the server, capped by the maximum delay
+/// of the policy, or else for the jittered backoff of the policy. Retries and failures
+/// are logged and counted in `METRICS`.
+///
+/// # Arguments
+///
+/// * `policy` - The retry policy.
+/// * `operation` - The name of the call, used in logs, e.g. `"getTransaction"`.
+/// * `classify` - Classifies the errors of the call.
+/// * `call` - Starts a new attempt of the call.
+///
+/// # Returns
+///
+/// The result of the first successful attempt, or the error of the last attempt.
+pub async fn with_retry<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    operation: &str,
+    classify: impl Fn(&E) -> ErrorClass,
+    mut call: F,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        let err = match call().await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+
+        let retry_after = match classify(&err) {
+            ErrorClass::Transient { retry_after } => retry_after,
+            ErrorClass::Permanent => {
+                Metrics::increment(&METRICS.rpc_permanent_errors);
+                return Err(err);
+            }
+        };
+        if attempt >= policy.max_retries {
+            Metrics::increment(&METRICS.rpc_retries_exhausted);
+            warn!(
+                "{} failed after {} retries: {}",
+                operation, policy.max_retries, err
+            );
+            return Err(err);
+        }
+
+        let delay = match retry_after {
+            Some(retry_after) => retry_after.min(policy.max_delay),
+            None => policy.backoff(attempt),
+        };
+        attempt += 1;
+        Metrics::increment(&METRICS.rpc_retries);
+        warn!(
+            "{} failed with a transient error, retry {}/{} in {:?}: {}",
+            operation, attempt, policy.max_retries, delay, err
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Test to verify that transient errors are retried until the call succeeds, while
+    /// permanent errors are returned right away.
+    #[tokio::test]
+    async fn test_with_retry() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        };
+        let classify = |err: &String| match err.as_str() {
+            "busy" => ErrorClass::Transient { retry_after: None },
+            _ => ErrorClass::Permanent,
+        };
+
+        let attempts = AtomicU32::new(0);
+        let result = with_retry(&policy, "getTransaction", classify, || async {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err("busy".to_string()),
+                _ => Ok("done"),
+            }
+        })
+        .await;
+        assert_eq!(result, Ok("done"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), String> =
+            with_retry(&policy, "getTransaction", classify, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("invalid params".to_string())
+            })
+            .await;
+        assert_eq!(result, Err("invalid params".to_string()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), String> =
+            with_retry(&policy, "getTransaction", classify, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("busy".to_string())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    /// Test to verify that the backoff doubles with each retry within its jitter range
+    /// and is capped by the maximum delay.
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+        };
+
+        let first = policy.backoff(0);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let second = policy.backoff(1);
+        assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200));
+        let capped = policy.backoff(4);
+        assert!(capped >= Duration::from_millis(150) && capped <= Duration::from_millis(300));
+    }
+}
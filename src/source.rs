@@ -0,0 +1,280 @@
+use async_trait::async_trait;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::account::Account;
+use solana_sdk::clock::{Slot, UnixTimestamp};
+use solana_sdk::epoch_info::EpochInfo;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+
+/// Where the signatures, transactions, clock and accounts of the chain are read from
+/// when fetching the recent transactions of an address.
+///
+/// Each RPC endpoint is a source backed by its RPC client, and tests substitute
+/// in-memory fixtures so that the parsing, filtering and storing of transactions runs
+/// without a node. The aggregator retries, rate limits and fails over the calls, so a
+/// source only answers them.
+#[async_trait]
+pub trait TransactionSource: Send + Sync {
+    /// Lists the signatures of the recent transactions mentioning an address, newest
+    /// first, as `getSignaturesForAddress` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key of the account or program.
+    /// * `config` - The commitment and bounds of the listing.
+    async fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, ClientError>;
+
+    /// Returns a transaction and its status metadata, as `getTransaction` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `signature` - The signature of the transaction.
+    /// * `config` - The encoding and commitment of the transaction.
+    async fn get_transaction(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, ClientError>;
+
+    /// Returns the current epoch and the position of the current slot within it.
+    async fn get_epoch_info(&self) -> Result<EpochInfo, ClientError>;
+
+    /// Returns the estimated production time of a block.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - The slot of the block.
+    async fn get_block_time(&self, slot: Slot) -> Result<UnixTimestamp, ClientError>;
+
+    /// Returns the total supply of a token mint in raw units, as the `amount` of
+    /// `getTokenSupply`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mint` - The public key of the mint.
+    async fn get_token_supply(&self, mint: &Pubkey) -> Result<u64, ClientError>;
+
+    /// Returns an account, as `getAccountInfo` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key of the account.
+    ///
+    /// # Returns
+    ///
+    /// The account, or `None` if it does not exist.
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, ClientError>;
+}
+
+#[async_trait]
+impl TransactionSource for RpcClient {
+    async fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, ClientError> {
+        self.get_signatures_for_address_with_config(address, config)
+            .await
+    }
+
+    async fn get_transaction(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta, ClientError> {
+        self.get_transaction_with_config(signature, config).await
+    }
+
+    async fn get_epoch_info(&self) -> Result<EpochInfo, ClientError> {
+        RpcClient::get_epoch_info(self).await
+    }
+
+    async fn get_block_time(&self, slot: Slot) -> Result<UnixTimestamp, ClientError> {
+        RpcClient::get_block_time(self, slot).await
+    }
+
+    async fn get_token_supply(&self, mint: &Pubkey) -> Result<u64, ClientError> {
+        let supply = RpcClient::get_token_supply(self, mint).await?;
+        Ok(supply.amount.parse().map_err(|_| {
+            ClientErrorKind::Custom(format!(
+                "invalid supply of mint {}: {}",
+                mint, supply.amount
+            ))
+        })?)
+    }
+
+    async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, ClientError> {
+        Ok(self
+            .get_account_with_commitment(address, self.commitment())
+            .await?
+            .value)
+    }
+}
+
+/// In-memory sources for tests that need no node at all.
+#[cfg(test)]
+pub mod fixture {
+    use super::TransactionSource;
+    use crate::mock_rpc;
+    use async_trait::async_trait;
+    use serde_json::{json, Value};
+    use solana_client::client_error::{ClientError, ClientErrorKind};
+    use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+    use solana_client::rpc_config::RpcTransactionConfig;
+    use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+    use solana_sdk::account::Account;
+    use solana_sdk::clock::{Slot, UnixTimestamp};
+    use solana_sdk::epoch_info::EpochInfo;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
+    use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// An in-memory `TransactionSource` serving a fixed set of transactions, at the clock
+    /// of `epoch_info`.
+    #[derive(Default)]
+    pub struct FixtureSource {
+        signatures: HashMap<String, Vec<Value>>, // Listing entries per address, newest first
+        transactions: HashMap<String, Value>,    // Results of `getTransaction` per signature
+        supplies: HashMap<String, u64>,          // Raw supply per mint
+        accounts: HashMap<String, Account>,      // Accounts per address
+        calls: Mutex<Vec<String>>,               // Methods called, in order
+    }
+
+    impl FixtureSource {
+        /// Adds a transaction, listed for each of the given addresses as the newest one.
+        ///
+        /// # Arguments
+        ///
+        /// * `addresses` - The addresses whose listings include the transaction.
+        /// * `transaction` - The result of `getTransaction` for the transaction.
+        pub fn with_transaction(mut self, addresses: &[&str], transaction: Value) -> Self {
+            let signature = transaction["transaction"]["signatures"][0]
+                .as_str()
+                .expect("the transaction has a signature")
+                .to_string();
+            let entry = json!({
+                "signature": signature,
+                "slot": transaction["slot"],
+                "err": transaction["meta"]["err"],
+                "memo": null,
+                "blockTime": transaction["blockTime"],
+                "confirmationStatus": "finalized"
+            });
+            for address in addresses {
+                self.signatures
+                    .entry(address.to_string())
+                    .or_default()
+                    .insert(0, entry.clone());
+            }
+            self.transactions.insert(signature, transaction);
+            self
+        }
+
+        /// Adds a token mint with the given supply.
+        ///
+        /// # Arguments
+        ///
+        /// * `mint` - The address of the mint.
+        /// * `supply` - The raw supply of the mint, 1 for an NFT.
+        pub fn with_mint(mut self, mint: &str, supply: u64) -> Self {
+            self.supplies.insert(mint.to_string(), supply);
+            self
+        }
+
+        /// Adds an account holding the given data.
+        ///
+        /// # Arguments
+        ///
+        /// * `address` - The address of the account.
+        /// * `data` - The data of the account, e.g. Metaplex metadata.
+        pub fn with_account(mut self, address: &str, data: Vec<u8>) -> Self {
+            let account = Account {
+                data,
+                ..Default::default()
+            };
+            self.accounts.insert(address.to_string(), account);
+            self
+        }
+
+        /// Returns the number of calls of the given method.
+        pub fn calls(&self, method: &str) -> usize {
+            self.calls
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|m| *m == method)
+                .count()
+        }
+
+        /// Records a call of the given method.
+        fn record(&self, method: &str) {
+            self.calls.lock().unwrap().push(method.to_string());
+        }
+    }
+
+    #[async_trait]
+    impl TransactionSource for FixtureSource {
+        async fn get_signatures_for_address(
+            &self,
+            address: &Pubkey,
+            _config: GetConfirmedSignaturesForAddress2Config,
+        ) -> Result<Vec<RpcConfirmedTransactionStatusWithSignature>, ClientError> {
+            self.record("getSignaturesForAddress");
+            let entries = self
+                .signatures
+                .get(&address.to_string())
+                .cloned()
+                .unwrap_or_default();
+            Ok(serde_json::from_value(Value::Array(entries))?)
+        }
+
+        async fn get_transaction(
+            &self,
+            signature: &Signature,
+            _config: RpcTransactionConfig,
+        ) -> Result<EncodedConfirmedTransactionWithStatusMeta, ClientError> {
+            self.record("getTransaction");
+            let transaction = self
+                .transactions
+                .get(&signature.to_string())
+                .ok_or_else(|| {
+                    ClientErrorKind::Custom(format!("transaction {} not found", signature))
+                })?;
+            Ok(serde_json::from_value(transaction.clone())?)
+        }
+
+        async fn get_epoch_info(&self) -> Result<EpochInfo, ClientError> {
+            self.record("getEpochInfo");
+            Ok(serde_json::from_value(mock_rpc::epoch_info())?)
+        }
+
+        async fn get_block_time(&self, _slot: Slot) -> Result<UnixTimestamp, ClientError> {
+            self.record("getBlockTime");
+            Ok(1700000000)
+        }
+
+        async fn get_token_supply(&self, mint: &Pubkey) -> Result<u64, ClientError> {
+            self.record("getTokenSupply");
+            self.supplies
+                .get(&mint.to_string())
+                .copied()
+                .ok_or_else(|| ClientErrorKind::Custom(format!("mint {} not found", mint)).into())
+        }
+
+        async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>, ClientError> {
+            self.record("getAccountInfo");
+            Ok(self.accounts.get(&address.to_string()).cloned())
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::aggregator::Aggregator;
+use crate::db::Commitment;
+use crate::retry::RetryPolicy;
+use futures::stream::{select_all, StreamExt};
+use log::{error, info, warn};
+use solana_client::nonblocking::pubsub_client::{PubsubClient, PubsubClientError};
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use std::str::FromStr;
+
+/// How new transactions of the watched addresses are discovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IngestionMode {
+    /// The signatures of each address are listed every few seconds.
+    #[default]
+    Poll,
+    /// The transactions mentioning each address are pushed over the PubSub WebSocket,
+    /// and the signatures are only listed after each (re)connection to fill the gap.
+    Subscribe,
+    /// Every block is fetched in full and its transactions are filtered for the watched
+    /// addresses, resuming from a slot checkpoint.
+    Blocks,
+    /// The transactions are streamed by a Geyser plugin of our own node over a socket.
+    Geyser,
+}
+
+impl FromStr for IngestionMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "poll" => Ok(Self::Poll),
+            "subscribe" => Ok(Self::Subscribe),
+            "blocks" => Ok(Self::Blocks),
+            "geyser" => Ok(Self::Geyser),
+            _ => Err(format!("unknown ingestion mode: {}", value)),
+        }
+    }
+}
+
+/// Returns the PubSub WebSocket URL served alongside an RPC endpoint, by switching
+/// its scheme from `http` to `ws` or from `https` to `wss`.
+///
+/// # Arguments
+///
+/// * `rpc_url` - The URL of the RPC endpoint.
+///
+/// # Returns
+///
+/// The WebSocket URL, or the URL unchanged if it is not an HTTP URL.
+pub fn websocket_url(rpc_url: &str) -> String {
+    if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    }
+}
+
+/// Ingests the transactions of a set of addresses as they are announced by
+/// `logsSubscribe`, reconnecting and resubscribing whenever the connection drops.
+pub struct LogsSubscriber {
+    url: String,            // URL of the PubSub WebSocket
+    addresses: Vec<String>, // Addresses whose transactions are subscribed to
+    commitment: Commitment, // Commitment the transactions are announced at
+    reconnect: RetryPolicy, // Backoff between reconnection attempts
+}
+
+impl LogsSubscriber {
+    /// Creates a subscriber for the transactions mentioning each address.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the PubSub WebSocket.
+    /// * `addresses` - The public keys of the accounts or programs to subscribe to.
+    /// * `commitment` - The commitment to announce transactions at. Transactions are
+    ///   announced at `confirmed` at the least, since the node cannot serve them earlier.
+    /// * `reconnect` - The backoff between reconnection attempts.
+    pub fn new(
+        url: &str,
+        addresses: Vec<String>,
+        commitment: Commitment,
+        reconnect: RetryPolicy,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            addresses,
+            commitment: commitment.max(Commitment::Confirmed),
+            reconnect,
+        }
+    }
+
+    /// Ingests the announced transactions until the task running it is aborted,
+    /// reconnecting with a jittered exponential backoff while connecting fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregator` - The aggregator fetching and storing the announced transactions.
+    pub async fn run(&self, aggregator: &Aggregator) {
+        let mut failures = 0;
+        loop {
+            match self.session(aggregator).await {
+                Ok(()) => {
+                    warn!("PubSub connection to {} closed, reconnecting", self.url);
+                    failures = 0;
+                }
+                Err(err) => warn!("PubSub connection to {} failed: {}", self.url, err),
+            }
+            tokio::time::sleep(self.reconnect.backoff(failures)).await;
+            failures += 1;
+        }
+    }
+
+    /// Connects and subscribes to the transactions mentioning each address, fills the
+    /// gap since the previous connection by listing the recent transactions of each
+    /// address, then ingests the announced transactions until the connection closes.
+    ///
+    /// Transactions announced while the gap is filled are queued, so none is missed
+    /// between listing and subscribing.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregator` - The aggregator fetching and storing the transactions.
+    ///
+    /// # Returns
+    ///
+    /// `Ok` once an established connection closes, or the error of connecting or
+    /// subscribing.
+    async fn session(&self, aggregator: &Aggregator) -> Result<(), PubsubClientError> {
+        let client = PubsubClient::new(&self.url).await?;
+        let mut subscriptions = Vec::new();
+        for address in &self.addresses {
+            let (notifications, _unsubscribe) = client
+                .logs_subscribe(
+                    RpcTransactionLogsFilter::Mentions(vec![address.clone()]),
+                    RpcTransactionLogsConfig {
+                        commitment: Some(self.commitment.into()),
+                    },
+                )
+                .await?;
+            subscriptions.push(notifications.map(move |notification| (address, notification)));
+        }
+        info!(
+            "Subscribed to the transactions of {} addresses on {}",
+            self.addresses.len(),
+            self.url
+        );
+
+        for address in &self.addresses {
+            if let Err(err) = aggregator.fetch_recent_transactions(address).await {
+                error!("Error filling the gap for address {}: {:?}", address, err);
+            }
+        }
+
+        let mut notifications = select_all(subscriptions);
+        while let Some((address, notification)) = notifications.next().await {
+            let signature = &notification.value.signature;
+            match aggregator
+                .ingest_transaction(address, signature, self.commitment)
+                .await
+            {
+                Ok(Some(_)) => info!("Ingested transaction {} for address {}", signature, address),
+                Ok(None) => {}
+                Err(err) => error!(
+                    "Error ingesting transaction {} for address {}: {:?}",
+                    signature, address, err
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::AggregatorConfig;
+    use crate::db::InMemoryDatabase;
+    use crate::mock_rpc::{self, MockPubsub, MockRpc};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
+    use std::sync::Arc;
+    use tokio::time::Duration;
+
+    /// Test to verify that the WebSocket URL is derived from the scheme of the RPC URL.
+    #[test]
+    fn test_websocket_url() {
+        assert_eq!(
+            websocket_url("https://api.mainnet-beta.solana.com"),
+            "wss://api.mainnet-beta.solana.com"
+        );
+        assert_eq!(
+            websocket_url("http://127.0.0.1:8899"),
+            "ws://127.0.0.1:8899"
+        );
+    }
+
+    /// Test to verify that announced transactions are ingested, that the subscriber
+    /// resubscribes after the connection drops, and that the transactions sent while
+    /// disconnected are listed on each connection.
+    #[tokio::test]
+    async fn test_subscriber_resubscribes_and_fills_gaps() {
+        let address = Pubkey::new_unique().to_string();
+        let [missed, first, second] = [(); 3].map(|_| Signature::new_unique().to_string());
+
+        let node = MockRpc::start(mock_rpc::transfer_handler(vec![missed.clone()], &address)).await;
+        // The first connection drops after announcing a transaction
+        let pubsub = MockPubsub::start(vec![vec![first.clone()], vec![second.clone()]]).await;
+
+        let (db, _dir) = InMemoryDatabase::temporary();
+        let db = Arc::new(db);
+        let aggregator = Aggregator::new(
+            std::slice::from_ref(&node.url),
+            db.clone(),
+            AggregatorConfig::default(),
+        );
+        let subscriber = LogsSubscriber::new(
+            &pubsub.url,
+            vec![address.clone()],
+            Commitment::Finalized,
+            RetryPolicy {
+                base_delay: Duration::from_millis(10),
+                ..Default::default()
+            },
+        );
+        let task = tokio::spawn(async move { subscriber.run(&aggregator).await });
+
+        let stored = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let stored = db.get_transactions(&address).await;
+                if stored.len() == 3 {
+                    return stored;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("Transactions were not ingested");
+        task.abort();
+
+        let mut signatures: Vec<String> = stored.into_iter().map(|tx| tx.signature).collect();
+        let mut expected = vec![missed, first, second];
+        signatures.sort();
+        expected.sort();
+        assert_eq!(signatures, expected);
+        assert_eq!(pubsub.connections(), 2);
+        assert_eq!(node.calls("getSignaturesForAddress"), 2);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::amount::SOL_DECIMALS;
+use crate::events::TransactionEvent;
+use crate::instructions::InstructionRecord;
+
+/// Mint of wrapped SOL, which swaps unwrap into native SOL of the signer.
+const WRAPPED_SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+/// Asset name used for native SOL in swap events, matching the serialized `Asset::Sol`.
+const SOL_ASSET: &str = "SOL";
+
+/// Known DEX aggregator programs. Aggregators route through AMMs, so they take
+/// precedence when naming the venue of a swap.
+const AGGREGATORS: &[(&str, &str)] = &[
+    ("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4", "jupiter"),
+    ("JUP4Fb2cqiRUcaTHdrPC8h2gNsA2ETXiPDD33WcGuJB", "jupiter"),
+];
+
+/// Known AMM and order book programs.
+const AMMS: &[(&str, &str)] = &[
+    ("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8", "raydium"),
+    (
+        "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK",
+        "raydium_clmm",
+    ),
+    (
+        "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C",
+        "raydium_cpmm",
+    ),
+    (
+        "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc",
+        "orca_whirlpool",
+    ),
+    ("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP", "orca"),
+    (
+        "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo",
+        "meteora_dlmm",
+    ),
+    ("Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB", "meteora"),
+    ("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY", "phoenix"),
+];
+
+/// Detects a swap made by the signer of a transaction.
+///
+/// A transaction is classified as a swap when it invokes a known AMM or aggregator
+/// program and the signer ends up with less of one asset and more of another. The
+/// input is the single token the signer was debited and the output the single token
+/// it was credited; raw amounts of different mints are not comparable, so several
+/// tokens on the same side make the swap ambiguous and it is not classified. When only
+/// one side is a token, the other side is native SOL, since wrapped SOL accounts are
+/// usually closed by the swap itself.
+///
+/// # Arguments
+///
+/// * `signer` - The public key of the signer (fee payer) of the transaction.
+/// * `sol_delta` - The change of the signer's SOL balance in lamports, excluding the fee.
+/// * `token_deltas` - The per-mint token balance changes of the signer as
+///   `(mint, raw delta, decimals)`.
+/// * `instructions` - The top-level and inner instructions of the transaction.
+///
+/// # Returns
+///
+/// A `TransactionEvent::Swap`, or `None` if the transaction is not a swap.
+pub fn detect_swap(
+    signer: &str,
+    sol_delta: i128,
+    token_deltas: &[(String, i128, u8)],
+    instructions: &[InstructionRecord],
+) -> Option<TransactionEvent> {
+    let (program_id, venue) = find_venue(instructions)?;
+
+    // Wrapped SOL is reported as native SOL so both sides of a SOL swap look alike
+    let tokens = token_deltas.iter().map(|(mint, delta, decimals)| {
+        let mint = if mint == WRAPPED_SOL_MINT {
+            SOL_ASSET
+        } else {
+            mint.as_str()
+        };
+        (mint, *delta, *decimals)
+    });
+    let (debited, credited): (Vec<_>, Vec<_>) = tokens
+        .filter(|(_, delta, _)| *delta != 0)
+        .partition(|(_, delta, _)| *delta < 0);
+    if debited.len() > 1 || credited.len() > 1 {
+        return None;
+    }
+    let (input, output) = (debited.first().copied(), credited.first().copied());
+
+    let sol = (SOL_ASSET, sol_delta, SOL_DECIMALS);
+    let (input, output) = match (input, output) {
+        (Some(input), Some(output)) => (input, output),
+        (Some(input), None) if sol_delta > 0 => (input, sol),
+        (None, Some(output)) if sol_delta < 0 => (sol, output),
+        _ => return None,
+    };
+    if input.0 == output.0 {
+        return None;
+    }
+
+    Some(TransactionEvent::Swap {
+        signer: signer.to_string(),
+        venue: venue.to_string(),
+        program_id: program_id.to_string(),
+        input_mint: input.0.to_string(),
+        input_amount: input.1.unsigned_abs() as u64,
+        input_decimals: input.2,
+        output_mint: output.0.to_string(),
+        output_amount: output.1.unsigned_abs() as u64,
+        output_decimals: output.2,
+    })
+}
+
+/// Finds the venue of a swap among the programs invoked by a transaction, preferring
+/// aggregators over the AMMs they route through.
+fn find_venue(instructions: &[InstructionRecord]) -> Option<(&'static str, &'static str)> {
+    [AGGREGATORS, AMMS].into_iter().find_map(|programs| {
+        programs.iter().copied().find(|(program_id, _)| {
+            instructions
+                .iter()
+                .any(|instruction| instruction.program_id == *program_id)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an instruction record invoking the given program.
+    fn invoke(program_id: &str, stack_height: u32) -> InstructionRecord {
+        InstructionRecord {
+            index: 0,
+            stack_height,
+            program_id: program_id.to_string(),
+            accounts: vec![],
+            data: vec![],
+        }
+    }
+
+    /// Test to verify that a SOL to USDC swap routed by Jupiter through Raydium is
+    /// attributed to Jupiter, that legs are picked by sign and ambiguous legs are not
+    /// classified, and that token movements without a known venue are not classified as
+    /// swaps.
+    #[test]
+    fn test_detect_swap() {
+        let usdc = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string();
+        let instructions = vec![
+            invoke("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4", 1),
+            invoke("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8", 2),
+        ];
+        // The ATA rent is included in the SOL spent, and the wrapped SOL account was closed
+        let token_deltas = vec![(usdc.clone(), 151_250_000, 6)];
+
+        let swap = detect_swap("signer", -1_002_039_280, &token_deltas, &instructions);
+        assert_eq!(
+            swap,
+            Some(TransactionEvent::Swap {
+                signer: "signer".to_string(),
+                venue: "jupiter".to_string(),
+                program_id: "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4".to_string(),
+                input_mint: "SOL".to_string(),
+                input_amount: 1_002_039_280,
+                input_decimals: 9,
+                output_mint: usdc.clone(),
+                output_amount: 151_250_000,
+                output_decimals: 6,
+            })
+        );
+
+        // Legs are picked by sign, whatever the decimals of their raw amounts
+        let bonk = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263".to_string();
+        let token_deltas = vec![(bonk.clone(), -5_000_000, 5), (usdc.clone(), 20_000_000, 6)];
+        let swap = detect_swap("signer", 0, &token_deltas, &instructions).unwrap();
+        assert!(matches!(
+            swap,
+            TransactionEvent::Swap { input_mint, output_mint, .. }
+                if input_mint == bonk && output_mint == usdc
+        ));
+
+        // Two debited mints leave the input ambiguous
+        let token_deltas = vec![
+            (bonk.clone(), -5_000_000, 5),
+            (WRAPPED_SOL_MINT.to_string(), -1_000_000_000, 9),
+            (usdc.clone(), 20_000_000, 6),
+        ];
+        assert_eq!(detect_swap("signer", 0, &token_deltas, &instructions), None);
+
+        let token_deltas = vec![(usdc.clone(), 151_250_000, 6)];
+        let transfer = vec![invoke("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA", 1)];
+        assert_eq!(
+            detect_swap("signer", -1_000_000, &token_deltas, &transfer),
+            None
+        );
+    }
+}
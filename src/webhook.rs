@@ -0,0 +1,602 @@
+use crate::aggregator::Aggregator;
+use crate::amount::{Amount, Asset, SOL_DECIMALS};
+use crate::auth::token_matches;
+use crate::classify::TransactionKind;
+use crate::db::{Commitment, TransactionData, TransactionStatus};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Header carrying the hex HMAC-SHA256 of the request body, optionally prefixed with
+/// `sha256=`.
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// Name recorded as the endpoint of the transactions received by the webhook.
+const WEBHOOK_SOURCE: &str = "webhook";
+
+/// Errors rejecting a webhook delivery.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WebhookError {
+    /// Indicates a missing or wrong `Authorization` header or body signature.
+    #[error("Missing or invalid webhook credentials")]
+    Unauthorized,
+
+    /// Indicates a body that is not JSON, or an entry that is neither a raw nor an
+    /// enhanced transaction.
+    #[error("Invalid webhook payload: {0}")]
+    InvalidPayload(String),
+}
+
+/// How webhook deliveries are authenticated. Every configured check must pass.
+#[derive(Debug, Clone, Default)]
+pub struct WebhookAuth {
+    pub auth_token: Option<String>, // Expected value of the `Authorization` header
+    pub hmac_secret: Option<String>, // Secret of the HMAC-SHA256 signature of the body
+}
+
+impl WebhookAuth {
+    /// Reads the credentials from the `WEBHOOK_AUTH_TOKEN` and `WEBHOOK_HMAC_SECRET`
+    /// environment variables.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Self {
+            auth_token: var("WEBHOOK_AUTH_TOKEN"),
+            hmac_secret: var("WEBHOOK_HMAC_SECRET"),
+        }
+    }
+
+    /// Returns whether any credential is configured. Without one, the webhook is
+    /// disabled rather than left open.
+    pub fn is_configured(&self) -> bool {
+        self.auth_token.is_some() || self.hmac_secret.is_some()
+    }
+
+    /// Authenticates a delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `authorization` - The `Authorization` header of the request, if any.
+    /// * `signature` - The signature header of the request, if any.
+    /// * `body` - The raw body of the request.
+    ///
+    /// # Returns
+    ///
+    /// `Ok` if every configured check passes, `WebhookError::Unauthorized` otherwise.
+    pub fn verify(
+        &self,
+        authorization: Option<&str>,
+        signature: Option<&str>,
+        body: &[u8],
+    ) -> Result<(), WebhookError> {
+        if !self.is_configured() {
+            return Err(WebhookError::Unauthorized);
+        }
+        if let Some(token) = &self.auth_token {
+            if !token_matches(token, authorization) {
+                return Err(WebhookError::Unauthorized);
+            }
+        }
+        if let Some(secret) = &self.hmac_secret {
+            let signature = signature.ok_or(WebhookError::Unauthorized)?;
+            let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+            let signature = decode_hex(signature).ok_or(WebhookError::Unauthorized)?;
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(body);
+            mac.verify_slice(&signature)
+                .map_err(|_| WebhookError::Unauthorized)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a webhook delivery, returned to the provider.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct WebhookReport {
+    pub received: usize,   // Transactions in the payload
+    pub stored: usize,     // Transactions stored, counted once per watched address
+    pub duplicates: usize, // Transactions already stored, e.g. by polling, per watched address
+}
+
+/// A payload entry, in either of the formats pushed by webhooks.
+enum WebhookEntry {
+    /// A transaction in the `getTransaction` JSON format, pushed by raw webhooks.
+    Raw(Box<EncodedConfirmedTransactionWithStatusMeta>),
+    /// A transaction already parsed by the provider, pushed by enhanced webhooks.
+    Enhanced(EnhancedTransaction),
+}
+
+impl WebhookEntry {
+    /// Parses a payload entry. Entries carrying a `transaction` field are raw
+    /// transactions, the others enhanced transactions.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The JSON value of the entry.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the entry, or the error of the format it failed to parse as.
+    fn parse(entry: Value) -> Result<Self, serde_json::Error> {
+        if entry.get("transaction").is_some() {
+            serde_json::from_value(entry).map(|transaction| Self::Raw(Box::new(transaction)))
+        } else {
+            serde_json::from_value(entry).map(Self::Enhanced)
+        }
+    }
+}
+
+/// A transaction in the enhanced format of Helius webhooks. Only the fields the
+/// aggregator stores are read.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EnhancedTransaction {
+    signature: String, // Signature of the transaction
+    slot: u64,         // Slot the transaction was processed in
+    timestamp: u64,    // Block time of the transaction
+    #[serde(default, rename = "type")]
+    transaction_type: String, // Type assigned by the provider, e.g. `TRANSFER` or `SWAP`
+    #[serde(default)]
+    fee: u64, // Fee paid by the fee payer, in lamports
+    #[serde(default)]
+    fee_payer: String, // Public key of the fee payer
+    #[serde(default)]
+    transaction_error: Option<Value>, // Error reported by the runtime, if the transaction failed
+    native_transfers: Vec<NativeTransfer>, // SOL moved by the transaction
+    token_transfers: Vec<TokenTransfer>, // Tokens moved by the transaction
+    #[serde(default)]
+    account_data: Vec<AccountData>, // Balance changes of the accounts of the transaction
+}
+
+/// A movement of SOL in an enhanced transaction.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NativeTransfer {
+    #[serde(default)]
+    from_user_account: String, // Public key the lamports left
+    #[serde(default)]
+    to_user_account: String, // Public key the lamports reached
+    amount: u64, // Lamports moved
+}
+
+/// A movement of tokens in an enhanced transaction.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenTransfer {
+    #[serde(default)]
+    from_user_account: String, // Owner of the token account the tokens left
+    #[serde(default)]
+    to_user_account: String, // Owner of the token account the tokens reached
+}
+
+/// The balance changes of an account in an enhanced transaction.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountData {
+    account: String, // Public key of the account
+    #[serde(default)]
+    token_balance_changes: Vec<TokenBalanceChange>, // Changes of the token accounts it owns
+}
+
+/// The change of a token account balance in an enhanced transaction.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenBalanceChange {
+    user_account: String,             // Owner of the token account
+    mint: String,                     // Mint of the token
+    raw_token_amount: RawTokenAmount, // Signed change in the token's smallest unit
+}
+
+/// A raw token amount, as reported by enhanced transactions.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTokenAmount {
+    token_amount: String, // Signed amount in the token's smallest unit
+    decimals: u8,         // Number of decimals of the token
+}
+
+impl EnhancedTransaction {
+    /// Returns whether the transaction mentions an address, as fee payer, in a
+    /// transfer or among its accounts.
+    fn mentions(&self, address: &str) -> bool {
+        self.fee_payer == address
+            || self.native_transfers.iter().any(|transfer| {
+                transfer.from_user_account == address || transfer.to_user_account == address
+            })
+            || self.token_transfers.iter().any(|transfer| {
+                transfer.from_user_account == address || transfer.to_user_account == address
+            })
+            || self.account_data.iter().any(|data| {
+                data.account == address
+                    || data
+                        .token_balance_changes
+                        .iter()
+                        .any(|change| change.user_account == address)
+            })
+    }
+
+    /// Converts the transaction into the data stored for an address.
+    ///
+    /// The amount is the first non-zero token balance change of the address, or else
+    /// its SOL balance change from the native transfers, which leave out the fee like
+    /// the amounts of raw transactions. The sender and receiver are those of the first
+    /// transfer involving the address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The public key the transaction is stored under.
+    ///
+    /// # Returns
+    ///
+    /// The `TransactionData` of the address, stored as `confirmed`.
+    fn transaction_data(&self, address: &str) -> TransactionData {
+        let transfers = self
+            .native_transfers
+            .iter()
+            .map(|transfer| (&transfer.from_user_account, &transfer.to_user_account))
+            .chain(
+                self.token_transfers
+                    .iter()
+                    .map(|transfer| (&transfer.from_user_account, &transfer.to_user_account)),
+            );
+        let (sender, receiver) = transfers
+            .clone()
+            .find(|(from, to)| *from == address || *to == address)
+            .or_else(|| transfers.clone().next())
+            .map_or_else(
+                || (self.fee_payer.clone(), "unknown".to_string()),
+                |(from, to)| (from.clone(), to.clone()),
+            );
+
+        let (status, error) = match &self.transaction_error {
+            Some(err) => (TransactionStatus::Failed, Some(err.to_string())),
+            None => (TransactionStatus::Success, None),
+        };
+
+        let mut kinds = Vec::new();
+        if status == TransactionStatus::Failed {
+            kinds.push(TransactionKind::Failed);
+        }
+        if self.transaction_type == "SWAP" {
+            kinds.push(TransactionKind::Swap);
+        } else if !self.token_transfers.is_empty() {
+            kinds.push(TransactionKind::TokenTransfer);
+        } else if !self.native_transfers.is_empty() {
+            kinds.push(TransactionKind::SolTransfer);
+        }
+        if kinds.is_empty() {
+            kinds.push(TransactionKind::Unknown);
+        }
+
+        TransactionData {
+            signature: self.signature.clone(),
+            sender,
+            receiver,
+            amount: self.balance_change(address),
+            timestamp: self.timestamp,
+            status,
+            error,
+            fee: self.fee,
+            slot: self.slot,
+            kinds,
+            endpoint: Some(WEBHOOK_SOURCE.to_string()),
+            commitment: Commitment::Confirmed,
+            ..Default::default()
+        }
+    }
+
+    /// Computes the balance change of an address, leaving the amount empty if it
+    /// cannot be represented.
+    fn balance_change(&self, address: &str) -> Amount {
+        let mut token_deltas: Vec<(&str, i128, u8)> = Vec::new();
+        let changes = self
+            .account_data
+            .iter()
+            .flat_map(|data| &data.token_balance_changes)
+            .filter(|change| change.user_account == address);
+        for change in changes {
+            let Ok(delta) = change.raw_token_amount.token_amount.parse::<i128>() else {
+                warn!(
+                    "Ignoring the invalid token amount {} of {} in {}",
+                    change.raw_token_amount.token_amount, address, self.signature
+                );
+                continue;
+            };
+            match token_deltas
+                .iter_mut()
+                .find(|(mint, _, _)| *mint == change.mint)
+            {
+                Some((_, total, _)) => *total += delta,
+                None => token_deltas.push((&change.mint, delta, change.raw_token_amount.decimals)),
+            }
+        }
+
+        let (asset, delta, decimals) =
+            match token_deltas.into_iter().find(|(_, delta, _)| *delta != 0) {
+                Some((mint, delta, decimals)) => (Asset::Token(mint.to_string()), delta, decimals),
+                None => {
+                    let delta = self.native_transfers.iter().fold(0, |delta, transfer| {
+                        let amount = i128::from(transfer.amount);
+                        let received = if transfer.to_user_account == address {
+                            amount
+                        } else {
+                            0
+                        };
+                        let sent = if transfer.from_user_account == address {
+                            amount
+                        } else {
+                            0
+                        };
+                        delta + received - sent
+                    });
+                    (Asset::Sol, delta, SOL_DECIMALS)
+                }
+            };
+        Amount::from_delta(asset.clone(), delta, decimals).unwrap_or_else(|| {
+            warn!(
+                "Skipping the balance change of {} in {:?}: {} overflows the amount",
+                address, asset, delta
+            );
+            Amount::default()
+        })
+    }
+}
+
+/// Receives the transactions pushed by an RPC provider and stores those mentioning
+/// the watched addresses.
+pub struct WebhookReceiver {
+    auth: WebhookAuth,           // Credentials every delivery must carry
+    aggregator: Arc<Aggregator>, // Aggregator parsing and storing the transactions
+    addresses: Vec<String>,      // Addresses whose transactions are stored
+}
+
+impl WebhookReceiver {
+    /// Creates a receiver storing the pushed transactions of the given addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - The credentials every delivery must carry.
+    /// * `aggregator` - The aggregator parsing and storing the transactions.
+    /// * `addresses` - The public keys of the accounts and programs to index.
+    pub fn new(auth: WebhookAuth, aggregator: Arc<Aggregator>, addresses: Vec<String>) -> Self {
+        Self {
+            auth,
+            aggregator,
+            addresses,
+        }
+    }
+
+    /// Authenticates a delivery and stores the transactions it carries.
+    ///
+    /// The payload is a transaction, or an array of them, either raw in the
+    /// `getTransaction` JSON format as pushed by raw webhooks, or in the enhanced
+    /// format of Helius enhanced webhooks. A delivery with an entry in neither format
+    /// is rejected as a whole, so that the provider reports it instead of the
+    /// transactions being silently lost.
+    /// Transactions are stored as `confirmed`, the commitment providers push at.
+    ///
+    /// # Arguments
+    ///
+    /// * `authorization` - The `Authorization` header of the request, if any.
+    /// * `signature` - The signature header of the request, if any.
+    /// * `body` - The raw body of the request.
+    ///
+    /// # Returns
+    ///
+    /// A result containing the counts of the delivery, or a `WebhookError` if it is
+    /// rejected.
+    pub async fn receive(
+        &self,
+        authorization: Option<&str>,
+        signature: Option<&str>,
+        body: &[u8],
+    ) -> Result<WebhookReport, WebhookError> {
+        self.auth.verify(authorization, signature, body)?;
+        let payload: Value = serde_json::from_slice(body)
+            .map_err(|err| WebhookError::InvalidPayload(err.to_string()))?;
+        let entries = match payload {
+            Value::Array(entries) => entries,
+            entry => vec![entry],
+        };
+
+        let entries = entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                WebhookEntry::parse(entry).map_err(|err| {
+                    WebhookError::InvalidPayload(format!("entry {}: {}", index, err))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut report = WebhookReport {
+            received: entries.len(),
+            ..Default::default()
+        };
+        for entry in entries {
+            let (stored, duplicates) = match entry {
+                WebhookEntry::Raw(transaction) => {
+                    self.aggregator
+                        .ingest_pushed_transaction(
+                            &self.addresses,
+                            &transaction,
+                            WEBHOOK_SOURCE,
+                            Commitment::Confirmed,
+                        )
+                        .await
+                }
+                WebhookEntry::Enhanced(transaction) => self.store_enhanced(&transaction).await,
+            };
+            report.stored += stored;
+            report.duplicates += duplicates;
+        }
+
+        info!(
+            "Webhook delivered {} transactions ({} stored, {} duplicates)",
+            report.received, report.stored, report.duplicates
+        );
+        Ok(report)
+    }
+
+    /// Stores an enhanced transaction under each watched address it mentions, unless
+    /// it is already stored under that address.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The enhanced transaction.
+    ///
+    /// # Returns
+    ///
+    /// The number of addresses the transaction was stored under, and the number it
+    /// was already stored under.
+    async fn store_enhanced(&self, transaction: &EnhancedTransaction) -> (usize, usize) {
+        let (mut stored, mut duplicates) = (0, 0);
+        for address in self.addresses.iter().filter(|a| transaction.mentions(a)) {
+            if self
+                .aggregator
+                .store_pushed_transaction(address, transaction.transaction_data(address))
+                .await
+            {
+                stored += 1;
+            } else {
+                duplicates += 1;
+            }
+        }
+        (stored, duplicates)
+    }
+}
+
+/// Decodes a hex string, returning `None` if it is not valid hex.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Direction;
+
+    /// Returns the hex HMAC-SHA256 of a body.
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Test to verify that deliveries are only accepted with every configured
+    /// credential, and never without any configured.
+    #[test]
+    fn test_verify_webhook_credentials() {
+        let body = br#"[{"slot": 1000}]"#;
+        let auth = WebhookAuth {
+            auth_token: Some("token".to_string()),
+            hmac_secret: Some("secret".to_string()),
+        };
+        let signature = sign("secret", body);
+
+        assert_eq!(auth.verify(Some("token"), Some(&signature), body), Ok(()));
+        let prefixed = format!("sha256={}", signature);
+        assert_eq!(auth.verify(Some("token"), Some(&prefixed), body), Ok(()));
+        assert_eq!(
+            auth.verify(Some("wrong"), Some(&signature), body),
+            Err(WebhookError::Unauthorized)
+        );
+        assert_eq!(
+            auth.verify(Some("token"), Some(&sign("other", body)), body),
+            Err(WebhookError::Unauthorized)
+        );
+        assert_eq!(
+            auth.verify(Some("token"), Some(&signature), b"[]"),
+            Err(WebhookError::Unauthorized)
+        );
+        assert_eq!(
+            auth.verify(Some("token"), None, body),
+            Err(WebhookError::Unauthorized)
+        );
+        assert_eq!(
+            WebhookAuth::default().verify(None, None, body),
+            Err(WebhookError::Unauthorized)
+        );
+    }
+
+    /// Test to verify that enhanced transactions are parsed into the balance change,
+    /// counterparties and kinds of each address they mention, and that entries in
+    /// neither format are rejected.
+    #[test]
+    fn test_parse_enhanced_transactions() {
+        let entry = serde_json::json!({
+            "signature": "enhanced_sig",
+            "slot": 1500,
+            "timestamp": 1700000000,
+            "type": "TRANSFER",
+            "fee": 5000,
+            "feePayer": "payer",
+            "transactionError": null,
+            "nativeTransfers": [
+                { "fromUserAccount": "payer", "toUserAccount": "watched", "amount": 700000 },
+                { "fromUserAccount": "watched", "toUserAccount": "other", "amount": 200000 }
+            ],
+            "tokenTransfers": [
+                { "fromUserAccount": "payer", "toUserAccount": "holder", "tokenAmount": 1.5, "mint": "mint" }
+            ],
+            "accountData": [
+                {
+                    "account": "holder",
+                    "nativeBalanceChange": 0,
+                    "tokenBalanceChanges": [{
+                        "userAccount": "holder",
+                        "mint": "mint",
+                        "rawTokenAmount": { "tokenAmount": "1500000", "decimals": 6 }
+                    }]
+                }
+            ]
+        });
+        let Ok(WebhookEntry::Enhanced(transaction)) = WebhookEntry::parse(entry) else {
+            panic!("expected an enhanced transaction");
+        };
+        assert!(transaction.mentions("watched"));
+        assert!(transaction.mentions("holder"));
+        assert!(!transaction.mentions("unrelated"));
+
+        let watched = transaction.transaction_data("watched");
+        assert_eq!(watched.signature, "enhanced_sig");
+        assert_eq!(watched.amount, Amount::sol(500000, Direction::Credit));
+        assert_eq!(
+            (watched.sender.as_str(), watched.receiver.as_str()),
+            ("payer", "watched")
+        );
+        assert_eq!(
+            (watched.slot, watched.timestamp, watched.fee),
+            (1500, 1700000000, 5000)
+        );
+        assert_eq!(watched.status, TransactionStatus::Success);
+        assert_eq!(watched.kinds, vec![TransactionKind::TokenTransfer]);
+        assert_eq!(watched.commitment, Commitment::Confirmed);
+
+        let holder = transaction.transaction_data("holder");
+        assert_eq!(holder.amount.asset, Asset::Token("mint".to_string()));
+        assert_eq!((holder.amount.raw, holder.amount.decimals), (1500000, 6));
+        assert_eq!(
+            (holder.sender.as_str(), holder.receiver.as_str()),
+            ("payer", "holder")
+        );
+
+        let truncated = serde_json::json!({ "type": "TRANSFER", "signature": "enhanced_sig" });
+        assert!(WebhookEntry::parse(truncated).is_err());
+    }
+}